@@ -22,6 +22,9 @@ pub enum ClassDefinition<'a> {
 #[serde(deny_unknown_fields)]
 pub struct Sierra<'a> {
     /// Contract ABI.
+    ///
+    /// Parsed leniently, see `lenient_abi`.
+    #[serde(default, deserialize_with = "lenient_abi")]
     pub abi: Cow<'a, str>,
 
     /// Main program definition.
@@ -34,6 +37,28 @@ pub struct Sierra<'a> {
     pub entry_points_by_type: SierraEntryPoints,
 }
 
+/// The ABI of a Sierra class is meant to be a JSON string, but some classes in
+/// the wild inline it as a JSON value or leave it out (or `null`) entirely.
+///
+/// Such ABIs are accepted here: an inlined value is kept as its raw JSON text
+/// and a missing ABI becomes the empty string. Since the ABI string is part of
+/// the class hash, anything that was not read back exactly as declared still
+/// fails class hash verification.
+fn lenient_abi<'de, 'a, D>(deserializer: D) -> Result<Cow<'a, str>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let raw = Option::<Box<RawValue>>::deserialize(deserializer)?;
+    let abi = match raw {
+        None => String::new(),
+        Some(raw) if raw.get().starts_with('"') => {
+            serde_json::from_str(raw.get()).map_err(serde::de::Error::custom)?
+        }
+        Some(raw) => raw.get().to_owned(),
+    };
+    Ok(Cow::Owned(abi))
+}
+
 impl<T> Dummy<T> for Sierra<'_> {
     fn dummy_with_rng<R: Rng + ?Sized>(_: &T, rng: &mut R) -> Self {
         Self {
@@ -190,3 +215,38 @@ pub struct SelectorAndFunctionIndex {
     pub selector: EntryPoint,
     pub function_idx: u64,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lenient_abi() {
+        let parse = |abi: &str| {
+            let json = format!(
+                r#"{{
+                    {abi}
+                    "sierra_program": [],
+                    "contract_class_version": "0.1.0",
+                    "entry_points_by_type": {{"CONSTRUCTOR": [], "EXTERNAL": [], "L1_HANDLER": []}}
+                }}"#
+            );
+            serde_json::from_str::<Sierra<'_>>(&json)
+                .unwrap()
+                .abi
+                .into_owned()
+        };
+
+        assert_eq!(
+            parse(r#""abi": "[{\"type\": \"function\"}]","#),
+            r#"[{"type": "function"}]"#
+        );
+        // Inlined ABIs are kept verbatim, including whitespace.
+        assert_eq!(
+            parse(r#""abi": [{"type":  "function"}],"#),
+            r#"[{"type":  "function"}]"#
+        );
+        assert_eq!(parse(r#""abi": null,"#), "");
+        assert_eq!(parse(""), "");
+    }
+}
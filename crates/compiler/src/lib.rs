@@ -31,16 +31,117 @@ pub fn compile_to_casm(sierra_definition: &[u8]) -> anyhow::Result<Vec<u8>> {
     result.unwrap_or_else(|e| Err(panic_error(e)))
 }
 
-fn panic_error(e: Box<dyn std::any::Any>) -> anyhow::Error {
-    match e.downcast_ref::<&str>() {
-        Some(e) => anyhow::anyhow!("Compiler panicked: {}", e),
-        None => match e.downcast_ref::<String>() {
-            Some(e) => anyhow::anyhow!("Compiler panicked: {}", e),
-            None => anyhow::anyhow!("Compiler panicked"),
-        },
+/// Sierra versions handled by one of the older, version specific compilers in
+/// [compile_to_casm]. All other versions are handled by the latest compiler,
+/// up to and including [latest_sierra_version].
+pub const LEGACY_SIERRA_VERSIONS: [&str; 3] = ["0.1.0", "1.0.0", "1.1.0"];
+
+/// The most recent Sierra version the latest compiler can compile.
+pub fn latest_sierra_version() -> String {
+    let version = cairo_lang_starknet_classes::compiler_version::current_sierra_version_id();
+    format!("{}.{}.{}", version.major, version.minor, version.patch)
+}
+
+/// Version of the latest compiler.
+pub fn compiler_version() -> String {
+    let version = cairo_lang_starknet_classes::compiler_version::current_compiler_version_id();
+    format!("{}.{}.{}", version.major, version.minor, version.patch)
+}
+
+/// Checks that a CASM class was produced by a compiler this node knows how to
+/// compute the compiled class hash for, ie. one that is not newer than
+/// [compiler_version].
+///
+/// The compiled class hash algorithm differs between CASM versions, so hashing
+/// a class produced by a newer compiler could silently result in a wrong hash.
+pub fn check_casm_compiler_version(casm_definition: &[u8]) -> anyhow::Result<()> {
+    #[derive(serde::Deserialize)]
+    struct Casm<'a> {
+        #[serde(borrow)]
+        compiler_version: Cow<'a, str>,
+    }
+
+    let casm = serde_json::from_slice::<Casm<'_>>(casm_definition)
+        .context("Parsing CASM compiler version")?;
+
+    let version = parse_compiler_version(&casm.compiler_version)?;
+    let supported = parse_compiler_version(&compiler_version())?;
+    anyhow::ensure!(
+        version <= supported,
+        "Unsupported CASM compiler version {}, the latest supported version is {}",
+        casm.compiler_version,
+        compiler_version()
+    );
+
+    Ok(())
+}
+
+/// Parses the `major.minor.patch` part of a compiler version, ignoring any
+/// pre-release suffix such as `-rc0`.
+fn parse_compiler_version(version: &str) -> anyhow::Result<(u64, u64, u64)> {
+    let release = version
+        .split_once('-')
+        .map_or(version, |(release, _)| release);
+    let mut parts = release.split('.').map(str::parse::<u64>);
+
+    match (parts.next(), parts.next(), parts.next(), parts.next()) {
+        (Some(Ok(major)), Some(Ok(minor)), Some(Ok(patch)), None) => Ok((major, minor, patch)),
+        _ => Err(anyhow::anyhow!("Invalid compiler version {version}")),
+    }
+}
+
+/// Whether a [compile_to_casm] error was caused by the compiler running out of
+/// resources, such as memory or threads, rather than by the class itself.
+/// Unlike other compilation errors these might not recur when retrying.
+pub fn is_resource_error(error: &anyhow::Error) -> bool {
+    use std::io::ErrorKind;
+
+    error.chain().any(|cause| {
+        if let Some(e) = cause.downcast_ref::<std::io::Error>() {
+            return matches!(e.kind(), ErrorKind::OutOfMemory | ErrorKind::WouldBlock);
+        }
+        if cause
+            .downcast_ref::<std::collections::TryReserveError>()
+            .is_some()
+        {
+            return true;
+        }
+        if let Some(CompilerPanic(Some(message))) = cause.downcast_ref::<CompilerPanic>() {
+            const RESOURCE_PANICS: [&str; 3] = [
+                "failed to spawn thread",
+                "Resource temporarily unavailable",
+                "memory allocation",
+            ];
+            return RESOURCE_PANICS
+                .iter()
+                .any(|pattern| message.contains(pattern));
+        }
+        false
+    })
+}
+
+#[derive(Debug)]
+struct CompilerPanic(Option<String>);
+
+impl std::fmt::Display for CompilerPanic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.0 {
+            Some(message) => write!(f, "Compiler panicked: {message}"),
+            None => write!(f, "Compiler panicked"),
+        }
     }
 }
 
+impl std::error::Error for CompilerPanic {}
+
+fn panic_error(e: Box<dyn std::any::Any>) -> anyhow::Error {
+    let message = match e.downcast_ref::<&str>() {
+        Some(e) => Some(e.to_string()),
+        None => e.downcast_ref::<String>().cloned(),
+    };
+    CompilerPanic(message).into()
+}
+
 #[derive(Debug, PartialEq)]
 struct SierraVersion(u64, u64, u64);
 
@@ -72,6 +173,7 @@ fn parse_sierra_version(program: &serde_json::value::RawValue) -> anyhow::Result
 ///
 /// Uses the _latest_ compiler for the parsing and calculation.
 pub fn casm_class_hash(casm_definition: &[u8]) -> anyhow::Result<CasmHash> {
+    check_casm_compiler_version(casm_definition)?;
     v2::casm_class_hash(casm_definition)
 }
 
@@ -293,6 +395,39 @@ struct FeederGatewayContractClass<'a> {
 mod tests {
     use super::{compile_to_casm, FeederGatewayContractClass};
 
+    mod casm_compiler_version {
+        use starknet_gateway_test_fixtures::class_definitions::CAIRO_1_1_0_BALANCE_CASM_JSON;
+
+        use super::super::{casm_class_hash, check_casm_compiler_version, compiler_version};
+
+        fn casm_with_version(version: &str) -> Vec<u8> {
+            let mut casm: serde_json::Value =
+                serde_json::from_slice(CAIRO_1_1_0_BALANCE_CASM_JSON).unwrap();
+            casm["compiler_version"] = version.into();
+            serde_json::to_vec(&casm).unwrap()
+        }
+
+        #[test]
+        fn supported_versions() {
+            check_casm_compiler_version(CAIRO_1_1_0_BALANCE_CASM_JSON).unwrap();
+            check_casm_compiler_version(&casm_with_version(&compiler_version())).unwrap();
+            check_casm_compiler_version(&casm_with_version("1.0.0-rc0")).unwrap();
+        }
+
+        #[test]
+        fn unsupported_version() {
+            let casm = casm_with_version("999.0.0");
+            check_casm_compiler_version(&casm).unwrap_err();
+            casm_class_hash(&casm).unwrap_err();
+        }
+
+        #[test]
+        fn malformed_version() {
+            check_casm_compiler_version(&casm_with_version("latest")).unwrap_err();
+            check_casm_compiler_version(br#"{"bytecode": []}"#).unwrap_err();
+        }
+    }
+
     mod parse_version {
         use rstest::rstest;
         use starknet_gateway_test_fixtures::class_definitions::{
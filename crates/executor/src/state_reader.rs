@@ -106,6 +106,27 @@ impl<S: StorageAdapter> PathfinderStateReader<S> {
                 )
             };
 
+        let casm_definition = match casm_definition {
+            Some(casm_definition) => Some(casm_definition),
+            // Only Sierra classes have a casm hash. Their CASM definition may have
+            // been pruned, in which case it is recompiled.
+            None if self
+                .storage_adapter
+                .casm_hash(pathfinder_class_hash)?
+                .is_some() =>
+            {
+                let casm_definition = pathfinder_compiler::compile_to_casm(&class_definition)
+                    .map_err(|error| {
+                        StateError::StateReadError(format!(
+                            "Recompiling pruned CASM definition of {pathfinder_class_hash}: \
+                             {error}"
+                        ))
+                    })?;
+                Some(casm_definition)
+            }
+            None => None,
+        };
+
         match casm_definition {
             Some(casm_definition) => {
                 // There's a CASM definition in storage, so this is a Sierra class. Extract
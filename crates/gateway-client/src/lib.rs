@@ -50,6 +50,14 @@ pub trait GatewayApi: Sync {
         unimplemented!();
     }
 
+    async fn casm_by_hash(
+        &self,
+        class_hash: ClassHash,
+        block: BlockId,
+    ) -> Result<bytes::Bytes, SequencerError> {
+        unimplemented!();
+    }
+
     async fn transaction_status(
         &self,
         transaction_hash: TransactionHash,
@@ -151,6 +159,14 @@ impl<T: GatewayApi + Sync + Send> GatewayApi for std::sync::Arc<T> {
         self.as_ref().pending_casm_by_hash(class_hash).await
     }
 
+    async fn casm_by_hash(
+        &self,
+        class_hash: ClassHash,
+        block: BlockId,
+    ) -> Result<bytes::Bytes, SequencerError> {
+        self.as_ref().casm_by_hash(class_hash, block).await
+    }
+
     async fn transaction_status(
         &self,
         transaction_hash: TransactionHash,
@@ -417,6 +433,22 @@ impl GatewayApi for Client {
             .await
     }
 
+    /// Gets CASM for a particular class hash as of the given block.
+    #[tracing::instrument(skip(self))]
+    async fn casm_by_hash(
+        &self,
+        class_hash: ClassHash,
+        block: BlockId,
+    ) -> Result<bytes::Bytes, SequencerError> {
+        self.feeder_gateway_request()
+            .get_compiled_class_by_class_hash()
+            .class_hash(class_hash)
+            .block(block)
+            .retry(self.retry)
+            .get_as_bytes()
+            .await
+    }
+
     /// Gets transaction status by transaction hash.
     #[tracing::instrument(skip(self))]
     async fn transaction_status(
@@ -107,7 +107,7 @@ pub mod add_transaction {
 
 pub mod class_definitions {
     use pathfinder_common::macro_prelude::*;
-    use pathfinder_common::ClassHash;
+    use pathfinder_common::{ClassHash, SierraHash};
 
     pub const CONTRACT_DEFINITION: &[u8] = bytes_fixture!("contracts/contract_definition.json");
     pub const CONTRACT_DEFINITION_CLASS_HASH: ClassHash =
@@ -138,6 +138,8 @@ pub mod class_definitions {
     // injected in order to achieve the correct hash. https://external.integration.starknet.io/feeder_gateway/get_full_contract?blockNumber=latest&contractAddress=0x06f17fb7a052f3d18c1911c9d9c2fb0032bbe1ea57c58b0baca85bda9f3698be
     pub const CAIRO_0_10_TUPLES_INTEGRATION: &[u8] =
         bytes_fixture!("contracts/cairo-0.10-tuples-integration.json");
+    pub const CAIRO_0_10_TUPLES_INTEGRATION_CLASS_HASH: ClassHash =
+        class_hash!("0x542460935cea188d21e752d8459d82d60497866aaad21f873cbb61621d34f7f");
     // https://alpha4.starknet.io/feeder_gateway/get_full_contract?blockNumber=latest&contractAddress=0x0424e799d610433168a31aab44c0d3e38b45d97387b45de80089f56c184fa315
     pub const CAIRO_0_10_TUPLES_GOERLI: &[u8] =
         bytes_fixture!("contracts/cairo-0.10-tuples-goerli.json");
@@ -172,6 +174,8 @@ pub mod class_definitions {
     // https://alpha4.starknet.io/feeder_gateway/get_class_by_hash?classHash=0x03dd9347d22f1ea2d5fbc7bd1f0860c6c334973499f9f1989fcb81bfff5191da
     pub const CAIRO_2_0_0_STACK_OVERFLOW: &[u8] =
         bytes_fixture!("contracts/sierra-2.0.0-stack-overflow.json");
+    pub const CAIRO_2_0_0_STACK_OVERFLOW_CLASS_HASH: SierraHash =
+        sierra_hash!("0x03dd9347d22f1ea2d5fbc7bd1f0860c6c334973499f9f1989fcb81bfff5191da");
 
     // A Cairo class from Testnet
     pub const CAIRO_TESTNET_0331118F4E4EB8A8DDB0F4493E09612E380EF527991C49A15C42574AB48DD747:
@@ -60,6 +60,7 @@ use crate::{core, sync};
 pub struct Client {
     inner: sync::Client,
     peers: Arc<RwLock<Decaying<HashSet<PeerId>>>>,
+    peer_order: PeerOrder,
 }
 
 impl Client {
@@ -67,16 +68,23 @@ impl Client {
         Self {
             inner,
             peers: Default::default(),
+            peer_order: Default::default(),
         }
     }
 
+    /// Makes the order in which peers are tried depend only on `seed` and the
+    /// set of known peers, so that a specific interleaving of peers can be
+    /// reproduced.
+    pub fn with_peer_order_seed(mut self, seed: u64) -> Self {
+        self.peer_order = PeerOrder::seeded(seed);
+        self
+    }
+
     fn core_client(&self) -> core::Client<sync::Command> {
         core::Client::new(self.inner.sender.clone(), self.inner.local_peer_id)
     }
 
     async fn get_random_peers(&self) -> Vec<PeerId> {
-        use rand::seq::SliceRandom;
-
         let r = self.peers.read().await;
         let mut peers = if let Some(peers) = r.get() {
             peers.iter().copied().collect::<Vec<_>>()
@@ -87,7 +95,9 @@ impl Client {
             // Check again because the previous lock in the queue might have been a write
             // lock that has already updated the peers.
             if let Some(peers) = w.get() {
-                return peers.iter().copied().collect::<Vec<_>>();
+                let mut peers = peers.iter().copied().collect::<Vec<_>>();
+                self.peer_order.shuffle(&mut peers);
+                return peers;
             }
 
             // TODO known peers abstraction should not poll
@@ -122,12 +132,40 @@ impl Client {
             w.update(peers);
             peers_vec
         };
-        peers.shuffle(&mut rand::thread_rng());
+        self.peer_order.shuffle(&mut peers);
 
         peers
     }
 }
 
+/// The order in which peers are tried, random unless seeded.
+#[derive(Clone, Debug, Default)]
+struct PeerOrder(Option<Arc<std::sync::Mutex<rand::rngs::StdRng>>>);
+
+impl PeerOrder {
+    fn seeded(seed: u64) -> Self {
+        use rand::SeedableRng;
+
+        Self(Some(Arc::new(std::sync::Mutex::new(
+            rand::rngs::StdRng::seed_from_u64(seed),
+        ))))
+    }
+
+    fn shuffle(&self, peers: &mut [PeerId]) {
+        use rand::seq::SliceRandom;
+
+        match &self.0 {
+            Some(rng) => {
+                // The known peers are kept in a set, start from a well defined
+                // order so that only the seed determines the outcome.
+                peers.sort();
+                peers.shuffle(&mut *rng.lock().unwrap());
+            }
+            None => peers.shuffle(&mut rand::thread_rng()),
+        }
+    }
+}
+
 impl HeaderStream for Client {
     fn header_stream(
         self,
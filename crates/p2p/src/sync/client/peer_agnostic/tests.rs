@@ -938,3 +938,54 @@ async fn make_event_stream(
 
     pretty_assertions_sorted::assert_eq!(actual, expected_stream);
 }
+
+#[tokio::test]
+async fn seeded_peer_order_reproduces_interleaving() {
+    // Every peer provides a single block before its stream ends, and the bad
+    // peer fails all requests, so the peer order decides which peer serves
+    // which block.
+    async fn interleaving(seed: u64) -> (Vec<PeerId>, Vec<PeerId>) {
+        let bad_peer = peer(0).0;
+        let peers = (0..4).map(|i| peer(i).0).collect::<HashSet<_>>();
+        let peer_order = PeerOrder::seeded(seed);
+        let get_peers = move || {
+            let mut peers = peers.iter().copied().collect::<Vec<_>>();
+            peer_order.shuffle(&mut peers);
+            async move { peers }
+        };
+        let requested = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let send_request = {
+            let requested = requested.clone();
+            move |peer: PeerId, _: ClassesRequest| {
+                requested.lock().unwrap().push(peer);
+                async move {
+                    anyhow::ensure!(peer != bad_peer, "bad peer");
+                    let (mut tx, rx) = fmpsc::channel(1);
+                    tx.try_send(Ok(class_resp(0))).unwrap();
+                    Ok(rx)
+                }
+            }
+        };
+
+        let num_blocks = 10;
+        let served = super::class_definition_stream::make(
+            BlockNumber::GENESIS,
+            BlockNumber::GENESIS + (num_blocks - 1),
+            stream::iter((0..num_blocks).map(|_| Ok(1))),
+            get_peers,
+            send_request,
+        )
+        .map_ok(|x| x.peer)
+        .try_collect::<Vec<_>>()
+        .await
+        .unwrap();
+
+        let requested = requested.lock().unwrap().clone();
+        (requested, served)
+    }
+
+    let (requested, served) = interleaving(7).await;
+    assert_eq!(served.len(), 10);
+    assert!(!served.contains(&peer(0).0));
+    assert_eq!(interleaving(7).await, (requested, served));
+}
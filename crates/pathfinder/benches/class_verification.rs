@@ -0,0 +1,52 @@
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion};
+use p2p::sync::client::types::ClassDefinition;
+use pathfinder_common::BlockNumber;
+use pathfinder_lib::sync::bench_verify_classes;
+use starknet_gateway_test_fixtures::class_definitions::{
+    CAIRO_0_10_TUPLES_INTEGRATION as CAIRO,
+    CAIRO_0_10_TUPLES_INTEGRATION_CLASS_HASH as CAIRO_HASH,
+    CAIRO_2_0_0_STACK_OVERFLOW as SIERRA,
+    CAIRO_2_0_0_STACK_OVERFLOW_CLASS_HASH as SIERRA_HASH,
+};
+
+/// A batch of the size that tracking sync requests from a peer at once.
+fn batch() -> Vec<ClassDefinition> {
+    (0..10)
+        .map(|i| match i % 2 {
+            0 => ClassDefinition::Cairo {
+                block_number: BlockNumber::GENESIS,
+                definition: CAIRO.to_vec(),
+                hash: CAIRO_HASH,
+            },
+            _ => ClassDefinition::Sierra {
+                block_number: BlockNumber::GENESIS,
+                sierra_definition: SIERRA.to_vec(),
+                hash: SIERRA_HASH,
+            },
+        })
+        .collect()
+}
+
+fn bench_class_verification(c: &mut Criterion) {
+    let mut group = c.benchmark_group("class_verification");
+
+    group.bench_function("separate_stages", |b| {
+        b.iter_batched(
+            batch,
+            |classes| black_box(bench_verify_classes(classes, false)),
+            BatchSize::SmallInput,
+        )
+    });
+    group.bench_function("fused_stage", |b| {
+        b.iter_batched(
+            batch,
+            |classes| black_box(bench_verify_classes(classes, true)),
+            BatchSize::SmallInput,
+        )
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_class_verification);
+criterion_main!(benches);
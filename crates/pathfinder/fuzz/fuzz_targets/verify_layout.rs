@@ -0,0 +1,17 @@
+//! Feeds arbitrary bytes received from a peer through class layout
+//! verification, which must reject anything it cannot parse as a bad layout
+//! instead of panicking.
+//!
+//! Run with `cargo +nightly fuzz run verify_layout` from `crates/pathfinder`.
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    // The first byte decides whether the rest is a Cairo 0 or a Sierra class.
+    let Some((kind, definition)) = data.split_first() else {
+        return;
+    };
+
+    pathfinder_lib::sync::fuzz_verify_layout(definition.to_vec(), kind % 2 == 1);
+});
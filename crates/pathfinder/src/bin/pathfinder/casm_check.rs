@@ -0,0 +1,256 @@
+//! Checks the stored CASM of Sierra classes against their stored casm hash.
+//!
+//! Class hash verification during sync does not cover the CASM, which is either
+//! compiled locally or fetched from the feeder gateway. Recompiling the Sierra
+//! class and hashing both the result and the stored CASM catches corrupted
+//! CASM as well as changes in the output of the embedded compiler.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use pathfinder_common::{CasmHash, ClassHash};
+use pathfinder_storage::Storage;
+
+/// Number of classes checked between saving the report.
+const BATCH_SIZE: usize = 100;
+
+/// Progress and findings of a check. Saved after every batch of classes so that
+/// an interrupted check resumes where it left off.
+#[derive(Debug, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct Report {
+    /// The last class checked. Classes are checked in class hash order.
+    pub last_checked: Option<ClassHash>,
+    pub checked: u64,
+    pub mismatches: Vec<Mismatch>,
+    /// Set once all classes have been checked. The next check starts over.
+    pub complete: bool,
+}
+
+/// A class whose stored CASM or recompiled CASM does not hash to the stored
+/// casm hash.
+#[derive(Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct Mismatch {
+    pub class_hash: ClassHash,
+    pub casm_hash: CasmHash,
+    /// Hash of the stored CASM, `None` if it could not be hashed.
+    pub stored_casm_hash: Option<CasmHash>,
+    /// Hash of the CASM compiled from the stored Sierra class, `None` if the
+    /// embedded compiler cannot compile the class.
+    pub recompiled_casm_hash: Option<CasmHash>,
+}
+
+/// Recompiles the CASM of all stored Sierra classes, compares its hash and the
+/// hash of the stored CASM to the stored casm hash, and records mismatches in
+/// the report at `report_path`. Resumes from an incomplete report.
+pub async fn check_casm_hashes(storage: Storage, report_path: PathBuf) {
+    let span = tracing::Span::current();
+    let result = util::task::spawn_blocking(move |_| {
+        let _g = span.enter();
+        check(&storage, &report_path)
+    })
+    .await
+    .context("Joining blocking task")
+    .and_then(|result| result);
+
+    match result {
+        Ok(report) => {
+            tracing::info!(
+                checked=%report.checked,
+                mismatches=%report.mismatches.len(),
+                "Checked stored CASM against casm hashes"
+            );
+        }
+        Err(error) => {
+            tracing::warn!(%error, "Failed to check stored CASM against casm hashes");
+        }
+    }
+}
+
+fn check(storage: &Storage, report_path: &Path) -> anyhow::Result<Report> {
+    let mut report = match load_report(report_path)? {
+        Some(report) if !report.complete => {
+            tracing::info!(checked=%report.checked, "Resuming CASM hash check");
+            report
+        }
+        _ => Report::default(),
+    };
+
+    let mut db = storage
+        .connection()
+        .context("Creating database connection")?;
+
+    loop {
+        let batch = db
+            .transaction()
+            .context("Creating database transaction")?
+            .casm_hashes_after(report.last_checked, BATCH_SIZE)
+            .context("Querying casm hashes")?;
+        if batch.is_empty() {
+            break;
+        }
+
+        for (class_hash, casm_hash) in batch {
+            // Don't hold a transaction open while compiling.
+            let tx = db.transaction().context("Creating database transaction")?;
+            let sierra_definition = tx.class_definition(class_hash)?;
+            let casm_definition = tx.casm_definition(class_hash)?;
+            drop(tx);
+
+            report.last_checked = Some(class_hash);
+            report.checked += 1;
+
+            let (Some(sierra_definition), Some(casm_definition)) =
+                (sierra_definition, casm_definition)
+            else {
+                continue;
+            };
+
+            if let Some(mismatch) =
+                check_class(class_hash, casm_hash, &sierra_definition, &casm_definition)
+            {
+                tracing::warn!(
+                    %class_hash, %casm_hash, stored=?mismatch.stored_casm_hash,
+                    recompiled=?mismatch.recompiled_casm_hash,
+                    "CASM does not match the stored casm hash"
+                );
+                metrics::increment_counter!("casm_hash_check_total", "result" => "mismatch");
+                report.mismatches.push(mismatch);
+            } else {
+                metrics::increment_counter!("casm_hash_check_total", "result" => "matches");
+            }
+        }
+
+        save_report(report_path, &report)?;
+    }
+
+    report.complete = true;
+    save_report(report_path, &report)?;
+
+    Ok(report)
+}
+
+fn check_class(
+    class_hash: ClassHash,
+    casm_hash: CasmHash,
+    sierra_definition: &[u8],
+    casm_definition: &[u8],
+) -> Option<Mismatch> {
+    let stored_casm_hash = pathfinder_compiler::casm_class_hash(casm_definition).ok();
+    let recompiled_casm_hash = pathfinder_compiler::compile_to_casm(sierra_definition)
+        .and_then(|casm| pathfinder_compiler::casm_class_hash(&casm))
+        .ok();
+
+    // A class the embedded compiler cannot compile is only checked against its
+    // stored CASM.
+    let matches = stored_casm_hash == Some(casm_hash)
+        && recompiled_casm_hash.is_none_or(|hash| hash == casm_hash);
+
+    (!matches).then_some(Mismatch {
+        class_hash,
+        casm_hash,
+        stored_casm_hash,
+        recompiled_casm_hash,
+    })
+}
+
+fn load_report(path: &Path) -> anyhow::Result<Option<Report>> {
+    match std::fs::read(path) {
+        Ok(report) => serde_json::from_slice(&report)
+            .map(Some)
+            .context("Parsing CASM hash check report"),
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(error) => Err(error).context("Reading CASM hash check report"),
+    }
+}
+
+fn save_report(path: &Path, report: &Report) -> anyhow::Result<()> {
+    // Replace the report atomically so that it is never left half written.
+    let tmp_path = path.with_extension("tmp");
+    let report = serde_json::to_vec_pretty(report).context("Serializing report")?;
+    std::fs::write(&tmp_path, report).context("Writing CASM hash check report")?;
+    std::fs::rename(&tmp_path, path).context("Replacing CASM hash check report")
+}
+
+#[cfg(test)]
+mod tests {
+    use pathfinder_common::macro_prelude::*;
+    use pathfinder_storage::StorageBuilder;
+    use starknet_gateway_test_fixtures::class_definitions::CAIRO_1_1_0_BALANCE_SIERRA_JSON;
+
+    use super::*;
+
+    /// Stores two intact classes with a class with tampered CASM in between.
+    fn setup() -> (Storage, CasmHash, CasmHash) {
+        let storage = StorageBuilder::in_memory().unwrap();
+
+        let casm = pathfinder_compiler::compile_to_casm(CAIRO_1_1_0_BALANCE_SIERRA_JSON).unwrap();
+        let casm_hash = pathfinder_compiler::casm_class_hash(&casm).unwrap();
+        let mut tampered = serde_json::from_slice::<serde_json::Value>(&casm).unwrap();
+        tampered["bytecode"][0] = serde_json::json!("0x1234");
+        let tampered = serde_json::to_vec(&tampered).unwrap();
+        let tampered_hash = pathfinder_compiler::casm_class_hash(&tampered).unwrap();
+
+        let mut db = storage.connection().unwrap();
+        let tx = db.transaction().unwrap();
+        for (hash, casm) in [
+            (sierra_hash!("0x1"), &casm),
+            (sierra_hash!("0x2"), &tampered),
+            (sierra_hash!("0x3"), &casm),
+        ] {
+            tx.insert_sierra_class(&hash, CAIRO_1_1_0_BALANCE_SIERRA_JSON, &casm_hash, casm)
+                .unwrap();
+        }
+        tx.commit().unwrap();
+
+        (storage, casm_hash, tampered_hash)
+    }
+
+    #[test]
+    fn tampered_casm_is_reported() {
+        let (storage, casm_hash, tampered_hash) = setup();
+        let dir = tempfile::tempdir().unwrap();
+        let report_path = dir.path().join("report.json");
+
+        let report = check(&storage, &report_path).unwrap();
+
+        let expected = Report {
+            last_checked: Some(class_hash!("0x3")),
+            checked: 3,
+            mismatches: vec![Mismatch {
+                class_hash: class_hash!("0x2"),
+                casm_hash,
+                stored_casm_hash: Some(tampered_hash),
+                recompiled_casm_hash: Some(casm_hash),
+            }],
+            complete: true,
+        };
+        assert_eq!(report, expected);
+        assert_eq!(load_report(&report_path).unwrap(), Some(expected));
+    }
+
+    #[test]
+    fn incomplete_check_is_resumed() {
+        let (storage, ..) = setup();
+        let dir = tempfile::tempdir().unwrap();
+        let report_path = dir.path().join("report.json");
+
+        // Interrupted after checking the tampered class.
+        let interrupted = Report {
+            last_checked: Some(class_hash!("0x2")),
+            checked: 2,
+            mismatches: vec![],
+            complete: false,
+        };
+        save_report(&report_path, &interrupted).unwrap();
+
+        let report = check(&storage, &report_path).unwrap();
+        assert_eq!(report.checked, 3);
+        assert_eq!(report.mismatches, vec![]);
+        assert!(report.complete);
+
+        // A complete check starts over.
+        let report = check(&storage, &report_path).unwrap();
+        assert_eq!(report.checked, 3);
+        assert_eq!(report.mismatches.len(), 1);
+    }
+}
@@ -316,6 +316,43 @@ This should only be enabled for debugging purposes as it adds substantial proces
     )]
     get_events_event_filter_block_range_limit: std::num::NonZeroUsize,
 
+    #[arg(
+        long = "rpc.get-events-page-size-limit",
+        long_help = "The maximum `chunk_size` accepted by `starknet_getEvents`. Larger requests are rejected with a `PAGE_SIZE_TOO_BIG` error.",
+        env = "PATHFINDER_RPC_GET_EVENTS_PAGE_SIZE_LIMIT",
+        default_value = "1024"
+    )]
+    get_events_page_size_limit: std::num::NonZeroUsize,
+
+    #[arg(
+        long = "rpc.get-events-key-filter-size-limit",
+        long_help = "The maximum total number of keys, summed over all key positions, accepted in a `starknet_getEvents` filter. \
+            Larger filters are rejected with a `TOO_MANY_KEYS_IN_FILTER` error.",
+        env = "PATHFINDER_RPC_GET_EVENTS_KEY_FILTER_SIZE_LIMIT",
+        default_value = "1024"
+    )]
+    get_events_key_filter_size_limit: std::num::NonZeroUsize,
+
+    #[arg(
+        long = "rpc.class-cache-size",
+        long_help = "The number of serialized classes kept in memory to serve `starknet_getClass` \
+            and `starknet_getClassAt`. Classes are cached separately for each RPC version.",
+        env = "PATHFINDER_RPC_CLASS_CACHE_SIZE",
+        default_value = "128"
+    )]
+    rpc_class_cache_size: std::num::NonZeroUsize,
+
+    #[arg(
+        long = "rpc.pending-receipt-block-fields-as-null",
+        long_help = "Serialize the `block_hash` and `block_number` of receipts of pending and \
+            pre-confirmed transactions as `null` instead of omitting them, for clients that \
+            expect the fields to always be present.",
+        env = "PATHFINDER_RPC_PENDING_RECEIPT_BLOCK_FIELDS_AS_NULL",
+        default_value = "false",
+        action=ArgAction::Set
+    )]
+    rpc_pending_receipt_block_fields_as_null: bool,
+
     #[arg(
         long = "storage.blockchain-history",
         long_help = "When set to `archive` all historical blockchain data is preserved. When set to an integer N, only the last N+1 blocks of the blockchain are kept in the database. \
@@ -338,6 +375,53 @@ This should only be enabled for debugging purposes as it adds substantial proces
     )]
     state_tries: Option<StateTries>,
 
+    #[arg(
+        long = "storage.class-compression-dictionary",
+        long_help = "Train a zstd dictionary over a sample of the stored class definitions on \
+                     startup, if none exists yet, and use it to compress class definitions \
+                     stored from then on. Class definitions stored earlier are unaffected.",
+        env = "PATHFINDER_STORAGE_CLASS_COMPRESSION_DICTIONARY",
+        default_value = "false",
+        action=ArgAction::Set
+    )]
+    class_compression_dictionary: bool,
+
+    #[arg(
+        long = "storage.prune-orphan-classes",
+        long_help = "Delete class definitions which are not declared by any state update on \
+                     startup. Classes stored after the latest declared class, such as those of \
+                     the pending block, are kept. This scans all stored classes.",
+        env = "PATHFINDER_STORAGE_PRUNE_ORPHAN_CLASSES",
+        default_value = "false",
+        action=ArgAction::Set
+    )]
+    prune_orphan_classes: bool,
+
+    #[arg(
+        long = "storage.prune-casm-definitions",
+        long_help = "Delete the CASM definitions of Sierra classes on startup and do not store \
+                     locally compiled ones when syncing classes. CASM is recompiled from the \
+                     Sierra definition whenever it is needed instead, which saves disk space at \
+                     the cost of slower execution of these classes. CASM fetched from the \
+                     feeder gateway, or which does not recompile to the same hash, is kept.",
+        env = "PATHFINDER_STORAGE_PRUNE_CASM_DEFINITIONS",
+        default_value = "false",
+        action=ArgAction::Set
+    )]
+    prune_casm_definitions: bool,
+
+    #[arg(
+        long = "storage.check-casm-hashes",
+        long_help = "On startup, recompile all stored Sierra classes in the background and check \
+                     that both the stored and the recompiled CASM hash to the stored casm hash. \
+                     Progress and mismatches are saved to casm-hash-check.json in the data \
+                     directory, an interrupted check is resumed on the next startup.",
+        env = "PATHFINDER_STORAGE_CHECK_CASM_HASHES",
+        default_value = "false",
+        action=ArgAction::Set
+    )]
+    check_casm_hashes: bool,
+
     #[arg(
         long = "rpc.custom-versioned-constants-json-path",
         long_help = "Path to a JSON file referencing sequencer versioned constants. The file maps \
@@ -360,6 +444,153 @@ This should only be enabled for debugging purposes as it adds substantial proces
     )]
     fetch_casm_from_fgw: bool,
 
+    #[arg(
+        long = "sync.trust-gateway-class-hashes",
+        long_help = "Do not compute the hashes of classes downloaded from the feeder gateway, \
+                     instead trust the hashes they were requested by. This speeds up sync at the \
+                     cost of security and should only be used with a trusted feeder gateway. \
+                     Classes received from peers are always verified",
+        env = "PATHFINDER_SYNC_TRUST_GATEWAY_CLASS_HASHES",
+        default_value = "false",
+        action=ArgAction::Set
+    )]
+    trust_gateway_class_hashes: bool,
+
+    #[arg(
+        long = "sync.record-casm-provenance",
+        long_help = "Record whether the CASM of each synced Sierra class was compiled locally or \
+                     fetched from the feeder gateway, along with when it was stored",
+        env = "PATHFINDER_SYNC_RECORD_CASM_PROVENANCE",
+        default_value = "false",
+        action=ArgAction::Set
+    )]
+    record_casm_provenance: bool,
+
+    #[arg(
+        long = "sync.check-gateway-casm",
+        long_help = "On startup, recompile the Sierra classes whose CASM was recorded as fetched \
+                     from the feeder gateway and log any difference from the stored CASM. Only \
+                     covers classes stored with --sync.record-casm-provenance enabled",
+        env = "PATHFINDER_SYNC_CHECK_GATEWAY_CASM",
+        default_value = "false",
+        action=ArgAction::Set
+    )]
+    check_gateway_casm: bool,
+
+    #[arg(
+        long = "sync.compile-retries",
+        long_help = "How often to retry compiling a Sierra class locally after the compiler ran \
+                     out of resources, before fetching its CASM from the feeder gateway instead",
+        env = "PATHFINDER_SYNC_COMPILE_RETRIES",
+        default_value = "2"
+    )]
+    compile_retries: usize,
+
+    #[arg(
+        long = "sync.compile-retry-delay",
+        value_name = "Milliseconds",
+        long_help = "Delay before retrying a Sierra class compilation, extended by a random \
+                     jitter of up to the same duration",
+        env = "PATHFINDER_SYNC_COMPILE_RETRY_DELAY",
+        default_value = "100"
+    )]
+    compile_retry_delay: u64,
+
+    #[arg(
+        long = "sync.fgw-circuit-breaker-threshold",
+        long_help = "Number of consecutive failed CASM requests to the feeder gateway after \
+                     which further requests are skipped for the cooldown period",
+        env = "PATHFINDER_SYNC_FGW_CIRCUIT_BREAKER_THRESHOLD",
+        default_value = "5"
+    )]
+    fgw_circuit_breaker_threshold: u32,
+
+    #[arg(
+        long = "sync.fgw-circuit-breaker-cooldown",
+        value_name = "Seconds",
+        long_help = "How long CASM requests to the feeder gateway are skipped once the circuit \
+                     breaker has opened",
+        env = "PATHFINDER_SYNC_FGW_CIRCUIT_BREAKER_COOLDOWN",
+        default_value = "30"
+    )]
+    fgw_circuit_breaker_cooldown: u64,
+
+    #[arg(
+        long = "sync.casm-compiler-command",
+        value_name = "Path",
+        long_help = "Executable used to compile Sierra classes larger than \
+                     --sync.casm-compiler-command-threshold in a separate process, so that \
+                     compiling them cannot exhaust the memory of the node. It is given the Sierra \
+                     definition on stdin and must write the CASM definition to stdout. All \
+                     classes are compiled in-process if not set.",
+        env = "PATHFINDER_SYNC_CASM_COMPILER_COMMAND"
+    )]
+    casm_compiler_command: Option<PathBuf>,
+
+    #[arg(
+        long = "sync.casm-compiler-command-threshold",
+        value_name = "KiB",
+        long_help = "Size of the Sierra definition above which a class is compiled with \
+                     --sync.casm-compiler-command instead of in-process",
+        env = "PATHFINDER_SYNC_CASM_COMPILER_COMMAND_THRESHOLD",
+        default_value = "1024"
+    )]
+    casm_compiler_command_threshold: usize,
+
+    #[arg(
+        long = "sync.class-memory-threshold",
+        value_name = "MiB",
+        long_help = "Resident memory of the process above which class sync stops compiling and \
+                     buffering more classes until memory usage drops below it again. Only \
+                     supported on Linux, disabled by default.",
+        env = "PATHFINDER_SYNC_CLASS_MEMORY_THRESHOLD"
+    )]
+    class_sync_memory_threshold: Option<NonZeroU64>,
+
+    #[arg(
+        long = "sync.class-pipeline-buffer",
+        long_help = "How many items each stage of the class sync pipeline may process ahead of \
+                     the next stage, eg. blocks of expected declarations or batches of verified \
+                     classes. Larger buffers keep stages from waiting on each other at the cost \
+                     of holding more classes in memory.",
+        env = "PATHFINDER_SYNC_CLASS_PIPELINE_BUFFER",
+        default_value = "1"
+    )]
+    class_pipeline_buffer: NonZeroUsize,
+
+    #[arg(
+        long = "sync.class-chunk-size",
+        long_help = "How many downloaded classes are hash verified together and handed on to be \
+                     checked against their declarations. Classes are still pulled from peers one \
+                     chunk at a time while the pipeline buffer is full, so together with \
+                     --sync.class-pipeline-buffer this bounds how many classes are held in \
+                     memory. Defaults to eight times the number of available CPUs.",
+        env = "PATHFINDER_SYNC_CLASS_CHUNK_SIZE"
+    )]
+    class_chunk_size: Option<NonZeroUsize>,
+
+    #[arg(
+        long = "sync.class-stall-timeout",
+        value_name = "Seconds",
+        long_help = "How long checkpoint sync waits for peers to deliver the next class \
+                     definitions before giving up on them and restarting with new peers. Time \
+                     spent waiting for memory usage to drop does not count.",
+        env = "PATHFINDER_SYNC_CLASS_STALL_TIMEOUT",
+        default_value = "300"
+    )]
+    class_stall_timeout: NonZeroU64,
+
+    #[arg(
+        long = "sync.class-dead-letter-file",
+        value_name = "Path",
+        long_help = "File that batches of synced classes which could not be stored are appended \
+                     to, one class per line, so that the classes can be inspected and stored \
+                     later. Sync still stops at such a batch and requests its classes again. \
+                     Disabled by default.",
+        env = "PATHFINDER_SYNC_CLASS_DEAD_LETTER_FILE"
+    )]
+    class_dead_letter_file: Option<PathBuf>,
+
     #[arg(
         long = "shutdown.grace-period",
         value_name = "Seconds",
@@ -726,11 +957,35 @@ pub struct Config {
     pub gateway_timeout: Duration,
     pub event_filter_cache_size: NonZeroUsize,
     pub get_events_event_filter_block_range_limit: NonZeroUsize,
+    pub get_events_page_size_limit: NonZeroUsize,
+    pub get_events_key_filter_size_limit: NonZeroUsize,
+    pub rpc_class_cache_size: NonZeroUsize,
+    pub rpc_pending_receipt_block_fields_as_null: bool,
     pub blockchain_history: Option<BlockchainHistory>,
     pub state_tries: Option<StateTries>,
+    pub class_compression_dictionary: bool,
+    pub prune_orphan_classes: bool,
+    pub prune_casm_definitions: bool,
+    pub check_casm_hashes: bool,
     pub versioned_constants_map: VersionedConstantsMap,
     pub feeder_gateway_fetch_concurrency: NonZeroUsize,
     pub fetch_casm_from_fgw: bool,
+    pub trust_gateway_class_hashes: bool,
+    pub record_casm_provenance: bool,
+    pub check_gateway_casm: bool,
+    pub compile_retries: usize,
+    pub compile_retry_delay: Duration,
+    pub fgw_circuit_breaker_threshold: u32,
+    pub fgw_circuit_breaker_cooldown: Duration,
+    pub casm_compiler_command: Option<PathBuf>,
+    /// In bytes.
+    pub casm_compiler_command_threshold: usize,
+    /// In bytes.
+    pub class_sync_memory_threshold: Option<u64>,
+    pub class_pipeline_buffer: NonZeroUsize,
+    pub class_chunk_size: Option<NonZeroUsize>,
+    pub class_stall_timeout: Duration,
+    pub class_dead_letter_file: Option<PathBuf>,
     pub shutdown_grace_period: Duration,
     pub fee_estimation_epsilon: Percentage,
     pub native_execution: NativeExecutionConfig,
@@ -913,15 +1168,39 @@ impl Config {
             event_filter_cache_size: cli.event_filter_cache_size,
             get_events_event_filter_block_range_limit: cli
                 .get_events_event_filter_block_range_limit,
+            get_events_page_size_limit: cli.get_events_page_size_limit,
+            get_events_key_filter_size_limit: cli.get_events_key_filter_size_limit,
+            rpc_class_cache_size: cli.rpc_class_cache_size,
+            rpc_pending_receipt_block_fields_as_null: cli.rpc_pending_receipt_block_fields_as_null,
             gateway_timeout: Duration::from_secs(cli.gateway_timeout.get()),
             feeder_gateway_fetch_concurrency: cli.feeder_gateway_fetch_concurrency,
             blockchain_history: cli.blockchain_history,
             state_tries: cli.state_tries,
+            class_compression_dictionary: cli.class_compression_dictionary,
+            prune_orphan_classes: cli.prune_orphan_classes,
+            prune_casm_definitions: cli.prune_casm_definitions,
+            check_casm_hashes: cli.check_casm_hashes,
             versioned_constants_map: cli
                 .custom_versioned_constants_path
                 .map(|path| parse_versioned_constants_or_exit(&path))
                 .unwrap_or_default(),
             fetch_casm_from_fgw: cli.fetch_casm_from_fgw,
+            trust_gateway_class_hashes: cli.trust_gateway_class_hashes,
+            record_casm_provenance: cli.record_casm_provenance,
+            check_gateway_casm: cli.check_gateway_casm,
+            compile_retries: cli.compile_retries,
+            compile_retry_delay: Duration::from_millis(cli.compile_retry_delay),
+            fgw_circuit_breaker_threshold: cli.fgw_circuit_breaker_threshold,
+            fgw_circuit_breaker_cooldown: Duration::from_secs(cli.fgw_circuit_breaker_cooldown),
+            casm_compiler_command: cli.casm_compiler_command,
+            casm_compiler_command_threshold: cli.casm_compiler_command_threshold * 1024,
+            class_sync_memory_threshold: cli
+                .class_sync_memory_threshold
+                .map(|mib| mib.get().saturating_mul(1024 * 1024)),
+            class_pipeline_buffer: cli.class_pipeline_buffer,
+            class_chunk_size: cli.class_chunk_size,
+            class_stall_timeout: Duration::from_secs(cli.class_stall_timeout.get()),
+            class_dead_letter_file: cli.class_dead_letter_file,
             shutdown_grace_period: Duration::from_secs(cli.shutdown_grace_period.get()),
             fee_estimation_epsilon: cli.fee_estimation_epsilon,
             #[cfg_attr(not(feature = "cairo-native"), allow(clippy::unit_arg))]
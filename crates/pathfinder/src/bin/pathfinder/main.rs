@@ -26,6 +26,7 @@ use tracing::{info, warn};
 
 use crate::config::{NetworkConfig, StateTries};
 
+mod casm_check;
 mod config;
 mod p2p;
 mod update;
@@ -68,6 +69,13 @@ async fn async_main() -> anyhow::Result<Storage> {
         "🏁 Starting node."
     );
 
+    if config.trust_gateway_class_hashes {
+        warn!(
+            "⚠️ Class hashes are NOT verified for classes downloaded from the feeder gateway. \
+             Only use this with a feeder gateway you trust."
+        );
+    }
+
     if !config.data_directory.exists() {
         std::fs::DirBuilder::new()
             .create(&config.data_directory)
@@ -133,6 +141,7 @@ async fn async_main() -> anyhow::Result<Storage> {
             .event_filter_cache_size(config.event_filter_cache_size.get())
             .trie_prune_mode(config.state_tries.map(StateTries::into))
             .blockchain_history_mode(config.blockchain_history.map(BlockchainHistory::into))
+            .class_compression_dictionary(config.class_compression_dictionary)
             .migrate()?;
 
     let sync_storage = storage_manager
@@ -195,6 +204,24 @@ Hint: This is usually caused by exceeding the file descriptor limit of your syst
       Try increasing the file limit to using `ulimit` or similar tooling.",
         )?;
 
+    let gateway_casm_storage = if config.check_gateway_casm {
+        let storage = storage_manager
+            .create_read_only_pool(NonZeroU32::new(1).unwrap())
+            .context("Creating database connection pool for checking gateway CASM")?;
+        Some(storage)
+    } else {
+        None
+    };
+
+    let casm_check_storage = if config.check_casm_hashes {
+        let storage = storage_manager
+            .create_read_only_pool(NonZeroU32::new(1).unwrap())
+            .context("Creating database connection pool for checking casm hashes")?;
+        Some(storage)
+    } else {
+        None
+    };
+
     info!(location=?pathfinder_context.database, "Database migrated.");
     verify_database(
         &sync_storage,
@@ -212,6 +239,19 @@ Hint: This is usually caused by exceeding the file descriptor limit of your syst
         .prune_tries()
         .context("Pruning tries on startup")?;
 
+    if config.class_compression_dictionary {
+        train_class_compression_dictionary(&sync_storage)
+            .context("Training class compression dictionary")?;
+    }
+
+    if config.prune_orphan_classes {
+        prune_orphan_classes(&sync_storage).context("Pruning orphan classes")?;
+    }
+
+    if config.prune_casm_definitions {
+        prune_casm_definitions(&sync_storage).context("Pruning casm definitions")?;
+    }
+
     // Register signal handlers here, because we want to be able to interrupt long
     // running migrations or trie pruning. No tasks are spawned before this point so
     // we don't worry about detachment.
@@ -223,12 +263,17 @@ Hint: This is usually caused by exceeding the file descriptor limit of your syst
     let rpc_config = pathfinder_rpc::context::RpcConfig {
         batch_concurrency_limit: config.rpc_batch_concurrency_limit,
         get_events_event_filter_block_range_limit: config.get_events_event_filter_block_range_limit,
+        get_events_page_size_limit: config.get_events_page_size_limit,
+        get_events_key_filter_size_limit: config.get_events_key_filter_size_limit,
         fee_estimation_epsilon: config.fee_estimation_epsilon,
         versioned_constants_map: config.versioned_constants_map.clone(),
         native_execution: config.native_execution.is_enabled(),
         native_class_cache_size: config.native_execution.class_cache_size(),
         submission_tracker_time_limit: config.submission_tracker_time_limit,
         submission_tracker_size_limit: config.submission_tracker_size_limit,
+        fetch_casm_from_fgw: config.fetch_casm_from_fgw,
+        class_cache_size: config.rpc_class_cache_size,
+        pending_receipt_block_fields_as_null: config.rpc_pending_receipt_block_fields_as_null,
     };
 
     let notifications = Notifications::default();
@@ -338,6 +383,17 @@ Hint: This is usually caused by exceeding the file descriptor limit of your syst
         util::task::spawn(update::poll_github_for_releases());
     }
 
+    if let Some(storage) = gateway_casm_storage {
+        util::task::spawn(update::check_gateway_casm(storage));
+    }
+
+    if let Some(storage) = casm_check_storage {
+        util::task::spawn(casm_check::check_casm_hashes(
+            storage,
+            config.data_directory.join("casm-hash-check.json"),
+        ));
+    }
+
     // We are now ready.
     readiness.store(true, std::sync::atomic::Ordering::Relaxed);
 
@@ -489,6 +545,13 @@ fn start_sync(
         )
     } else {
         let p2p_client = p2p_client.expect("P2P client is expected with the p2p feature enabled");
+        let memory_pressure = match config.class_sync_memory_threshold {
+            Some(threshold) => pathfinder_lib::monitoring::spawn_memory_monitor(
+                threshold,
+                std::time::Duration::from_secs(1),
+            ),
+            None => Default::default(),
+        };
         start_p2p_sync(
             storage,
             pathfinder_context,
@@ -497,6 +560,32 @@ fn start_sync(
             gateway_public_key,
             config.sync_p2p.l1_checkpoint_override,
             verify_tree_hashes,
+            pathfinder_lib::sync::ClassSyncConfig {
+                record_casm_provenance: config.record_casm_provenance,
+                store_casm_definitions: !config.prune_casm_definitions,
+                casm_compiler: match &config.casm_compiler_command {
+                    Some(command) => pathfinder_lib::sync::SizeBasedCompiler::new(
+                        Arc::new(pathfinder_lib::sync::EmbeddedCompiler),
+                        Arc::new(pathfinder_lib::sync::ProcessCompiler::new(command.clone())),
+                        config.casm_compiler_command_threshold,
+                    ),
+                    None => Default::default(),
+                },
+                compile_retries: pathfinder_lib::sync::CompileRetries {
+                    max_retries: config.compile_retries,
+                    delay: config.compile_retry_delay,
+                },
+                fgw_breaker: pathfinder_lib::sync::FgwCircuitBreaker::new(
+                    config.fgw_circuit_breaker_threshold,
+                    config.fgw_circuit_breaker_cooldown,
+                ),
+                memory_pressure,
+                pipeline_buffer: config.class_pipeline_buffer,
+                chunk_size: config.class_chunk_size,
+                stall_timeout: config.class_stall_timeout,
+                stats: sync_state.class_sync_stats.clone(),
+                dead_letter: config.class_dead_letter_file.clone(),
+            },
         )
     }
 }
@@ -561,12 +650,15 @@ fn start_feeder_gateway_sync(
         sequencer_public_key: gateway_public_key,
         fetch_concurrency: config.feeder_gateway_fetch_concurrency,
         fetch_casm_from_fgw: config.fetch_casm_from_fgw,
+        trust_class_hashes: config.trust_gateway_class_hashes,
+        store_casm_definitions: !config.prune_casm_definitions,
     };
 
     util::task::spawn(state::sync(sync_context, state::l1::sync, state::l2::sync))
 }
 
 #[cfg(feature = "p2p")]
+#[allow(clippy::too_many_arguments)]
 fn start_p2p_sync(
     storage: Storage,
     pathfinder_context: PathfinderContext,
@@ -575,6 +667,7 @@ fn start_p2p_sync(
     gateway_public_key: pathfinder_common::PublicKey,
     l1_checkpoint_override: Option<pathfinder_ethereum::EthereumStateUpdate>,
     verify_tree_hashes: bool,
+    class_sync: pathfinder_lib::sync::ClassSyncConfig,
 ) -> tokio::task::JoinHandle<anyhow::Result<()>> {
     use pathfinder_block_hashes::BlockHashDb;
 
@@ -589,6 +682,7 @@ fn start_p2p_sync(
         l1_checkpoint_override,
         verify_tree_hashes,
         block_hash_db: Some(BlockHashDb::new(pathfinder_context.network)),
+        class_sync,
     };
     util::task::spawn(sync.run())
 }
@@ -880,6 +974,73 @@ async fn verify_database(
     Ok(())
 }
 
+/// Deletes class definitions which are not declared anywhere.
+fn prune_orphan_classes(storage: &Storage) -> anyhow::Result<()> {
+    let mut conn = storage.connection().context("Create database connection")?;
+    let tx = conn.transaction().context("Create database transaction")?;
+
+    let pruned = tx.prune_orphan_classes()?;
+    tx.commit().context("Committing database transaction")?;
+    info!(count = pruned, "Deleted orphan class definitions");
+
+    Ok(())
+}
+
+/// Deletes the CASM definitions of declared Sierra classes, they are
+/// recompiled from the Sierra definitions when needed. CASM which was fetched
+/// from the gateway, or which does not recompile to the same hash, is kept.
+fn prune_casm_definitions(storage: &Storage) -> anyhow::Result<()> {
+    let mut conn = storage.connection().context("Create database connection")?;
+    let tx = conn.transaction().context("Create database transaction")?;
+
+    let pruned = tx.prune_casm_definitions(BlockNumber::MAX, |class_hash, sierra, casm_hash| {
+        let recompiled = pathfinder_compiler::compile_to_casm(sierra)
+            .and_then(|casm| pathfinder_compiler::casm_class_hash(&casm));
+        match recompiled {
+            Ok(hash) => hash == casm_hash,
+            Err(error) => {
+                tracing::debug!(%class_hash, %error, "Keeping casm definition which fails to recompile");
+                false
+            }
+        }
+    })?;
+    tx.commit().context("Committing database transaction")?;
+    info!(count = pruned, "Deleted casm definitions");
+
+    Ok(())
+}
+
+/// Trains a class compression dictionary unless one exists already. This is
+/// skipped until enough class definitions have been stored.
+fn train_class_compression_dictionary(storage: &Storage) -> anyhow::Result<()> {
+    // Class definitions are large enough for this to exceed the recommended
+    // sample size of 100 times the dictionary size, while keeping the samples'
+    // memory usage in check.
+    const MAX_SAMPLES: usize = 500;
+    const MAX_DICTIONARY_SIZE: usize = 112 * 1024;
+
+    let mut conn = storage.connection().context("Create database connection")?;
+    let tx = conn.transaction().context("Create database transaction")?;
+
+    if let Some(id) = tx.class_compression_dictionary_id()? {
+        tracing::debug!(%id, "Using existing class compression dictionary");
+        return Ok(());
+    }
+
+    match tx.train_class_compression_dictionary(MAX_SAMPLES, MAX_DICTIONARY_SIZE)? {
+        Some(id) => {
+            tx.commit().context("Committing database transaction")?;
+            info!(%id, "Trained class compression dictionary");
+        }
+        None => info!(
+            min_samples = pathfinder_storage::MIN_DICTIONARY_TRAINING_SAMPLES,
+            "Too few class definitions stored to train a compression dictionary, skipping"
+        ),
+    }
+
+    Ok(())
+}
+
 fn handle_critical_task_result(
     task_name: &str,
     task_result: Result<anyhow::Result<()>, JoinError>,
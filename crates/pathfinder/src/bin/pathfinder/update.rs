@@ -1,4 +1,9 @@
-//! Discover pathfinder releases via github API.
+//! Discover pathfinder releases via github API, and what the embedded compiler
+//! of this release can do with classes an older release could not compile.
+
+use anyhow::Context;
+use pathfinder_common::{CasmHash, ClassHash};
+use pathfinder_storage::{CasmSource, Storage};
 
 /// Monitors Github for new releases and logs when this occurs.
 ///
@@ -75,6 +80,121 @@ pub async fn poll_github_for_releases() {
     }
 }
 
+/// Outcome of recompiling a class whose CASM was fetched from the feeder
+/// gateway.
+#[derive(Debug, PartialEq)]
+enum GatewayCasm {
+    /// The embedded compiler produces the same CASM as the feeder gateway.
+    Matches,
+    /// The embedded compiler produces CASM with a different compiled class hash.
+    Differs { local: CasmHash, gateway: CasmHash },
+    /// The embedded compiler still can't compile the class.
+    Unsupported,
+}
+
+impl GatewayCasm {
+    fn as_str(&self) -> &'static str {
+        match self {
+            GatewayCasm::Matches => "matches",
+            GatewayCasm::Differs { .. } => "differs",
+            GatewayCasm::Unsupported => "unsupported",
+        }
+    }
+}
+
+/// Recompiles the Sierra classes whose CASM was recorded as fetched from the
+/// feeder gateway and compares the result to the stored CASM.
+///
+/// Classes are only fetched from the feeder gateway if the embedded compiler
+/// could not compile them. Once a newer release can, any difference between
+/// the two is logged and counted, which is evidence about the correctness of
+/// the feeder gateway. This relies on the provenance recorded with
+/// `--sync.record-casm-provenance`.
+pub async fn check_gateway_casm(storage: Storage) {
+    let span = tracing::Span::current();
+    let result = util::task::spawn_blocking(move |_| {
+        let _g = span.enter();
+        recompile_gateway_casm(&storage)
+    })
+    .await
+    .context("Joining blocking task")
+    .and_then(|result| result);
+
+    match result {
+        Ok(checked) => {
+            let count = |outcome: &str| checked.iter().filter(|x| x.1.as_str() == outcome).count();
+            tracing::info!(
+                matches=%count("matches"),
+                differs=%count("differs"),
+                unsupported=%count("unsupported"),
+                "Recompiled CASM fetched from the feeder gateway"
+            );
+        }
+        Err(error) => {
+            tracing::warn!(%error, "Failed to recompile CASM fetched from the feeder gateway");
+        }
+    }
+}
+
+fn recompile_gateway_casm(storage: &Storage) -> anyhow::Result<Vec<(ClassHash, GatewayCasm)>> {
+    let mut db = storage
+        .connection()
+        .context("Creating database connection")?;
+    let class_hashes = db
+        .transaction()
+        .context("Creating database transaction")?
+        .classes_with_casm_source(CasmSource::Gateway)?;
+
+    let mut checked = Vec::new();
+    for class_hash in class_hashes {
+        // Don't hold a transaction open while compiling.
+        let tx = db.transaction().context("Creating database transaction")?;
+        let sierra_definition = tx.class_definition(class_hash)?;
+        let casm_definition = tx.casm_definition(class_hash)?;
+        drop(tx);
+
+        let (Some(sierra_definition), Some(casm_definition)) = (sierra_definition, casm_definition)
+        else {
+            continue;
+        };
+
+        let outcome = match compare_casm(&sierra_definition, &casm_definition) {
+            Ok(outcome) => outcome,
+            Err(error) => {
+                tracing::warn!(%class_hash, %error, "Failed to compare CASM fetched from the feeder gateway");
+                continue;
+            }
+        };
+
+        if let GatewayCasm::Differs { local, gateway } = &outcome {
+            tracing::warn!(%class_hash, %local, %gateway, "Locally compiled CASM differs from the feeder gateway's");
+        }
+        metrics::increment_counter!("gateway_casm_recompile_total", "result" => outcome.as_str());
+
+        checked.push((class_hash, outcome));
+    }
+
+    Ok(checked)
+}
+
+fn compare_casm(sierra_definition: &[u8], casm_definition: &[u8]) -> anyhow::Result<GatewayCasm> {
+    let Ok(local) = pathfinder_compiler::compile_to_casm(sierra_definition) else {
+        return Ok(GatewayCasm::Unsupported);
+    };
+
+    // The JSON formatting differs so compare the compiled class hashes, which
+    // cover everything relevant for execution.
+    let local = pathfinder_compiler::casm_class_hash(&local).context("Hashing local CASM")?;
+    let gateway =
+        pathfinder_compiler::casm_class_hash(casm_definition).context("Hashing gateway CASM")?;
+
+    if local == gateway {
+        Ok(GatewayCasm::Matches)
+    } else {
+        Ok(GatewayCasm::Differs { local, gateway })
+    }
+}
+
 /// Creates a [reqwest::Client] for use in querying Github API.
 ///
 /// Adds a 5 minute request timeout, and sets the required headers:
@@ -165,6 +285,90 @@ async fn fetch_latest_github_release(
 
 #[cfg(test)]
 mod tests {
+    use pathfinder_common::macro_prelude::*;
+    use pathfinder_common::SierraHash;
+    use pathfinder_storage::{CasmProvenance, StorageBuilder};
+    use starknet_gateway_test_fixtures::class_definitions::{
+        CAIRO_1_1_0_BALANCE_CASM_JSON,
+        CAIRO_1_1_0_BALANCE_SIERRA_JSON,
+    };
+
+    use super::*;
+
+    #[test]
+    fn gateway_sourced_casm_is_recompiled() {
+        let storage = StorageBuilder::in_memory().unwrap();
+        let gateway = sierra_hash!("0x1");
+        let compiled = sierra_hash!("0x2");
+        let tampered = sierra_hash!("0x3");
+
+        let local_casm =
+            pathfinder_compiler::compile_to_casm(CAIRO_1_1_0_BALANCE_SIERRA_JSON).unwrap();
+        // The feeder gateway serving CASM with different bytecode.
+        let mut other_casm = serde_json::from_slice::<serde_json::Value>(&local_casm).unwrap();
+        other_casm["bytecode"][0] = serde_json::json!("0x1234");
+        let other_casm = serde_json::to_vec(&other_casm).unwrap();
+
+        {
+            let mut db = storage.connection().unwrap();
+            let tx = db.transaction().unwrap();
+            let classes: [(SierraHash, &[u8], CasmSource); 3] = [
+                (gateway, &local_casm, CasmSource::Gateway),
+                (
+                    compiled,
+                    CAIRO_1_1_0_BALANCE_CASM_JSON,
+                    CasmSource::Compiled,
+                ),
+                (tampered, &other_casm, CasmSource::Gateway),
+            ];
+            for (hash, casm, source) in classes {
+                tx.insert_sierra_class(
+                    &hash,
+                    CAIRO_1_1_0_BALANCE_SIERRA_JSON,
+                    &casm_hash!("0x1"),
+                    casm,
+                )
+                .unwrap();
+                tx.insert_casm_provenance(
+                    &hash,
+                    CasmProvenance {
+                        source,
+                        recorded_at: 0,
+                    },
+                )
+                .unwrap();
+            }
+            tx.commit().unwrap();
+        }
+
+        let checked = recompile_gateway_casm(&storage).unwrap();
+
+        let local = pathfinder_compiler::casm_class_hash(&local_casm).unwrap();
+        let other = pathfinder_compiler::casm_class_hash(&other_casm).unwrap();
+        assert_ne!(local, other);
+        assert_eq!(
+            checked,
+            vec![
+                (ClassHash(gateway.0), GatewayCasm::Matches),
+                (
+                    ClassHash(tampered.0),
+                    GatewayCasm::Differs {
+                        local,
+                        gateway: other
+                    }
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn unsupported_classes_are_skipped() {
+        assert_eq!(
+            compare_casm(b"not a sierra class", CAIRO_1_1_0_BALANCE_CASM_JSON).unwrap(),
+            GatewayCasm::Unsupported
+        );
+    }
+
     #[tokio::test]
     async fn fetch_latest_github_release() {
         let client = super::configure_client().unwrap();
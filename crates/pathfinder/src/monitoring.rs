@@ -79,6 +79,106 @@ async fn metrics_route(axum::extract::State(state): axum::extract::State<State>)
     state.prometheus.render()
 }
 
+/// Signals whether the process' memory usage is above a threshold, see
+/// [spawn_memory_monitor]. The default never signals pressure.
+#[derive(Clone, Debug)]
+pub struct MemoryPressure(tokio::sync::watch::Receiver<bool>);
+
+impl Default for MemoryPressure {
+    fn default() -> Self {
+        Self::channel().1
+    }
+}
+
+impl MemoryPressure {
+    /// Returns a [MemoryPressure] controlled by the returned sender, where
+    /// `true` means memory usage is above the threshold.
+    pub fn channel() -> (tokio::sync::watch::Sender<bool>, Self) {
+        let (tx, rx) = tokio::sync::watch::channel(false);
+        (tx, Self(rx))
+    }
+
+    pub fn is_high(&self) -> bool {
+        *self.0.borrow()
+    }
+
+    /// Resolves once memory usage is below the threshold, immediately if it
+    /// already is. Also resolves if the monitor is gone.
+    pub async fn relieved(&self) {
+        let mut rx = self.0.clone();
+        _ = rx.wait_for(|high| !high).await;
+    }
+}
+
+/// Spawns a task which polls the resident memory of the process every
+/// `interval` and signals pressure while it exceeds `threshold` bytes.
+///
+/// Resident memory is only available on Linux, elsewhere pressure is never
+/// signalled.
+pub fn spawn_memory_monitor(threshold: u64, interval: std::time::Duration) -> MemoryPressure {
+    let (tx, pressure) = MemoryPressure::channel();
+
+    util::task::spawn(async move {
+        let mut interval = tokio::time::interval(interval);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {}
+                _ = tx.closed() => return,
+            }
+
+            let resident = match resident_memory() {
+                Ok(Some(x)) => x,
+                Ok(None) => return,
+                Err(error) => {
+                    tracing::warn!(%error, "Reading resident memory failed, stopping memory monitor");
+                    _ = tx.send(false);
+                    return;
+                }
+            };
+
+            let high = resident > threshold;
+            tx.send_if_modified(|current| {
+                if *current == high {
+                    return false;
+                }
+                if high {
+                    tracing::info!(%resident, %threshold, "Memory pressure, pausing class sync");
+                } else {
+                    tracing::info!(%resident, %threshold, "Memory pressure relieved, resuming class sync");
+                }
+                *current = high;
+                true
+            });
+        }
+    });
+
+    pressure
+}
+
+/// Returns the resident memory of the process in bytes, or `None` if this is
+/// not supported on the platform.
+fn resident_memory() -> anyhow::Result<Option<u64>> {
+    use anyhow::Context;
+
+    if !cfg!(target_os = "linux") {
+        return Ok(None);
+    }
+
+    let status = std::fs::read_to_string("/proc/self/status").context("Reading process status")?;
+    let kib = status
+        .lines()
+        .find_map(|line| line.strip_prefix("VmRSS:"))
+        .and_then(|x| x.trim().strip_suffix("kB"))
+        .context("VmRSS missing from process status")?
+        .trim()
+        .parse::<u64>()
+        .context("Parsing VmRSS")?;
+
+    Ok(Some(kib * 1024))
+}
+
 #[cfg(test)]
 mod tests {
     use std::sync::atomic::AtomicBool;
@@ -106,6 +206,24 @@ mod tests {
         .unwrap();
     }
 
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn memory_monitor() {
+        // Any running process uses more than a byte of memory.
+        let pressure = super::spawn_memory_monitor(1, Duration::from_millis(10));
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while !pressure.is_high() {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .unwrap();
+
+        let pressure = super::spawn_memory_monitor(u64::MAX, Duration::from_millis(10));
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(!pressure.is_high());
+    }
+
     #[tokio::test]
     async fn health() {
         let readiness = Arc::new(AtomicBool::new(false));
@@ -160,6 +278,7 @@ mod tests {
         let handle = PrometheusBuilder::new().build_recorder().handle();
         let sync_state = Arc::new(SyncState {
             status: RwLock::new(Syncing::False),
+            ..Default::default()
         });
         let (addr, _) = super::spawn_server(
             ([127, 0, 0, 1], 0),
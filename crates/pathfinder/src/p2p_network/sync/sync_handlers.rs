@@ -168,9 +168,10 @@ fn get_classes_for_block(
                         block_number
                     )
                 })?;
-            let casm_definition = db_tx.casm_definition(class_hash)?;
-            Ok(match casm_definition {
-                Some(_casm) => ClassDefinition::Sierra {
+            // The CASM definition itself may have been pruned, so classify by its hash.
+            let casm_hash = db_tx.casm_hash(class_hash)?;
+            Ok(match casm_hash {
+                Some(_) => ClassDefinition::Sierra {
                     sierra: definition,
                     _casm: Vec::new(), // TODO casm
                 },
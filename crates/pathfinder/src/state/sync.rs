@@ -17,7 +17,7 @@ use pathfinder_merkle_tree::starknet_state::update_starknet_state;
 use pathfinder_rpc::types::syncing::{self, NumberedBlock, Syncing};
 use pathfinder_rpc::{Notifications, PendingData, Reorg, SyncState, TopicBroadcasters};
 use pathfinder_storage::pruning::BlockchainHistoryMode;
-use pathfinder_storage::{Connection, Storage, Transaction, TransactionBehavior};
+use pathfinder_storage::{CasmSource, Connection, Storage, Transaction, TransactionBehavior};
 use primitive_types::H160;
 use starknet_gateway_client::GatewayApi;
 use starknet_gateway_types::reply::{Block, GasPrices, PendingBlock, PreConfirmedBlock};
@@ -63,6 +63,7 @@ pub enum SyncEvent {
         sierra_hash: SierraHash,
         casm_definition: Vec<u8>,
         casm_hash: CasmHash,
+        casm_source: CasmSource,
     },
     /// A new L2 pending update was polled.
     Pending((Box<PendingBlock>, Box<StateUpdate>)),
@@ -90,6 +91,10 @@ pub struct SyncContext<G, E> {
     pub sequencer_public_key: PublicKey,
     pub fetch_concurrency: std::num::NonZeroUsize,
     pub fetch_casm_from_fgw: bool,
+    pub trust_class_hashes: bool,
+    /// Whether to store locally compiled CASM definitions, instead of
+    /// recompiling them from the Sierra definition when needed.
+    pub store_casm_definitions: bool,
 }
 
 impl<G, E> From<&SyncContext<G, E>> for L1SyncContext<E>
@@ -120,6 +125,7 @@ where
             sequencer_public_key: value.sequencer_public_key,
             fetch_concurrency: value.fetch_concurrency,
             fetch_casm_from_fgw: value.fetch_casm_from_fgw,
+            trust_class_hashes: value.trust_class_hashes,
         }
     }
 }
@@ -168,6 +174,8 @@ where
         sequencer_public_key: _,
         fetch_concurrency: _,
         fetch_casm_from_fgw,
+        trust_class_hashes: _,
+        store_casm_definitions,
     } = context;
 
     let mut db_conn = storage
@@ -243,6 +251,7 @@ where
         state,
         pending_data,
         verify_tree_hashes: context.verify_tree_hashes,
+        store_casm_definitions,
         websocket_txs,
         notifications,
     };
@@ -412,6 +421,7 @@ struct ConsumerContext {
     pub state: Arc<SyncState>,
     pub pending_data: WatchSender<PendingData>,
     pub verify_tree_hashes: bool,
+    pub store_casm_definitions: bool,
     pub websocket_txs: Option<TopicBroadcasters>,
     pub notifications: Notifications,
 }
@@ -426,6 +436,7 @@ async fn consumer(
         state,
         pending_data,
         verify_tree_hashes,
+        store_casm_definitions,
         mut websocket_txs,
         mut notifications,
     } = context;
@@ -617,15 +628,27 @@ async fn consumer(
                     sierra_hash,
                     casm_definition,
                     casm_hash,
+                    casm_source,
                 } => {
                     tracing::trace!("Inserting new Sierra class with hash: {sierra_hash}");
-                    tx.insert_sierra_class(
-                        &sierra_hash,
-                        &sierra_definition,
-                        &casm_hash,
-                        &casm_definition,
-                    )
-                    .context("Inserting sierra class")?;
+                    // Only locally compiled CASM is known to be reproducible from the Sierra
+                    // definition.
+                    if store_casm_definitions || casm_source != CasmSource::Compiled {
+                        tx.insert_sierra_class(
+                            &sierra_hash,
+                            &sierra_definition,
+                            &casm_hash,
+                            &casm_definition,
+                        )
+                        .context("Inserting sierra class")?;
+                    } else {
+                        tx.insert_sierra_class_without_casm(
+                            &sierra_hash,
+                            &sierra_definition,
+                            &casm_hash,
+                        )
+                        .context("Inserting sierra class without casm")?;
+                    }
 
                     tracing::debug!(sierra=%sierra_hash, casm=%casm_hash, "Inserted new Sierra class");
                 }
@@ -1186,7 +1209,7 @@ mod tests {
     };
     use pathfinder_crypto::Felt;
     use pathfinder_rpc::SyncState;
-    use pathfinder_storage::StorageBuilder;
+    use pathfinder_storage::{CasmSource, StorageBuilder};
     use starknet_gateway_types::reply::{self, Block, GasPrices};
 
     use super::l2;
@@ -1639,6 +1662,7 @@ mod tests {
             state: Arc::new(SyncState::default()),
             pending_data: tx,
             verify_tree_hashes: false,
+            store_casm_definitions: true,
             websocket_txs: None,
             notifications: Default::default(),
         };
@@ -1693,6 +1717,7 @@ mod tests {
             state: Arc::new(SyncState::default()),
             pending_data: tx,
             verify_tree_hashes: false,
+            store_casm_definitions: true,
             websocket_txs: None,
             notifications: Default::default(),
         };
@@ -1761,6 +1786,7 @@ mod tests {
             state: Arc::new(SyncState::default()),
             pending_data: tx,
             verify_tree_hashes: false,
+            store_casm_definitions: true,
             websocket_txs: None,
             notifications: Default::default(),
         };
@@ -1814,6 +1840,7 @@ mod tests {
             state: Arc::new(SyncState::default()),
             pending_data: tx,
             verify_tree_hashes: false,
+            store_casm_definitions: true,
             websocket_txs: None,
             notifications: Default::default(),
         };
@@ -1856,6 +1883,7 @@ mod tests {
             state: Arc::new(SyncState::default()),
             pending_data: tx,
             verify_tree_hashes: false,
+            store_casm_definitions: true,
             websocket_txs: None,
             notifications: Default::default(),
         };
@@ -1889,6 +1917,7 @@ mod tests {
                 sierra_hash: SierraHash(class_hash),
                 casm_definition: b"casm definition".to_vec(),
                 casm_hash: casm_hash_bytes!(b"casm hash"),
+                casm_source: CasmSource::Gateway,
             })
             .await
             .unwrap();
@@ -1901,6 +1930,7 @@ mod tests {
             state: Arc::new(SyncState::default()),
             pending_data: tx,
             verify_tree_hashes: false,
+            store_casm_definitions: true,
             websocket_txs: None,
             notifications: Default::default(),
         };
@@ -1949,6 +1979,7 @@ mod tests {
             state: Arc::new(SyncState::default()),
             pending_data: tx,
             verify_tree_hashes: false,
+            store_casm_definitions: true,
             websocket_txs: None,
             notifications: Default::default(),
         };
@@ -2111,6 +2142,7 @@ mod tests {
                 state: Arc::new(SyncState::default()),
                 pending_data: tx,
                 verify_tree_hashes: false,
+                store_casm_definitions: true,
                 websocket_txs: None,
                 notifications,
             };
@@ -2168,6 +2200,7 @@ mod tests {
                 state: Arc::new(SyncState::default()),
                 pending_data: tx,
                 verify_tree_hashes: false,
+                store_casm_definitions: true,
                 websocket_txs: None,
                 notifications: Default::default(),
             };
@@ -2267,6 +2300,7 @@ mod tests {
                 state: Arc::new(SyncState::default()),
                 pending_data: tx,
                 verify_tree_hashes: false,
+                store_casm_definitions: true,
                 websocket_txs: None,
                 notifications,
             };
@@ -2291,6 +2325,7 @@ mod tests {
                 state: Arc::new(SyncState::default()),
                 pending_data: tx,
                 verify_tree_hashes: false,
+                store_casm_definitions: true,
                 websocket_txs: None,
                 notifications,
             };
@@ -2320,6 +2355,7 @@ Blockchain history must include the reorg tail and its parent block to perform a
                 state: Arc::new(SyncState::default()),
                 pending_data: tx,
                 verify_tree_hashes: false,
+                store_casm_definitions: true,
                 websocket_txs: None,
                 notifications,
             };
@@ -2365,6 +2401,7 @@ Blockchain history must include the reorg tail and its parent block to perform a
                 state: Arc::new(SyncState::default()),
                 pending_data: tx,
                 verify_tree_hashes: false,
+                store_casm_definitions: true,
                 websocket_txs: None,
                 notifications,
             };
@@ -2392,6 +2429,7 @@ Blockchain history must include the reorg tail and its parent block to perform a
                 state: Arc::new(SyncState::default()),
                 pending_data: tx,
                 verify_tree_hashes: false,
+                store_casm_definitions: true,
                 websocket_txs: None,
                 notifications,
             };
@@ -2447,6 +2485,7 @@ Blockchain history must include the reorg tail and its parent block to perform a
                 state: Arc::new(SyncState::default()),
                 pending_data: tx,
                 verify_tree_hashes: false,
+                store_casm_definitions: true,
                 websocket_txs: None,
                 notifications: Default::default(),
             };
@@ -2565,6 +2604,7 @@ Blockchain history must include the reorg tail and its parent block to perform a
                 state: Arc::new(SyncState::default()),
                 pending_data: tx,
                 verify_tree_hashes: false,
+                store_casm_definitions: true,
                 websocket_txs: None,
                 notifications: Default::default(),
             };
@@ -2651,6 +2691,7 @@ Blockchain history must include the reorg tail and its parent block to perform a
                 state: Arc::new(SyncState::default()),
                 pending_data: tx,
                 verify_tree_hashes: false,
+                store_casm_definitions: true,
                 websocket_txs: None,
                 notifications: Default::default(),
             };
@@ -2685,6 +2726,7 @@ Blockchain history must include the reorg tail and its parent block to perform a
                 state: Arc::new(SyncState::default()),
                 pending_data: tx,
                 verify_tree_hashes: false,
+                store_casm_definitions: true,
                 websocket_txs: None,
                 notifications: Default::default(),
             };
@@ -1,5 +1,6 @@
 use anyhow::Context;
 use pathfinder_common::{ClassHash, SierraHash};
+use pathfinder_storage::CasmSource;
 use starknet_gateway_client::GatewayApi;
 
 pub enum DownloadedClass {
@@ -11,6 +12,7 @@ pub enum DownloadedClass {
         sierra_definition: Vec<u8>,
         sierra_hash: SierraHash,
         casm_definition: Vec<u8>,
+        casm_source: CasmSource,
     },
 }
 
@@ -18,6 +20,7 @@ pub async fn download_class<SequencerClient: GatewayApi>(
     sequencer: &SequencerClient,
     class_hash: ClassHash,
     fetch_casm_from_fgw: bool,
+    trust_class_hash: bool,
 ) -> Result<DownloadedClass, anyhow::Error> {
     use pathfinder_class_hash::compute_class_hash;
 
@@ -29,7 +32,11 @@ pub async fn download_class<SequencerClient: GatewayApi>(
 
     let (tx, rx) = tokio::sync::oneshot::channel();
     rayon::spawn(move || {
-        let computed_hash = compute_class_hash(&definition).context("Computing class hash");
+        let computed_hash = if trust_class_hash {
+            trusted_class_hash(&definition, class_hash)
+        } else {
+            compute_class_hash(&definition).context("Computing class hash")
+        };
         let _ = tx.send((computed_hash, definition));
     });
     let (hash, definition) = rx.await.context("Panic on rayon thread")?;
@@ -61,7 +68,7 @@ pub async fn download_class<SequencerClient: GatewayApi>(
             // replaces the casm definition with empty bytes.
             let span = tracing::Span::current();
 
-            let (sierra_definition, casm_definition) = if fetch_casm_from_fgw {
+            let (sierra_definition, casm_definition, casm_source) = if fetch_casm_from_fgw {
                 (
                     definition,
                     sequencer
@@ -69,6 +76,7 @@ pub async fn download_class<SequencerClient: GatewayApi>(
                         .await
                         .with_context(|| format!("Downloading CASM {}", class_hash.0))?
                         .to_vec(),
+                    CasmSource::Gateway,
                 )
             } else {
                 let (send, recv) = tokio::sync::oneshot::channel();
@@ -82,25 +90,44 @@ pub async fn download_class<SequencerClient: GatewayApi>(
                 let (casm_definition, sierra_definition) =
                     recv.await.expect("Panic on rayon thread");
 
-                let casm_definition = match casm_definition {
-                    Ok(casm_definition) => casm_definition,
+                let (casm_definition, casm_source) = match casm_definition {
+                    Ok(casm_definition) => (casm_definition, CasmSource::Compiled),
                     Err(error) => {
                         tracing::info!(class_hash=%hash, ?error, "CASM compilation failed, falling back to fetching from gateway");
-                        sequencer
+                        let casm_definition = sequencer
                             .pending_casm_by_hash(class_hash)
                             .await
                             .with_context(|| format!("Downloading CASM {}", class_hash.0))?
-                            .to_vec()
+                            .to_vec();
+                        (casm_definition, CasmSource::Gateway)
                     }
                 };
-                (sierra_definition, casm_definition)
+                (sierra_definition, casm_definition, casm_source)
             };
 
             Ok(DownloadedClass::Sierra {
                 sierra_definition,
                 sierra_hash: SierraHash(hash.0),
                 casm_definition,
+                casm_source,
             })
         }
     }
 }
+
+/// Takes `class_hash` as is instead of computing it from `definition`, which is
+/// only parsed to tell Cairo 0 and Sierra classes apart.
+fn trusted_class_hash(
+    definition: &[u8],
+    class_hash: ClassHash,
+) -> anyhow::Result<pathfinder_class_hash::ComputedClassHash> {
+    use pathfinder_class_hash::json::ContractDefinition;
+    use pathfinder_class_hash::{parse_contract_definition, ComputedClassHash};
+
+    let hash = match parse_contract_definition(definition).context("Parsing class definition")? {
+        ContractDefinition::Cairo(_) => ComputedClassHash::Cairo(class_hash),
+        ContractDefinition::Sierra(_) => ComputedClassHash::Sierra(class_hash),
+    };
+
+    Ok(hash)
+}
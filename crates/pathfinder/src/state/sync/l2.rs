@@ -93,6 +93,9 @@ pub struct L2SyncContext<GatewayClient> {
     pub sequencer_public_key: PublicKey,
     pub fetch_concurrency: std::num::NonZeroUsize,
     pub fetch_casm_from_fgw: bool,
+    /// Take the hashes of downloaded classes for granted instead of computing
+    /// them.
+    pub trust_class_hashes: bool,
 }
 
 pub async fn sync<GatewayClient>(
@@ -125,6 +128,7 @@ where
         sequencer_public_key,
         fetch_concurrency: _,
         fetch_casm_from_fgw,
+        trust_class_hashes,
     } = context;
 
     // Start polling head of chain
@@ -237,6 +241,7 @@ where
             &sequencer,
             storage.clone(),
             fetch_casm_from_fgw,
+            trust_class_hashes,
         )
         .await
         .with_context(|| format!("Handling newly declared classes for block {next:?}"))?;
@@ -376,6 +381,7 @@ pub async fn download_new_classes(
     sequencer: &impl GatewayApi,
     storage: Storage,
     fetch_casm_from_fgw: bool,
+    trust_class_hashes: bool,
 ) -> Result<Vec<DownloadedClass>, anyhow::Error> {
     let deployed_classes = state_update
         .contract_updates
@@ -429,9 +435,14 @@ pub async fn download_new_classes(
 
     let futures = require_downloading.into_iter().map(|class_hash| {
         async move {
-            download_class(sequencer, class_hash, fetch_casm_from_fgw)
-                .await
-                .with_context(|| format!("Downloading class {}", class_hash.0))
+            download_class(
+                sequencer,
+                class_hash,
+                fetch_casm_from_fgw,
+                trust_class_hashes,
+            )
+            .await
+            .with_context(|| format!("Downloading class {}", class_hash.0))
         }
         .in_current_span()
     });
@@ -627,6 +638,7 @@ where
         sequencer_public_key,
         fetch_concurrency,
         fetch_casm_from_fgw,
+        trust_class_hashes,
     } = context;
 
     let mut start = match head {
@@ -724,12 +736,17 @@ where
                     .context("Verifying block contents")?;
 
                 let t_declare = std::time::Instant::now();
-                let downloaded_classes =
-                    download_new_classes(&state_update, &sequencer, storage, fetch_casm_from_fgw)
-                        .await
-                        .with_context(|| {
-                            format!("Handling newly declared classes for block {block_number:?}")
-                        })?;
+                let downloaded_classes = download_new_classes(
+                    &state_update,
+                    &sequencer,
+                    storage,
+                    fetch_casm_from_fgw,
+                    trust_class_hashes,
+                )
+                .await
+                .with_context(|| {
+                    format!("Handling newly declared classes for block {block_number:?}")
+                })?;
                 let t_declare = t_declare.elapsed();
 
                 let timings = Timings {
@@ -882,6 +899,7 @@ pub(super) async fn emit_events_for_downloaded_classes(
                 sierra_definition,
                 sierra_hash,
                 casm_definition,
+                casm_source,
             } => {
                 // NOTE: we _have_ to use the same compiled_class_class hash as returned by the
                 // feeder gateway, since that's what has been added to the class
@@ -902,6 +920,7 @@ pub(super) async fn emit_events_for_downloaded_classes(
                         sierra_hash,
                         casm_definition,
                         casm_hash,
+                        casm_source,
                     })
                     .await
                     .with_context(|| {
@@ -1346,6 +1365,7 @@ mod tests {
                 sequencer_public_key: PublicKey::ZERO,
                 fetch_concurrency: std::num::NonZeroUsize::new(1).unwrap(),
                 fetch_casm_from_fgw: false,
+                trust_class_hashes: false,
             };
 
             let latest = tokio::sync::watch::channel(Default::default());
@@ -1388,6 +1408,7 @@ mod tests {
                 sequencer_public_key: PublicKey::ZERO,
                 fetch_concurrency: std::num::NonZeroUsize::new(1).unwrap(),
                 fetch_casm_from_fgw: false,
+                trust_class_hashes: false,
             };
 
             tokio::spawn(sync(
@@ -1418,6 +1439,7 @@ mod tests {
                 sequencer_public_key: PublicKey::ZERO,
                 fetch_concurrency: std::num::NonZeroUsize::new(2).unwrap(),
                 fetch_casm_from_fgw: false,
+                trust_class_hashes: false,
             };
 
             tokio::spawn(async move {
@@ -1927,6 +1949,7 @@ mod tests {
                     sequencer_public_key: PublicKey::ZERO,
                     fetch_concurrency: std::num::NonZeroUsize::new(1).unwrap(),
                     fetch_casm_from_fgw: false,
+                    trust_class_hashes: false,
                 };
                 let latest_track = tokio::sync::watch::channel(Default::default());
 
@@ -92,6 +92,8 @@ pub async fn poll_pre_starknet_0_14_0<S: GatewayApi + Clone + Send + 'static>(
             sequencer,
             storage.clone(),
             fetch_casm_from_fgw,
+            // Pending classes are few, keep verifying them.
+            false,
         )
         .await
         {
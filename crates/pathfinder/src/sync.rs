@@ -40,6 +40,27 @@ mod stream;
 mod track;
 mod transactions;
 
+#[cfg(feature = "bench")]
+pub use class_definitions::bench_verify_classes;
+#[cfg(any(test, feature = "fuzzing"))]
+pub use class_definitions::fuzz_verify_layout;
+#[cfg(any(test, feature = "test-utils"))]
+pub use class_definitions::run_pipeline as run_class_pipeline;
+pub use class_definitions::{
+    export_stream as export_class_definitions,
+    refetch_class,
+    CasmCompiler,
+    ClassSyncConfig,
+    CompileRetries,
+    CompiledClass,
+    CompiledClassDefinition,
+    EmbeddedCompiler,
+    ExpectedDeclarations,
+    FgwCircuitBreaker,
+    ProcessCompiler,
+    SizeBasedCompiler,
+};
+
 const CHECKPOINT_MARGIN: u64 = 10;
 
 pub struct Sync<P, G> {
@@ -53,6 +74,7 @@ pub struct Sync<P, G> {
     pub l1_checkpoint_override: Option<EthereumStateUpdate>,
     pub verify_tree_hashes: bool,
     pub block_hash_db: Option<BlockHashDb>,
+    pub class_sync: ClassSyncConfig,
 }
 
 impl<P, G> Sync<P, G>
@@ -116,6 +138,10 @@ where
         let mut checkpoint = self.get_checkpoint().await;
         let from = (checkpoint.block_number, checkpoint.block_hash);
 
+        class_definitions::recover_interrupted_writes(self.storage.clone())
+            .await
+            .context("Recovering interrupted class definition writes")?;
+
         tracing::info!(?from, "Checkpoint sync started");
 
         loop {
@@ -129,6 +155,7 @@ where
                 public_key: self.public_key,
                 verify_tree_hashes: self.verify_tree_hashes,
                 block_hash_db: self.block_hash_db.clone(),
+                class_sync: self.class_sync.clone(),
             }
             .run(checkpoint)
             .await;
@@ -190,6 +217,14 @@ where
                 public_key: self.public_key,
                 verify_tree_hashes: self.verify_tree_hashes,
                 block_hash_db: self.block_hash_db.clone(),
+                record_casm_provenance: self.class_sync.record_casm_provenance,
+                store_casm_definitions: self.class_sync.store_casm_definitions,
+                casm_compiler: self.class_sync.casm_compiler.clone(),
+                compile_retries: self.class_sync.compile_retries,
+                fgw_breaker: self.class_sync.fgw_breaker.clone(),
+                memory_pressure: self.class_sync.memory_pressure.clone(),
+                class_pipeline_buffer: self.class_sync.pipeline_buffer,
+                class_sync_stats: self.class_sync.stats.clone(),
             }
             .run(&mut next, &mut parent_hash, self.fgw_client.clone())
             .await;
@@ -492,6 +527,10 @@ mod tests {
             }),
             verify_tree_hashes: true,
             block_hash_db: None,
+            class_sync: ClassSyncConfig {
+                stall_timeout: Duration::from_secs(60),
+                ..Default::default()
+            },
         };
 
         let sync_done = if error_setup.fatal_at.is_some() {
@@ -1063,7 +1102,19 @@ mod tests {
     #[async_trait::async_trait]
     impl GatewayApi for FakeFgw {
         async fn pending_casm_by_hash(&self, _: ClassHash) -> Result<bytes::Bytes, SequencerError> {
-            Ok(bytes::Bytes::from_static(b"I'm from the fgw!"))
+            Ok(bytes::Bytes::from_static(
+                br#"{"compiler_version": "1.0.0"}"#,
+            ))
+        }
+
+        async fn casm_by_hash(
+            &self,
+            _: ClassHash,
+            _: BlockId,
+        ) -> Result<bytes::Bytes, SequencerError> {
+            Ok(bytes::Bytes::from_static(
+                br#"{"compiler_version": "1.0.0"}"#,
+            ))
         }
 
         async fn block_header(
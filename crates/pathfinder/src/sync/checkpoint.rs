@@ -3,7 +3,7 @@ use std::collections::HashSet;
 use std::num::NonZeroUsize;
 
 use anyhow::Context;
-use futures::{Stream, StreamExt, TryStreamExt};
+use futures::{Stream, StreamExt, TryFutureExt, TryStreamExt};
 use p2p::sync::client::conv::TryFromDto;
 use p2p::sync::client::peer_agnostic::traits::{
     BlockClient,
@@ -33,7 +33,7 @@ use tracing::Instrument;
 
 use crate::state::block_hash::calculate_transaction_commitment;
 use crate::sync::error::SyncError;
-use crate::sync::stream::{InfallibleSource, Source, SyncReceiver, SyncResult};
+use crate::sync::stream::{InfallibleSource, ProcessStage, Source, SyncReceiver, SyncResult};
 use crate::sync::{class_definitions, events, headers, state_updates, transactions};
 
 /// Provides P2P sync capability for blocks secured by L1.
@@ -49,6 +49,7 @@ pub struct Sync<P, G> {
     pub public_key: PublicKey,
     pub verify_tree_hashes: bool,
     pub block_hash_db: Option<pathfinder_block_hashes::BlockHashDb>,
+    pub class_sync: class_definitions::ClassSyncConfig,
 }
 
 impl<P, G> Sync<P, G>
@@ -74,6 +75,7 @@ where
         l1_anchor_override: Option<EthereumStateUpdate>,
         verify_tree_hashes: bool,
         block_hash_db: Option<BlockHashDb>,
+        class_sync: class_definitions::ClassSyncConfig,
     ) -> Self {
         Self {
             storage,
@@ -85,6 +87,7 @@ where
             public_key,
             verify_tree_hashes,
             block_hash_db,
+            class_sync,
         }
     }
 
@@ -252,14 +255,19 @@ where
             ),
         );
 
-        let expected_declarations =
-            class_definitions::expected_declarations_stream(self.storage.clone(), start, stop);
+        let expected_declarations = class_definitions::expected_declarations_stream(
+            self.storage.clone(),
+            start,
+            stop,
+            self.class_sync.pipeline_buffer,
+        );
 
         handle_class_stream(
             class_stream,
             self.storage.clone(),
             self.fgw_client.clone(),
             expected_declarations,
+            self.class_sync.clone(),
         )
         .await?;
 
@@ -364,6 +372,18 @@ async fn handle_state_diff_stream(
         .await
 }
 
+/// Downloaded classes are verified and checked against their declarations in
+/// chunks of [chunk_size](class_definitions::ClassSyncConfig::chunk_size),
+/// which defaults to a multiple of the available parallelism. The chunk size
+/// only changes how classes are batched, not which classes end up stored.
+///
+/// Together with the pipeline buffer it bounds how many classes are in flight:
+/// the declaration check pulls a new chunk only once the previous one has been
+/// dechunked, and stops pulling altogether while its buffered batches are not
+/// consumed or while under memory pressure.
+///
+/// Fails with [SyncError::ClassStreamStalled] if `class_definitions` yields no
+/// classes for the configured stall timeout.
 async fn handle_class_stream<SequencerClient: GatewayApi + Clone + Send + 'static>(
     class_definitions: impl Stream<Item = StreamItem<ClassDefinition>> + Send + 'static,
     storage: Storage,
@@ -371,35 +391,118 @@ async fn handle_class_stream<SequencerClient: GatewayApi + Clone + Send + 'stati
     expected_declarations: impl Stream<Item = anyhow::Result<(BlockNumber, HashSet<ClassHash>)>>
         + Send
         + 'static,
+    config: class_definitions::ClassSyncConfig,
 ) -> Result<(), SyncError> {
-    // Increasing the chunk size above num cpus improves performance even more.
-    let chunk_size = std::thread::available_parallelism()
-        .context("Getting available parallelism")?
-        .get()
-        * 8;
-
-    let classes_with_hashes = class_definitions
-        .map_err(Into::into)
-        .and_then(class_definitions::verify_layout)
-        .try_chunks(chunk_size)
-        .map_err(|e| e.1)
-        .and_then(class_definitions::verify_hash)
-        .boxed();
-
-    class_definitions::verify_declared_at(expected_declarations.boxed(), classes_with_hashes)
-        .try_chunks(chunk_size)
-        .map_err(|e| e.1)
-        .and_then(|x| {
-            class_definitions::compile_sierra_to_casm_or_fetch(
-                x,
-                fgw.clone(),
-                tokio::runtime::Handle::current(),
+    let class_definitions::ClassSyncConfig {
+        record_casm_provenance,
+        store_casm_definitions,
+        casm_compiler,
+        compile_retries,
+        fgw_breaker,
+        memory_pressure,
+        pipeline_buffer: buffer,
+        chunk_size,
+        stall_timeout,
+        stats,
+        dead_letter,
+    } = config;
+
+    let chunk_size = match chunk_size {
+        Some(chunk_size) => chunk_size.get(),
+        // Increasing the chunk size above num cpus improves performance even more.
+        None => {
+            std::thread::available_parallelism()
+                .context("Getting available parallelism")?
+                .get()
+                * 8
+        }
+    };
+
+    // Classes preceding a bad one in the same chunk are still passed on, so that
+    // they are stored and only the bad one is requested again.
+    let layout_stats = stats.clone();
+    let hash_stats = stats.clone();
+    let classes_with_hashes = class_definitions::flatten_partial_batches(
+        class_definitions
+            .map_err(SyncError::from)
+            .try_chunks(chunk_size)
+            .map_err(|e| e.1.into())
+            .and_then(move |x| {
+                class_definitions::record_stats(
+                    layout_stats.clone(),
+                    class_definitions::VerifyLayout::NAME,
+                    x.len(),
+                    class_definitions::verify_layout(x),
+                )
+                .map_err(class_definitions::PartialBatch::from)
+            })
+            .and_then(move |x| {
+                class_definitions::record_stats(
+                    hash_stats.clone(),
+                    class_definitions::VerifyHash::NAME,
+                    x.len(),
+                    class_definitions::verify_hash(x),
+                )
+            }),
+    )
+    .boxed();
+
+    // Declarations of adjacent blocks are grouped so that the classes of many
+    // sparse blocks are persisted in a single transaction.
+    let expected_declarations = class_definitions::coalesce_declarations(
+        expected_declarations,
+        NonZeroUsize::new(chunk_size).context("Chunk size is zero")?,
+    );
+    let classes =
+        class_definitions::flatten_partial_batches(class_definitions::verify_declared_at(
+            expected_declarations.boxed(),
+            classes_with_hashes,
+            memory_pressure.clone(),
+            buffer,
+            stall_timeout,
+        ));
+
+    let compile_fgw = fgw.clone();
+    let compile_stats = stats.clone();
+    class_definitions::compile_with_cairo_lane(
+        classes,
+        move |x| {
+            class_definitions::record_stats(
+                compile_stats.clone(),
+                class_definitions::CompileSierraToCasm::<SequencerClient>::NAME,
+                x.len(),
+                class_definitions::compile_sierra_to_casm_or_fetch(
+                    x,
+                    compile_fgw.clone(),
+                    tokio::runtime::Handle::current(),
+                    casm_compiler.clone(),
+                    compile_retries,
+                    memory_pressure.clone(),
+                    fgw_breaker.clone(),
+                ),
             )
-        })
-        .and_then(|x| class_definitions::persist(storage.clone(), x))
-        .inspect_ok(|x| tracing::info!(tail=%x, "Class definitions chunk synced"))
-        .try_fold((), |_, _| std::future::ready(Ok(())))
-        .await
+        },
+        // Compiles a few chunks ahead of the one being stored.
+        NonZeroUsize::new(4).expect("4>0"),
+    )
+    .and_then(|x| {
+        class_definitions::record_stats(
+            stats.clone(),
+            class_definitions::Store::NAME,
+            x.len(),
+            class_definitions::persist(
+                storage.clone(),
+                x,
+                record_casm_provenance,
+                store_casm_definitions,
+                class_definitions::PersistRetries::default(),
+                dead_letter.clone(),
+            ),
+        )
+    })
+    .inspect_ok(|x| tracing::info!(tail=%x, "Class definitions chunk synced"))
+    .try_fold((), |_, _| std::future::ready(Ok(())))
+    .await
 }
 
 async fn handle_event_stream(
@@ -1350,6 +1453,7 @@ mod tests {
     mod handle_class_stream {
         use std::collections::HashMap;
         use std::future;
+        use std::sync::Arc;
 
         use assert_matches::assert_matches;
         use fake::{Dummy, Fake, Faker};
@@ -1359,13 +1463,17 @@ mod tests {
         use pathfinder_common::macro_prelude::*;
         use pathfinder_common::prelude::*;
         use pathfinder_common::transaction::TransactionVariant;
+        use pathfinder_common::BlockId;
         use pathfinder_crypto::Felt;
+        use pathfinder_rpc::ClassSyncStats;
         use pathfinder_storage::fake::{self as fake_storage, Block};
         use pathfinder_storage::StorageBuilder;
         use starknet_gateway_test_fixtures::class_definitions::{
             CAIRO_0_10_TUPLES_INTEGRATION as CAIRO,
+            CAIRO_0_10_TUPLES_INTEGRATION_CLASS_HASH as CAIRO_HASH,
             CAIRO_0_11_SIERRA as SIERRA0,
             CAIRO_2_0_0_STACK_OVERFLOW as SIERRA2,
+            CAIRO_2_0_0_STACK_OVERFLOW_CLASS_HASH as SIERRA2_HASH,
         };
         use starknet_gateway_types::error::SequencerError;
 
@@ -1374,8 +1482,8 @@ mod tests {
 
         const SIERRA0_HASH: SierraHash =
             sierra_hash!("0x04e70b19333ae94bd958625f7b61ce9eec631653597e68645e13780061b2136c");
-        const SIERRA2_HASH: SierraHash =
-            sierra_hash!("0x03dd9347d22f1ea2d5fbc7bd1f0860c6c334973499f9f1989fcb81bfff5191da");
+
+        const FGW_CASM: &[u8] = br#"{"compiler_version": "1.0.0", "source": "fgw"}"#;
 
         #[derive(Clone)]
         struct FakeFgw;
@@ -1386,7 +1494,15 @@ mod tests {
                 &self,
                 _: ClassHash,
             ) -> Result<bytes::Bytes, SequencerError> {
-                Ok(bytes::Bytes::from_static(b"I'm from the fgw!"))
+                Ok(bytes::Bytes::from_static(FGW_CASM))
+            }
+
+            async fn casm_by_hash(
+                &self,
+                _: ClassHash,
+                _: BlockId,
+            ) -> Result<bytes::Bytes, SequencerError> {
+                Ok(bytes::Bytes::from_static(FGW_CASM))
             }
         }
 
@@ -1441,13 +1557,7 @@ mod tests {
                 let mut blocks = vec![fake_block(0), fake_block(1)];
 
                 let (cairo_hash, sierra0_hash, sierra2_hash) = if expect_correct_class_hashes {
-                    (
-                        class_hash!(
-                            "0x542460935cea188d21e752d8459d82d60497866aaad21f873cbb61621d34f7f"
-                        ),
-                        SIERRA0_HASH,
-                        SIERRA2_HASH,
-                    )
+                    (CAIRO_HASH, SIERRA0_HASH, SIERRA2_HASH)
                 } else {
                     Default::default()
                 };
@@ -1541,6 +1651,7 @@ mod tests {
                 storage.clone(),
                 FakeFgw,
                 declared_classes.to_stream(),
+                Default::default(),
             )
             .await
             .unwrap();
@@ -1553,7 +1664,7 @@ mod tests {
                     db.casm_definition(ClassHash(SIERRA0_HASH.0))
                         .unwrap()
                         .unwrap(),
-                    b"I'm from the fgw!"
+                    FGW_CASM
                 );
                 assert!(serde_json::from_slice::<serde_json::Value>(
                     &db.casm_definition(ClassHash(SIERRA2_HASH.0))
@@ -1576,6 +1687,139 @@ mod tests {
             assert_eq!(actual_defs, expected_defs);
         }
 
+        #[rstest::rstest]
+        #[case::one(1)]
+        #[case::two(2)]
+        #[case::larger_than_stream(100)]
+        #[tokio::test]
+        async fn chunk_size_does_not_change_output(#[case] chunk_size: usize) {
+            let Setup {
+                streamed_classes,
+                declared_classes,
+                expected_defs,
+                storage,
+            } = setup(true).await;
+            let stats = Arc::new(ClassSyncStats::default());
+
+            handle_class_stream(
+                stream::iter(streamed_classes),
+                storage.clone(),
+                FakeFgw,
+                declared_classes.to_stream(),
+                class_definitions::ClassSyncConfig {
+                    chunk_size: NonZeroUsize::new(chunk_size),
+                    stats: stats.clone(),
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap();
+
+            let actual_defs = tokio::task::spawn_blocking(move || {
+                let mut db = storage.connection().unwrap();
+                let db = db.transaction().unwrap();
+                db.declared_classes_at((BlockNumber::GENESIS + 1).into())
+                    .unwrap()
+                    .unwrap()
+                    .into_iter()
+                    .map(|c| (c, db.class_definition(c).unwrap().unwrap()))
+                    .collect::<HashMap<_, _>>()
+            })
+            .await
+            .unwrap();
+            assert_eq!(actual_defs, expected_defs);
+            assert_eq!(
+                stats.snapshot()[class_definitions::VerifyHash::NAME].items,
+                3
+            );
+        }
+
+        #[tokio::test]
+        async fn stage_stats_are_recorded() {
+            let Setup {
+                streamed_classes,
+                declared_classes,
+                storage,
+                ..
+            } = setup(true).await;
+            let stats = Arc::new(ClassSyncStats::default());
+
+            handle_class_stream(
+                stream::iter(streamed_classes),
+                storage,
+                FakeFgw,
+                declared_classes.to_stream(),
+                class_definitions::ClassSyncConfig {
+                    stats: stats.clone(),
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap();
+
+            let items = stats
+                .snapshot()
+                .into_iter()
+                .map(|(stage, stats)| (stage, stats.items))
+                .collect::<HashMap<_, _>>();
+            // Only the two Sierra classes need compiling.
+            assert_eq!(
+                items,
+                HashMap::from([
+                    (class_definitions::VerifyLayout::NAME, 3),
+                    (class_definitions::VerifyHash::NAME, 3),
+                    (class_definitions::CompileSierraToCasm::<FakeFgw>::NAME, 2),
+                    (class_definitions::Store::NAME, 3),
+                ])
+            );
+        }
+
+        #[tokio::test]
+        async fn casm_provenance() {
+            use pathfinder_storage::CasmSource;
+
+            let Setup {
+                streamed_classes,
+                declared_classes,
+                storage,
+                ..
+            } = setup(true).await;
+
+            handle_class_stream(
+                stream::iter(streamed_classes),
+                storage.clone(),
+                FakeFgw,
+                declared_classes.to_stream(),
+                class_definitions::ClassSyncConfig {
+                    record_casm_provenance: true,
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap();
+
+            let (fetched, compiled) = tokio::task::spawn_blocking(move || {
+                let mut db = storage.connection().unwrap();
+                let db = db.transaction().unwrap();
+                (
+                    db.casm_provenance(ClassHash(SIERRA0_HASH.0))
+                        .unwrap()
+                        .unwrap(),
+                    db.casm_provenance(ClassHash(SIERRA2_HASH.0))
+                        .unwrap()
+                        .unwrap(),
+                )
+            })
+            .await
+            .unwrap();
+
+            // SIERRA0 does not compile so its casm comes from the feeder gateway.
+            assert_eq!(fetched.source, CasmSource::Gateway);
+            assert_eq!(compiled.source, CasmSource::Compiled);
+            assert!(fetched.recorded_at > 0);
+            assert!(compiled.recorded_at > 0);
+        }
+
         #[rstest::rstest]
         #[case::cairo(ClassDefinition::Cairo {
             block_number: BlockNumber::GENESIS + 1,
@@ -1599,6 +1843,7 @@ mod tests {
                         storage,
                         FakeFgw,
                         Faker.fake::<DeclaredClasses>().to_stream(),
+                        Default::default(),
                     )
                     .await,
                     Err(SyncError::BadClassLayout(x)) => assert_eq!(x, expected_peer_id));
@@ -1630,6 +1875,7 @@ mod tests {
                         storage,
                         FakeFgw,
                         declared_classes.to_stream(),
+                        Default::default(),
                     )
                     .await,
                     Err(SyncError::UnexpectedClass(x)) => assert_eq!(x, expected_peer_id));
@@ -1643,6 +1889,7 @@ mod tests {
                     StorageBuilder::in_memory().unwrap(),
                     FakeFgw,
                     Faker.fake::<DeclaredClasses>().to_stream(),
+                    Default::default(),
                 )
                 .await,
                 Err(SyncError::Fatal(_))
@@ -1,6 +1,9 @@
-use std::collections::{HashSet, VecDeque};
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 use std::thread;
+use std::time::Duration;
 
 use anyhow::Context;
 use futures::pin_mut;
@@ -10,20 +13,28 @@ use p2p::sync::client::types::ClassDefinition as P2PClassDefinition;
 use p2p::PeerData;
 use p2p_proto::transaction;
 use pathfinder_class_hash::from_parts::{compute_cairo_class_hash, compute_sierra_class_hash};
-use pathfinder_common::class_definition::{Cairo, ClassDefinition as GwClassDefinition, Sierra};
+use pathfinder_common::class_definition::{
+    Cairo,
+    ClassDefinition as GwClassDefinition,
+    Sierra,
+    CLASS_DEFINITION_MAX_ALLOWED_SIZE,
+};
 use pathfinder_common::state_update::DeclaredClasses;
-use pathfinder_common::{BlockNumber, CasmHash, ClassHash, SierraHash};
-use pathfinder_storage::{Storage, Transaction};
+use pathfinder_common::{BlockId, BlockNumber, CasmHash, ClassHash, SierraHash};
+use pathfinder_rpc::ClassSyncStats;
+use pathfinder_storage::{CasmProvenance, CasmSource, Storage, Transaction};
 use rayon::iter::{IntoParallelIterator, ParallelIterator};
 use serde_json::de;
 use starknet_gateway_client::GatewayApi;
-use starknet_gateway_types::error::SequencerError;
+use starknet_gateway_types::error::{KnownStarknetErrorCode, SequencerError};
 use starknet_gateway_types::reply::call;
 use tokio::sync::mpsc::{self, Receiver};
 use tokio::sync::{oneshot, Mutex};
 use tokio_stream::wrappers::ReceiverStream;
+use tokio_util::sync::CancellationToken;
 
 use super::storage_adapters;
+use crate::monitoring::MemoryPressure;
 use crate::sync::error::SyncError;
 use crate::sync::stream::ProcessStage;
 
@@ -35,32 +46,38 @@ pub struct ClassWithLayout {
     pub hash: ClassHash,
 }
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq)]
 pub(super) enum ClassDefinition {
     Cairo(Vec<u8>),
     Sierra(Vec<u8>),
 }
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq)]
 pub struct Class {
     pub block_number: BlockNumber,
     pub hash: ClassHash,
     pub definition: ClassDefinition,
 }
 
-#[derive(Debug)]
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
 pub struct CompiledClass {
     pub block_number: BlockNumber,
     pub hash: ClassHash,
     pub definition: CompiledClassDefinition,
 }
 
-#[derive(Debug)]
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
 pub enum CompiledClassDefinition {
     Cairo(Vec<u8>),
     Sierra {
         sierra_definition: Vec<u8>,
-        casm_definition: Vec<u8>,
+        /// `None` if the CASM definition was pruned from storage, see
+        /// [Transaction::casm_definition_is_pruned].
+        casm_definition: Option<Vec<u8>>,
+        /// Whether `casm_definition` was compiled locally or fetched from the
+        /// feeder gateway. `None` if this is not known, ie. the class was read
+        /// back from storage and its provenance was not recorded.
+        casm_source: Option<CasmSource>,
     },
 }
 
@@ -90,6 +107,35 @@ pub(super) async fn next_missing(
     .context("Joining blocking task")?
 }
 
+/// Clears the definitions of classes whose write was started by [persist_impl]
+/// but never finished, so that [next_missing] returns their blocks again and
+/// the classes are downloaded anew. Returns the number of classes cleared.
+pub(super) async fn recover_interrupted_writes(storage: Storage) -> anyhow::Result<usize> {
+    util::task::spawn_blocking(move |_| {
+        let mut db = storage
+            .connection()
+            .context("Creating database connection")?;
+        let db = db.transaction().context("Creating database transaction")?;
+
+        let interrupted = db
+            .interrupted_class_writes()
+            .context("Querying interrupted class definition writes")?;
+        for class_hash in &interrupted {
+            tracing::warn!(%class_hash, "Class definition partially written, downloading it again");
+            db.clear_class_definition(*class_hash)
+                .context("Clearing partially written class definition")?;
+            db.finish_class_write(*class_hash)
+                .context("Clearing class write in progress marker")?;
+        }
+
+        db.commit().context("Committing database transaction")?;
+
+        Ok(interrupted.len())
+    })
+    .await
+    .context("Joining blocking task")?
+}
+
 pub(super) fn get_counts(
     db: pathfinder_storage::Transaction<'_>,
     start: BlockNumber,
@@ -99,22 +145,150 @@ pub(super) fn get_counts(
         .context("Querying declared classes counts")
 }
 
+/// Same as [get_counts] but fetches the counts for the whole batch in a single
+/// statement, and cross-checks each count against the classes declared in the
+/// block's state update. The declarations of the whole batch are counted in a
+/// single statement as well.
+pub(super) fn get_counts_checked(
+    db: pathfinder_storage::Transaction<'_>,
+    start: BlockNumber,
+    batch_size: NonZeroUsize,
+) -> anyhow::Result<VecDeque<usize>> {
+    let counts = db
+        .declared_classes_counts_batched(start, batch_size)
+        .context("Querying declared classes counts")?;
+    let Some(last) = counts.len().checked_sub(1) else {
+        return Ok(counts);
+    };
+    let declared = db
+        .declared_classes_counts_by_block(start, start + last as u64)
+        .context("Querying declared classes counts by block")?;
+
+    check_counts(start, counts, &declared)
+}
+
+/// Fails with [DeclaredClassCountMismatch] at the first block in `counts`,
+/// starting at `start`, whose count differs from the number of classes
+/// `declared` by it. Blocks missing from `declared` declare no classes.
+fn check_counts(
+    start: BlockNumber,
+    counts: VecDeque<usize>,
+    declared: &HashMap<BlockNumber, usize>,
+) -> anyhow::Result<VecDeque<usize>> {
+    for (i, &counted) in counts.iter().enumerate() {
+        let block_number = start + i as u64;
+        let declared = declared.get(&block_number).copied().unwrap_or_default();
+
+        if counted != declared {
+            return Err(DeclaredClassCountMismatch {
+                block_number,
+                counted,
+                declared,
+            }
+            .into());
+        }
+    }
+
+    Ok(counts)
+}
+
+/// The declared class count of a block disagrees with the number of classes
+/// declared in its state update.
+///
+/// The counts determine how many classes are requested from peers, so a bad
+/// count would otherwise silently truncate or extend the set of classes synced
+/// for the block.
+#[derive(Debug, PartialEq, thiserror::Error)]
+#[error(
+    "Declared class count {counted} at block {block_number} does not match the {declared} \
+     classes declared in its state update"
+)]
+pub(super) struct DeclaredClassCountMismatch {
+    pub block_number: BlockNumber,
+    pub counted: usize,
+    pub declared: usize,
+}
+
+/// The minimum number of blocks for which declared class counts are fetched
+/// at once. This also bounds the number of counts buffered in memory.
+const DECLARED_CLASS_COUNTS_WINDOW: usize = 10_000;
+
 pub(super) fn declared_class_counts_stream(
     storage: Storage,
     mut start: BlockNumber,
     stop: BlockNumber,
     batch_size: NonZeroUsize,
 ) -> impl futures::Stream<Item = anyhow::Result<usize>> {
-    storage_adapters::counts_stream(storage, start, stop, batch_size, get_counts)
+    storage_adapters::counts_stream(
+        storage,
+        start,
+        stop,
+        batch_size.max(NonZeroUsize::new(DECLARED_CLASS_COUNTS_WINDOW).expect("10_000>0")),
+        get_counts_checked,
+    )
+}
+
+/// Awaits `future` and, if it succeeds, records in `stats` that `stage`
+/// processed `items` in the time it took.
+pub(super) async fn record_stats<T, E>(
+    stats: Arc<ClassSyncStats>,
+    stage: &'static str,
+    items: usize,
+    future: impl std::future::Future<Output = Result<T, E>>,
+) -> Result<T, E> {
+    let started = std::time::Instant::now();
+    let result = future.await;
+    if result.is_ok() {
+        stats.record(stage, items, started.elapsed());
+    }
+    result
 }
 
+/// Verifies the layout of a batch of classes on the rayon pool.
+///
+/// Dropping the returned future, eg. because sync abandons the batch, cancels
+/// the verification so that classes not yet picked up by the pool are not
+/// deserialized for nothing.
 pub(super) async fn verify_layout(
-    peer_data: PeerData<P2PClassDefinition>,
-) -> Result<PeerData<ClassWithLayout>, SyncError> {
-    let PeerData { peer, data } = peer_data;
-    verify_layout_impl(&peer, data).map(|x| PeerData::new(peer, x))
+    peer_data: Vec<PeerData<P2PClassDefinition>>,
+) -> Result<Vec<PeerData<ClassWithLayout>>, SyncError> {
+    let cancellation_token = CancellationToken::new();
+    let _guard = cancellation_token.clone().drop_guard();
+    let (tx, rx) = oneshot::channel();
+    rayon::spawn(move || {
+        let result = map_cancellable(peer_data, &cancellation_token, |PeerData { peer, data }| {
+            verify_layout_impl(&peer, data).map(|x| PeerData::new(peer, x))
+        });
+        if let Some(result) = result {
+            _ = tx.send(result);
+        }
+    });
+    // The verification is only cancelled once this future is dropped, so the
+    // sender is only dropped early if the rayon task panicked.
+    rx.await.context("Panic on rayon thread")?
+}
+
+/// Applies `f` to `items` in parallel, checking `cancellation_token` before
+/// each item. Returns `None` if cancelled.
+fn map_cancellable<T: Send, U: Send>(
+    items: Vec<T>,
+    cancellation_token: &CancellationToken,
+    f: impl Fn(T) -> Result<U, SyncError> + Sync + Send,
+) -> Option<Result<Vec<U>, SyncError>> {
+    items
+        .into_par_iter()
+        .map(|item| {
+            if cancellation_token.is_cancelled() {
+                return None;
+            }
+            Some(f(item))
+        })
+        .collect()
 }
 
+// Sync uses `verify_layout` or the fused stage, this one is left for comparing
+// the two in the benchmark.
+#[cfg_attr(not(any(test, feature = "bench")), allow(dead_code))]
 pub struct VerifyLayout;
 
 impl ProcessStage for VerifyLayout {
@@ -131,10 +305,119 @@ impl ProcessStage for VerifyLayout {
     }
 }
 
+/// Runs `definition` through [VerifyLayout] as if a peer had sent it as either a
+/// Cairo 0 or a Sierra class definition. Returns whether the layout was
+/// accepted.
+///
+/// Meant for fuzzing the layout verification with untrusted bytes.
+///
+/// ### Panics
+///
+/// If the layout is rejected with any error other than
+/// [SyncError::BadClassLayout].
+#[cfg(any(test, feature = "fuzzing"))]
+pub fn fuzz_verify_layout(definition: Vec<u8>, sierra: bool) -> bool {
+    let block_number = BlockNumber::GENESIS;
+    let class = if sierra {
+        P2PClassDefinition::Sierra {
+            block_number,
+            sierra_definition: definition,
+            hash: SierraHash::ZERO,
+        }
+    } else {
+        P2PClassDefinition::Cairo {
+            block_number,
+            definition,
+            hash: ClassHash::ZERO,
+        }
+    };
+
+    match verify_layout_impl(&PeerId::random(), class) {
+        Ok(_) => true,
+        Err(SyncError::BadClassLayout(_)) => false,
+        Err(error) => panic!("Unexpected layout verification error: {error}"),
+    }
+}
+
+/// Maximum nesting depth of arrays and objects accepted in a class definition.
+///
+/// Real classes stay well below this, but the Cairo 0 `program` is kept as a raw
+/// JSON value and only fully parsed later (eg. when computing the class hash),
+/// so a pathologically nested definition must be rejected up front.
+const CLASS_DEFINITION_MAX_NESTING_DEPTH: usize = 64;
+
+/// Checks that `definition` is valid UTF-8 and that its arrays and objects are
+/// not nested deeper than [CLASS_DEFINITION_MAX_NESTING_DEPTH].
+///
+/// This does not recurse, so it is safe to run on arbitrary input. It does
+/// not validate the JSON syntax, that is left to the actual deserialization.
+fn check_json_structure(definition: &[u8]) -> Result<(), &'static str> {
+    std::str::from_utf8(definition).map_err(|_| "invalid UTF-8")?;
+
+    let mut depth = 0usize;
+    let mut in_string = false;
+    let mut escaped = false;
+
+    for &byte in definition {
+        if in_string {
+            match byte {
+                _ if escaped => escaped = false,
+                b'\\' => escaped = true,
+                b'"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+
+        match byte {
+            b'"' => in_string = true,
+            b'[' | b'{' => {
+                depth += 1;
+                if depth > CLASS_DEFINITION_MAX_NESTING_DEPTH {
+                    return Err("nesting too deep");
+                }
+            }
+            b']' | b'}' => depth = depth.saturating_sub(1),
+            _ => {}
+        }
+    }
+
+    Ok(())
+}
+
 fn verify_layout_impl(
     peer: &PeerId,
     def: P2PClassDefinition,
 ) -> Result<ClassWithLayout, SyncError> {
+    // Definitions received via p2p are already capped when decompressing, but don't
+    // rely on that for bytes coming from an untrusted peer.
+    let (block_number, size) = match &def {
+        P2PClassDefinition::Cairo {
+            block_number,
+            definition,
+            ..
+        } => (*block_number, definition.len()),
+        P2PClassDefinition::Sierra {
+            block_number,
+            sierra_definition,
+            ..
+        } => (*block_number, sierra_definition.len()),
+    };
+    if size as u64 > CLASS_DEFINITION_MAX_ALLOWED_SIZE {
+        tracing::debug!(%peer, %block_number, %size, "Class definition too large");
+        return Err(SyncError::BadClassLayout(*peer));
+    }
+    let definition = match &def {
+        P2PClassDefinition::Cairo { definition, .. } => definition,
+        P2PClassDefinition::Sierra {
+            sierra_definition, ..
+        } => sierra_definition,
+    };
+    if let Err(reason) = check_json_structure(definition) {
+        tracing::debug!(%peer, %block_number, %reason, "Bad class layout");
+        return Err(SyncError::BadClassLayout(*peer));
+    }
+
     match def {
         P2PClassDefinition::Cairo {
             block_number,
@@ -175,6 +458,8 @@ fn verify_layout_impl(
     }
 }
 
+// Only constructed by the benchmark, see `VerifyLayout`.
+#[cfg_attr(not(any(test, feature = "bench")), allow(dead_code))]
 pub struct VerifyHash;
 
 impl ProcessStage for VerifyHash {
@@ -194,22 +479,117 @@ impl ProcessStage for VerifyHash {
     }
 }
 
+/// Does the work of [VerifyLayout] followed by [VerifyHash] in a single pass,
+/// so that each class is deserialized and hashed by the same rayon task and the
+/// intermediate [ClassWithLayout] batch is never collected.
+pub struct VerifyLayoutAndHash;
+
+impl ProcessStage for VerifyLayoutAndHash {
+    const NAME: &'static str = "Class::VerifyLayoutAndHash";
+
+    type Input = Vec<P2PClassDefinition>;
+    type Output = Vec<Class>;
+
+    fn map(&mut self, peer: &PeerId, input: Self::Input) -> Result<Self::Output, SyncError> {
+        input
+            .into_par_iter()
+            .map(|class| verify_layout_impl(peer, class).and_then(|c| verify_hash_impl(peer, c)))
+            .collect()
+    }
+}
+
+/// Verifies the layout and hash of `classes` either with [VerifyLayoutAndHash]
+/// or with [VerifyLayout] followed by [VerifyHash]. Returns the number of
+/// verified classes.
+///
+/// Meant for benchmarking the two approaches against each other.
+///
+/// ### Panics
+///
+/// If any of the classes fails verification.
+#[cfg(feature = "bench")]
+pub fn bench_verify_classes(classes: Vec<P2PClassDefinition>, fused: bool) -> usize {
+    let peer = PeerId::random();
+    let verified = if fused {
+        VerifyLayoutAndHash.map(&peer, classes)
+    } else {
+        VerifyLayout
+            .map(&peer, classes)
+            .and_then(|classes| VerifyHash.map(&peer, classes))
+    };
+
+    verified.expect("Classes to be valid").len()
+}
+
+/// Classes of a batch which were processed successfully before processing
+/// another class of the same batch failed.
+#[derive(Debug)]
+pub(super) struct PartialBatch<T> {
+    /// The classes preceding the one that failed, in their original order.
+    pub processed: Vec<T>,
+    pub error: SyncError,
+}
+
+impl<T> From<SyncError> for PartialBatch<T> {
+    fn from(error: SyncError) -> Self {
+        Self {
+            processed: Vec::new(),
+            error,
+        }
+    }
+}
+
+impl<T> From<futures::stream::TryChunksError<T, SyncError>> for PartialBatch<T> {
+    fn from(error: futures::stream::TryChunksError<T, SyncError>) -> Self {
+        Self {
+            processed: error.0,
+            error: error.1,
+        }
+    }
+}
+
+/// Passes on the successfully processed part of a failed batch ahead of its
+/// error, so that it can still be stored and need not be requested again.
+pub(super) fn flatten_partial_batches<T>(
+    batches: impl futures::Stream<Item = Result<Vec<T>, PartialBatch<T>>>,
+) -> impl futures::Stream<Item = Result<Vec<T>, SyncError>> {
+    batches.flat_map(|batch| {
+        let items = match batch {
+            Ok(processed) => vec![Ok(processed)],
+            Err(PartialBatch { processed, error }) if processed.is_empty() => vec![Err(error)],
+            Err(PartialBatch { processed, error }) => vec![Ok(processed), Err(error)],
+        };
+        futures::stream::iter(items)
+    })
+}
+
+/// On failure the classes preceding the first bad one are returned as part of
+/// the [PartialBatch].
 pub(super) async fn verify_hash(
     peer_data: Vec<PeerData<ClassWithLayout>>,
-) -> Result<Vec<PeerData<Class>>, SyncError> {
+) -> Result<Vec<PeerData<Class>>, PartialBatch<PeerData<Class>>> {
     use rayon::prelude::*;
     let (tx, rx) = oneshot::channel();
     rayon::spawn(move || {
-        let res = peer_data
+        let results = peer_data
             .into_par_iter()
             .map(|PeerData { peer, data }| {
                 let compiled = verify_hash_impl(&peer, data)?;
                 Ok(PeerData::new(peer, compiled))
             })
-            .collect::<Result<Vec<PeerData<Class>>, SyncError>>();
-        tx.send(res);
+            .collect::<Vec<Result<PeerData<Class>, SyncError>>>();
+        tx.send(results);
     });
-    rx.await.expect("Sender not to be dropped")
+    let results = rx.await.expect("Sender not to be dropped");
+
+    let mut processed = Vec::with_capacity(results.len());
+    for result in results {
+        match result {
+            Ok(class) => processed.push(class),
+            Err(error) => return Err(PartialBatch { processed, error }),
+        }
+    }
+    Ok(processed)
 }
 
 fn verify_hash_impl(peer: &PeerId, input: ClassWithLayout) -> Result<Class, SyncError> {
@@ -252,6 +632,23 @@ fn verify_hash_impl(peer: &PeerId, input: ClassWithLayout) -> Result<Class, Sync
     }
 }
 
+/// Takes the expected hash of a class for granted. Only ever applied to classes
+/// sourced from a trusted feeder gateway, never to classes received from peers.
+fn trust_hash_impl(input: ClassWithLayout) -> Class {
+    let ClassWithLayout {
+        block_number,
+        definition,
+        layout: _,
+        hash,
+    } = input;
+
+    Class {
+        block_number,
+        definition,
+        hash,
+    }
+}
+
 /// This function makes sure that the classes we receive from other peers are
 /// really declared at the expected blocks.
 ///
@@ -259,15 +656,25 @@ fn verify_hash_impl(peer: &PeerId, input: ClassWithLayout) -> Result<Class, Sync
 ///
 /// This function ingests two streams:
 /// - `expected_declarations` which is a stream of expected class declarations
-///   at each block,
+///   at each block, grouped by [coalesce_declarations],
 /// - `classes` which is a stream of chunked class definitions as received from
 ///   other peers,
 ///
-/// producing a stream of class definitions that we are sure are declared at the
-/// expected blocks.
+/// producing a stream of batches of class definitions that we are sure are
+/// declared at the expected blocks. Membership is checked per block, but the
+/// classes of a whole group are yielded as one batch so that they can be
+/// persisted in a single database transaction.
 ///
 /// Any mismatch between the expected and received class definitions will result
-/// in an error and termination of the resulting stream.
+/// in an error and termination of the resulting stream. The classes of the group
+/// preceding the bad one are returned as part of the [PartialBatch].
+///
+/// No further classes are pulled from `classes` while under `memory_pressure`.
+/// Up to `buffer` verified batches are yielded ahead of the consumer.
+///
+/// If `classes` yields nothing for `stall_timeout` while more classes are
+/// expected, the stream terminates with [SyncError::ClassStreamStalled] so
+/// that sync can restart with other peers instead of waiting forever.
 ///
 /// ### Important
 ///
@@ -278,82 +685,119 @@ fn verify_hash_impl(peer: &PeerId, input: ClassWithLayout) -> Result<Class, Sync
 pub(super) fn verify_declared_at(
     mut expected_declarations: BoxStream<
         'static,
-        anyhow::Result<(BlockNumber, HashSet<ClassHash>)>,
+        anyhow::Result<Vec<(BlockNumber, HashSet<ClassHash>)>>,
     >,
     mut classes: BoxStream<'static, Result<Vec<PeerData<Class>>, SyncError>>,
-) -> impl futures::Stream<Item = Result<PeerData<Class>, SyncError>> {
-    util::make_stream::from_future(move |tx| async move {
-        let mut dechunker = ClassDechunker::new();
+    memory_pressure: MemoryPressure,
+    buffer: NonZeroUsize,
+    stall_timeout: Duration,
+) -> impl futures::Stream<Item = Result<Vec<PeerData<Class>>, PartialBatch<PeerData<Class>>>> {
+    util::make_stream::from_future_with_capacity(buffer, move |tx| async move {
+        let mut dechunker = ClassDechunker::new(memory_pressure, stall_timeout);
 
-        while let Some(expected) = expected_declarations.next().await {
-            let (declared_at, mut declared) = match expected {
+        while let Some(group) = expected_declarations.next().await {
+            let group = match group {
                 Ok(x) => x,
                 Err(e) => {
-                    _ = tx.send(Err(e.into()));
+                    _ = tx.send(Err(SyncError::from(e).into())).await;
                     return;
                 }
             };
 
-            loop {
-                // even if `expected_declarations` skips empty blocks the current set can still
-                // be empty because it has just been exhausted and we need to fetch the
-                // expectations for the next block.
-                if declared.is_empty() {
-                    break;
-                }
-
-                let Some(maybe_class) = dechunker.next(&mut classes).await else {
-                    // `classes` stream has terminated
-                    return;
-                };
+            let mut batch = Vec::new();
 
-                let res = maybe_class.and_then(|PeerData { peer, data: class }| {
-                    // Check if the class is declared at the expected block
-                    if declared_at != class.block_number {
-                        tracing::debug!(%peer, expected_block_number=%declared_at, block_number=%class.block_number, %class.hash, "Unexpected class definition");
-                        return Err(SyncError::UnexpectedClass(peer));
-                    }
+            for (declared_at, mut declared) in group {
+                while !declared.is_empty() {
+                    let Some(maybe_class) = dechunker.next(&mut classes).await else {
+                        // `classes` stream has terminated, pass on what we have so far
+                        if !batch.is_empty() {
+                            _ = tx.send(Ok(batch)).await;
+                        }
+                        return;
+                    };
 
-                    if declared.remove(&class.hash) {
-                        Ok(PeerData::new(peer, class))
-                    } else {
-                        tracing::debug!(%peer, block_number=%class.block_number, %class.hash, "Unexpected class definition");
-                        Err(SyncError::UnexpectedClass(peer))
+                    match maybe_class.and_then(|x| check_declared_at(declared_at, &mut declared, x))
+                    {
+                        Ok(class) => batch.push(class),
+                        Err(error) => {
+                            _ = tx
+                                .send(Err(PartialBatch {
+                                    processed: batch,
+                                    error,
+                                }))
+                                .await;
+                            return;
+                        }
                     }
-                });
-                let bail = res.is_err();
-                // Send the result to the next stage
-                tx.send(res).await.expect("Receiver not to be dropped");
-                // Short-circuit on error
-                if bail {
-                    return;
                 }
             }
+
+            if !batch.is_empty() && tx.send(Ok(batch)).await.is_err() {
+                return;
+            }
         }
     })
 }
 
-struct ClassDechunker(VecDeque<PeerData<Class>>);
+/// Checks that `class` is one of the classes `declared` at block `declared_at`
+/// and removes it from the set.
+fn check_declared_at(
+    declared_at: BlockNumber,
+    declared: &mut HashSet<ClassHash>,
+    PeerData { peer, data: class }: PeerData<Class>,
+) -> Result<PeerData<Class>, SyncError> {
+    // Check if the class is declared at the expected block
+    if declared_at != class.block_number {
+        tracing::debug!(%peer, expected_block_number=%declared_at, block_number=%class.block_number, %class.hash, "Unexpected class definition");
+        return Err(SyncError::UnexpectedClass(peer));
+    }
+
+    if declared.remove(&class.hash) {
+        Ok(PeerData::new(peer, class))
+    } else {
+        tracing::debug!(%peer, block_number=%class.block_number, %class.hash, "Unexpected class definition");
+        Err(SyncError::UnexpectedClass(peer))
+    }
+}
+
+struct ClassDechunker {
+    buffer: VecDeque<PeerData<Class>>,
+    memory_pressure: MemoryPressure,
+    stall_timeout: Duration,
+}
 
 impl ClassDechunker {
-    fn new() -> Self {
-        Self(Default::default())
+    fn new(memory_pressure: MemoryPressure, stall_timeout: Duration) -> Self {
+        Self {
+            buffer: Default::default(),
+            memory_pressure,
+            stall_timeout,
+        }
     }
 
     /// Caller must guarantee: chunks in `classes` are never empty.
+    ///
+    /// No new chunk is pulled from `classes` while under memory pressure. Fails
+    /// with [SyncError::ClassStreamStalled] if no chunk arrives within the stall
+    /// timeout, time spent under memory pressure does not count.
     async fn next(
         &mut self,
         classes: &mut BoxStream<'static, Result<Vec<PeerData<Class>>, SyncError>>,
     ) -> Option<Result<PeerData<Class>, SyncError>> {
-        if self.0.is_empty() {
-            classes.next().await.map(|x| {
+        if self.buffer.is_empty() {
+            self.memory_pressure.relieved().await;
+            let Ok(chunk) = tokio::time::timeout(self.stall_timeout, classes.next()).await else {
+                tracing::debug!(timeout=?self.stall_timeout, "No class definitions received");
+                return Some(Err(SyncError::ClassStreamStalled));
+            };
+            chunk.map(|x| {
                 x.map(|chunk| {
-                    self.0.extend(chunk);
-                    self.0.pop_front().expect("Chunk not to be empty")
+                    self.buffer.extend(chunk);
+                    self.buffer.pop_front().expect("Chunk not to be empty")
                 })
             })
         } else {
-            self.0.pop_front().map(Ok)
+            self.buffer.pop_front().map(Ok)
         }
     }
 }
@@ -364,14 +808,44 @@ pub struct ExpectedDeclarations {
     pub classes: HashSet<ClassHash>,
 }
 
+/// The same class was returned more than once for a single block when querying
+/// for declared classes.
+///
+/// Declarations are collected into sets, so a duplicate would otherwise be
+/// silently swallowed and throw off the count of classes expected per block.
+#[derive(Debug, PartialEq, thiserror::Error)]
+#[error("Class {class_hash} declared more than once at block {block_number}")]
+pub(super) struct DuplicateDeclaration {
+    pub block_number: BlockNumber,
+    pub class_hash: ClassHash,
+}
+
 /// Returns a stream of sets of class hashes declared at each block in the range
-/// `start..=stop`.
+/// `start..=stop`. Up to `buffer` blocks are read ahead of the consumer.
 pub(super) fn expected_declarations_stream(
+    storage: Storage,
+    start: BlockNumber,
+    stop: BlockNumber,
+    buffer: NonZeroUsize,
+) -> impl futures::Stream<Item = anyhow::Result<(BlockNumber, HashSet<ClassHash>)>> {
+    expected_declarations_stream_impl(storage, start, stop, buffer, |db, block_number| {
+        db.declared_classes_at(block_number.into())
+    })
+}
+
+fn expected_declarations_stream_impl(
     storage: Storage,
     mut start: BlockNumber,
     stop: BlockNumber,
+    buffer: NonZeroUsize,
+    declared_classes_at: impl Fn(
+            &Transaction<'_>,
+            BlockNumber,
+        ) -> anyhow::Result<Option<Vec<ClassHash>>>
+        + Send
+        + 'static,
 ) -> impl futures::Stream<Item = anyhow::Result<(BlockNumber, HashSet<ClassHash>)>> {
-    util::make_stream::from_blocking(move |cancellation_token, tx| {
+    util::make_stream::from_blocking_with_capacity(buffer, move |cancellation_token, tx| {
         let mut db = match storage.connection().context("Creating database connection") {
             Ok(x) => x,
             Err(e) => {
@@ -392,11 +866,11 @@ pub(super) fn expected_declarations_stream(
                     return;
                 }
             };
-            let res = db
-                .declared_classes_at(start.into())
+            let res = declared_classes_at(&db, start)
                 .context("Querying declared classes at block")
                 .and_then(|x| x.context("Block header not found"))
-                .map(|x| (start, x.into_iter().collect::<HashSet<_>>()));
+                .and_then(|x| distinct_declarations(start, x))
+                .map(|x| (start, x));
             drop(db);
             let is_err = res.is_err();
             let is_empty = res.as_ref().map(|(_, x)| x.is_empty()).unwrap_or(false);
@@ -412,93 +886,154 @@ pub(super) fn expected_declarations_stream(
     })
 }
 
-pub struct CompileSierraToCasm<T> {
-    fgw: T,
-    tokio_handle: tokio::runtime::Handle,
-}
-
-impl<T> CompileSierraToCasm<T> {
-    pub fn new(fgw: T, tokio_handle: tokio::runtime::Handle) -> Self {
-        Self { fgw, tokio_handle }
+fn distinct_declarations(
+    block_number: BlockNumber,
+    declared: Vec<ClassHash>,
+) -> anyhow::Result<HashSet<ClassHash>> {
+    let mut classes = HashSet::with_capacity(declared.len());
+    for class_hash in declared {
+        if !classes.insert(class_hash) {
+            return Err(DuplicateDeclaration {
+                block_number,
+                class_hash,
+            }
+            .into());
+        }
     }
+    Ok(classes)
 }
 
-impl<T: GatewayApi + Clone + Send + 'static> ProcessStage for CompileSierraToCasm<T> {
-    const NAME: &'static str = "Class::CompileSierraToCasm";
+/// Groups the declarations of adjacent blocks until each group holds at least
+/// `max_classes` classes, the last group may hold fewer. Blocks are never split
+/// across groups, so the last block of a group is also the tail of all classes
+/// declared in it.
+pub(super) fn coalesce_declarations<S>(
+    expected_declarations: S,
+    max_classes: NonZeroUsize,
+) -> impl futures::Stream<Item = anyhow::Result<Vec<(BlockNumber, HashSet<ClassHash>)>>>
+where
+    S: futures::Stream<Item = anyhow::Result<(BlockNumber, HashSet<ClassHash>)>> + Send + 'static,
+{
+    util::make_stream::from_future(move |tx| async move {
+        let mut expected_declarations = std::pin::pin!(expected_declarations);
+        let mut group = Vec::new();
+        let mut num_classes = 0;
 
-    type Input = Vec<Class>;
-    type Output = Vec<CompiledClass>;
+        while let Some(declarations) = expected_declarations.next().await {
+            let declarations = match declarations {
+                Ok(x) => x,
+                Err(e) => {
+                    if !group.is_empty() && tx.send(Ok(group)).await.is_err() {
+                        return;
+                    }
+                    _ = tx.send(Err(e)).await;
+                    return;
+                }
+            };
 
-    fn map(&mut self, _: &PeerId, input: Self::Input) -> Result<Self::Output, SyncError> {
-        input
-            .into_par_iter()
-            .map(|class| {
-                let compiled = compile_or_fetch_impl(class, &self.fgw, &self.tokio_handle)?;
-                Ok(compiled)
-            })
-            .collect::<Result<Vec<CompiledClass>, SyncError>>()
-    }
-}
+            num_classes += declarations.1.len();
+            group.push(declarations);
 
-pub(super) async fn compile_sierra_to_casm_or_fetch<
-    SequencerClient: GatewayApi + Clone + Send + 'static,
->(
-    peer_data: Vec<PeerData<Class>>,
-    fgw: SequencerClient,
-    tokio_handle: tokio::runtime::Handle,
-) -> Result<Vec<PeerData<CompiledClass>>, SyncError> {
-    use rayon::prelude::*;
-    let (tx, rx) = oneshot::channel();
-    rayon::spawn(move || {
-        let res = peer_data
-            .into_par_iter()
-            .map(|x| {
-                let PeerData { peer, data } = x;
-                let compiled = compile_or_fetch_impl(data, &fgw, &tokio_handle)?;
-                Ok(PeerData::new(peer, compiled))
-            })
-            .collect::<Result<Vec<PeerData<CompiledClass>>, SyncError>>();
-        tx.send(res);
-    });
-    rx.await.expect("Sender not to be dropped")
-}
+            if num_classes >= max_classes.get() {
+                num_classes = 0;
+                if tx.send(Ok(std::mem::take(&mut group))).await.is_err() {
+                    return;
+                }
+            }
+        }
 
-fn compile_or_fetch_impl<SequencerClient: GatewayApi + Clone + Send + 'static>(
-    class: Class,
-    fgw: &SequencerClient,
-    tokio_handle: &tokio::runtime::Handle,
-) -> Result<CompiledClass, SyncError> {
-    let Class {
-        block_number,
-        hash,
-        definition,
-    } = class;
+        if !group.is_empty() {
+            _ = tx.send(Ok(group)).await;
+        }
+    })
+}
 
-    let definition = match definition {
-        ClassDefinition::Cairo(c) => CompiledClassDefinition::Cairo(c),
-        ClassDefinition::Sierra(sierra_definition) => {
-            let casm_definition = pathfinder_compiler::compile_to_casm(&sierra_definition)
-                .context("Compiling Sierra class");
+/// Returns a stream of all stored class definitions, in the order of the blocks
+/// they were declared in, starting at block `start`.
+///
+/// Classes are read from storage one at a time so memory usage does not depend
+/// on the size of the class set. An interrupted export can be resumed by
+/// restarting it from the block following the last fully exported block.
+pub fn export_stream(
+    storage: Storage,
+    mut start: BlockNumber,
+) -> impl futures::Stream<Item = anyhow::Result<CompiledClass>> {
+    util::make_stream::from_blocking(move |cancellation_token, tx| {
+        let mut db = match storage.connection().context("Creating database connection") {
+            Ok(x) => x,
+            Err(e) => {
+                _ = tx.blocking_send(Err(e));
+                return;
+            }
+        };
+
+        loop {
+            if cancellation_token.is_cancelled() {
+                return;
+            }
 
-            let casm_definition = match casm_definition {
+            let db = match db.transaction().context("Creating database transaction") {
                 Ok(x) => x,
-                // Feeder gateway request errors are recoverable at this point because we know
-                // that the class is declared and exists so if the gateway responds with an
-                // error we should restart the sync and retry later.
-                Err(_) => tokio_handle
-                    .block_on(fgw.pending_casm_by_hash(hash))
-                    .map_err(|error| {
-                        tracing::debug!(%block_number, class_hash=%hash, %error, "Fetching casm from feeder gateway failed");
-                        SyncError::FetchingCasmFailed
-                    })?
-                    .to_vec(),
+                Err(e) => {
+                    _ = tx.blocking_send(Err(e));
+                    return;
+                }
+            };
+            let mut declared = match db
+                .declared_classes_at(start.into())
+                .context("Querying declared classes at block")
+            {
+                Ok(Some(x)) => x,
+                // Past the end of the chain.
+                Ok(None) => return,
+                Err(e) => {
+                    _ = tx.blocking_send(Err(e));
+                    return;
+                }
             };
+            declared.sort();
 
-            CompiledClassDefinition::Sierra {
-                sierra_definition,
-                casm_definition,
+            for hash in declared {
+                if cancellation_token.is_cancelled() {
+                    return;
+                }
+
+                let class = exported_class(&db, start, hash);
+                let is_err = class.is_err();
+                if tx.blocking_send(class).is_err() || is_err {
+                    return;
+                }
             }
+
+            start += 1;
         }
+    })
+}
+
+fn exported_class(
+    db: &Transaction<'_>,
+    block_number: BlockNumber,
+    hash: ClassHash,
+) -> anyhow::Result<CompiledClass> {
+    let definition = db
+        .class_definition(hash)
+        .context("Querying class definition")?
+        .with_context(|| format!("Class definition missing: {hash}"))?;
+
+    // Only Sierra classes have a casm hash, their CASM definition may have been
+    // pruned.
+    let definition = match db.casm_hash(hash).context("Querying casm hash")? {
+        Some(_) => CompiledClassDefinition::Sierra {
+            sierra_definition: definition,
+            casm_definition: db
+                .casm_definition(hash)
+                .context("Querying casm definition")?,
+            casm_source: db
+                .casm_provenance(hash)
+                .context("Querying casm provenance")?
+                .map(|x| x.source),
+        },
+        None => CompiledClassDefinition::Cairo(definition),
     };
 
     Ok(CompiledClass {
@@ -508,148 +1043,3162 @@ fn compile_or_fetch_impl<SequencerClient: GatewayApi + Clone + Send + 'static>(
     })
 }
 
-pub struct Store(pub pathfinder_storage::Connection);
+/// How often local compilation of a Sierra class is retried after the
+/// compiler ran out of resources, before its CASM is fetched from the feeder
+/// gateway instead. Other compilation errors are never retried.
+#[derive(Copy, Clone, Debug)]
+pub struct CompileRetries {
+    pub max_retries: usize,
+    /// Delay before each retry, extended by a random jitter of up to the same
+    /// duration.
+    pub delay: std::time::Duration,
+}
 
-impl ProcessStage for Store {
-    const NAME: &'static str = "Class::Persist";
+impl Default for CompileRetries {
+    fn default() -> Self {
+        Self {
+            max_retries: 2,
+            delay: std::time::Duration::from_millis(100),
+        }
+    }
+}
 
-    type Input = CompiledClass;
-    type Output = BlockNumber;
+/// Compiles Sierra class definitions to CASM.
+pub trait CasmCompiler: Send + Sync {
+    fn compile(&self, sierra_definition: &[u8]) -> anyhow::Result<Vec<u8>>;
+}
 
-    fn map(&mut self, _: &PeerId, input: Self::Input) -> Result<Self::Output, SyncError> {
-        let CompiledClass {
-            block_number,
-            hash,
-            definition,
-        } = input;
+/// Compiles in-process using [pathfinder_compiler::compile_to_casm].
+pub struct EmbeddedCompiler;
 
-        let db = self
-            .0
-            .transaction()
-            .context("Creating database transaction")?;
+impl CasmCompiler for EmbeddedCompiler {
+    fn compile(&self, sierra_definition: &[u8]) -> anyhow::Result<Vec<u8>> {
+        pathfinder_compiler::compile_to_casm(sierra_definition)
+    }
+}
+
+/// Compiles in a separate process, so that a memory hungry compilation cannot
+/// take down the node. `command` is run with the Sierra definition on stdin and
+/// is expected to write the CASM definition to stdout.
+pub struct ProcessCompiler {
+    command: PathBuf,
+}
 
-        persist_impl(&db, hash, definition)?;
+impl ProcessCompiler {
+    pub fn new(command: PathBuf) -> Self {
+        Self { command }
+    }
+}
 
-        db.commit().context("Committing db transaction")?;
+impl CasmCompiler for ProcessCompiler {
+    fn compile(&self, sierra_definition: &[u8]) -> anyhow::Result<Vec<u8>> {
+        use std::io::Write;
+        use std::process::{Command, Stdio};
 
-        Ok(block_number)
+        let mut child = Command::new(&self.command)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .with_context(|| format!("Spawning compiler {}", self.command.display()))?;
+
+        // Written from another thread, the compiler might produce output before it
+        // has read all of its input.
+        let mut stdin = child.stdin.take().context("Opening compiler stdin")?;
+        let sierra_definition = sierra_definition.to_vec();
+        let writer = thread::spawn(move || stdin.write_all(&sierra_definition));
+
+        let output = child
+            .wait_with_output()
+            .context("Waiting for compiler to finish")?;
+        anyhow::ensure!(
+            output.status.success(),
+            "Compiler exited with {}: {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr)
+        );
+        writer
+            .join()
+            .expect("Writing to compiler not to panic")
+            .context("Writing Sierra definition to compiler")?;
+
+        Ok(output.stdout)
     }
 }
 
-pub(super) async fn persist(
-    storage: Storage,
-    classes: Vec<PeerData<CompiledClass>>,
-) -> Result<BlockNumber, SyncError> {
-    util::task::spawn_blocking(move |_| {
-        let mut db = storage
-            .connection()
-            .context("Creating database connection")?;
-        let tail = classes
-            .last()
-            .map(|x| x.data.block_number)
-            .context("No class definitions to persist")?;
+/// Compiles Sierra classes larger than `threshold` bytes with `large` and all
+/// other classes with `small`. Compiling big classes is what uses the most
+/// memory, so this allows moving only those out of process while small classes
+/// are still compiled in-process without the overhead.
+#[derive(Clone)]
+pub struct SizeBasedCompiler {
+    small: Arc<dyn CasmCompiler>,
+    large: Arc<dyn CasmCompiler>,
+    threshold: usize,
+}
 
-        for CompiledClass {
-            block_number: _,
-            definition,
-            hash,
-        } in classes.into_iter().map(|x| x.data)
-        {
-            let db = db.transaction().context("Creating database transaction")?;
-            persist_impl(&db, hash, definition)?;
-            db.commit().context("Committing db transaction")?;
+/// Compiles all classes in-process.
+impl Default for SizeBasedCompiler {
+    fn default() -> Self {
+        Self::new(
+            Arc::new(EmbeddedCompiler),
+            Arc::new(EmbeddedCompiler),
+            usize::MAX,
+        )
+    }
+}
+
+impl SizeBasedCompiler {
+    pub fn new(
+        small: Arc<dyn CasmCompiler>,
+        large: Arc<dyn CasmCompiler>,
+        threshold: usize,
+    ) -> Self {
+        Self {
+            small,
+            large,
+            threshold,
         }
+    }
 
-        Ok(tail)
-    })
-    .await
-    .context("Joining blocking task")?
+    fn select(&self, sierra_definition: &[u8]) -> &dyn CasmCompiler {
+        if sierra_definition.len() > self.threshold {
+            self.large.as_ref()
+        } else {
+            self.small.as_ref()
+        }
+    }
 }
 
-fn persist_impl(
-    db: &Transaction<'_>,
-    hash: ClassHash,
-    definition: CompiledClassDefinition,
-) -> anyhow::Result<()> {
-    match definition {
-        CompiledClassDefinition::Cairo(definition) => {
-            db.update_cairo_class(hash, &definition)
-                .context("Updating cairo class definition")?;
+impl CasmCompiler for SizeBasedCompiler {
+    fn compile(&self, sierra_definition: &[u8]) -> anyhow::Result<Vec<u8>> {
+        self.select(sierra_definition).compile(sierra_definition)
+    }
+}
+
+/// Guards the feeder gateway requests made for classes that can't be compiled
+/// locally, so that class sync doesn't keep hammering a gateway that is down.
+///
+/// After `threshold` consecutive failures the breaker opens and requests are
+/// skipped for `cooldown`, failing the classes which need them so that sync
+/// retries later. After that a single trial request is let through: success
+/// closes the breaker again, failure re-opens it for another cooldown.
+///
+/// A single breaker is shared by all class sync stages.
+#[derive(Clone)]
+pub struct FgwCircuitBreaker {
+    state: Arc<std::sync::Mutex<CircuitBreakerState>>,
+    threshold: u32,
+    cooldown: std::time::Duration,
+}
+
+#[derive(Default)]
+struct CircuitBreakerState {
+    consecutive_failures: u32,
+    open_until: Option<std::time::Instant>,
+    trial_in_flight: bool,
+}
+
+impl FgwCircuitBreaker {
+    pub fn new(threshold: u32, cooldown: std::time::Duration) -> Self {
+        Self {
+            state: Default::default(),
+            threshold,
+            cooldown,
         }
-        CompiledClassDefinition::Sierra {
-            sierra_definition,
-            casm_definition,
-        } => {
-            let casm_hash = db
-                .casm_hash(hash)
-                .context("Getting casm hash for sierra class")?
-                .context("Casm hash not found")?;
+    }
 
-            db.update_sierra_class(
-                &SierraHash(hash.0),
-                &sierra_definition,
-                &casm_hash,
-                &casm_definition,
-            )
-            .context("Updating sierra class definition")?;
+    /// Returns whether a request may be made now.
+    fn try_acquire(&self) -> bool {
+        let mut state = self.state.lock().unwrap();
+
+        let Some(open_until) = state.open_until else {
+            return true;
+        };
+        if std::time::Instant::now() < open_until {
+            return false;
         }
+
+        // Half-open, only a single trial request at a time.
+        if state.trial_in_flight {
+            return false;
+        }
+        state.trial_in_flight = true;
+        true
     }
 
-    Ok(())
+    fn record(&self, success: bool) {
+        let mut state = self.state.lock().unwrap();
+
+        if success {
+            *state = CircuitBreakerState::default();
+            return;
+        }
+
+        state.consecutive_failures += 1;
+        if state.trial_in_flight || state.consecutive_failures >= self.threshold {
+            tracing::warn!(
+                failures=%state.consecutive_failures, cooldown=?self.cooldown,
+                "Feeder gateway keeps failing, pausing casm requests"
+            );
+            metrics::increment_counter!("class_sync_fgw_circuit_breaker_opened_total");
+            state.open_until = Some(std::time::Instant::now() + self.cooldown);
+            state.trial_in_flight = false;
+        }
+    }
 }
 
-pub struct VerifyClassHashes {
-    pub declarations: BoxStream<'static, DeclaredClasses>,
-    pub tokio_handle: tokio::runtime::Handle,
+/// Configuration of class definition sync. The defaults match those of the
+/// command line options.
+#[derive(Clone)]
+pub struct ClassSyncConfig {
+    /// Record whether each Sierra class' CASM was compiled locally or fetched
+    /// from the feeder gateway.
+    pub record_casm_provenance: bool,
+    /// Store the CASM definitions of Sierra classes. Without them CASM is
+    /// recompiled from the Sierra definition when needed.
+    pub store_casm_definitions: bool,
+    pub casm_compiler: SizeBasedCompiler,
+    pub compile_retries: CompileRetries,
+    /// Shared by all class sync stages fetching CASM from the feeder gateway.
+    pub fgw_breaker: FgwCircuitBreaker,
+    /// Class sync stops pulling new work while memory usage is high.
+    pub memory_pressure: MemoryPressure,
+    /// How many items each class sync stage may buffer ahead of the next one.
+    pub pipeline_buffer: NonZeroUsize,
+    /// How many downloaded classes checkpoint sync verifies and hands on
+    /// together. Defaults to a multiple of the available parallelism.
+    pub chunk_size: Option<NonZeroUsize>,
+    /// Checkpoint sync restarts with new peers if no class definitions arrive
+    /// for this long.
+    pub stall_timeout: Duration,
+    /// Per stage throughput of class sync, exposed over RPC.
+    pub stats: Arc<ClassSyncStats>,
+    /// Where checkpoint sync appends batches of classes it failed to store.
+    pub dead_letter: Option<PathBuf>,
 }
 
-impl ProcessStage for VerifyClassHashes {
-    const NAME: &'static str = "Classes::VerifyHashes";
+impl Default for ClassSyncConfig {
+    fn default() -> Self {
+        Self {
+            record_casm_provenance: false,
+            store_casm_definitions: true,
+            casm_compiler: Default::default(),
+            compile_retries: Default::default(),
+            fgw_breaker: FgwCircuitBreaker::new(5, Duration::from_secs(30)),
+            memory_pressure: Default::default(),
+            pipeline_buffer: NonZeroUsize::MIN,
+            chunk_size: None,
+            stall_timeout: Duration::from_secs(300),
+            stats: Default::default(),
+            dead_letter: None,
+        }
+    }
+}
 
-    type Input = Vec<CompiledClass>;
+pub struct CompileSierraToCasm<T> {
+    fgw: T,
+    tokio_handle: tokio::runtime::Handle,
+    compiler: SizeBasedCompiler,
+    retries: CompileRetries,
+    memory_pressure: MemoryPressure,
+    fgw_breaker: FgwCircuitBreaker,
+}
+
+impl<T> CompileSierraToCasm<T> {
+    pub fn new(
+        fgw: T,
+        tokio_handle: tokio::runtime::Handle,
+        compiler: SizeBasedCompiler,
+        retries: CompileRetries,
+        memory_pressure: MemoryPressure,
+        fgw_breaker: FgwCircuitBreaker,
+    ) -> Self {
+        Self {
+            fgw,
+            tokio_handle,
+            compiler,
+            retries,
+            memory_pressure,
+            fgw_breaker,
+        }
+    }
+}
+
+impl<T: GatewayApi + Clone + Send + 'static> ProcessStage for CompileSierraToCasm<T> {
+    const NAME: &'static str = "Class::CompileSierraToCasm";
+
+    type Input = Vec<Class>;
     type Output = Vec<CompiledClass>;
 
-    fn map(&mut self, peer: &PeerId, input: Self::Input) -> Result<Self::Output, SyncError> {
-        let mut declared_classes = self
-            .tokio_handle
-            .block_on(self.declarations.next())
-            .context("Getting declared classes")?;
+    fn map(&mut self, _: &PeerId, input: Self::Input) -> Result<Self::Output, SyncError> {
+        // Don't start another memory hungry compilation while under pressure.
+        self.tokio_handle.block_on(self.memory_pressure.relieved());
 
-        for class in input.iter() {
-            match class.definition {
-                CompiledClassDefinition::Cairo(_) => {
-                    if !declared_classes.cairo.remove(&class.hash) {
-                        tracing::debug!(%peer, block_number=%class.block_number, class_hash=%class.hash, "Class hash not found in declared classes");
-                        return Err(SyncError::ClassDefinitionsDeclarationsMismatch(*peer));
+        input
+            .into_par_iter()
+            .map(|class| {
+                let compiled = compile_or_fetch_impl(
+                    class,
+                    &self.fgw,
+                    &self.tokio_handle,
+                    &self.compiler,
+                    self.retries,
+                    &self.fgw_breaker,
+                )?;
+                Ok(compiled)
+            })
+            .collect::<Result<Vec<CompiledClass>, SyncError>>()
+    }
+}
+
+pub(super) async fn compile_sierra_to_casm_or_fetch<
+    SequencerClient: GatewayApi + Clone + Send + 'static,
+>(
+    peer_data: Vec<PeerData<Class>>,
+    fgw: SequencerClient,
+    tokio_handle: tokio::runtime::Handle,
+    compiler: SizeBasedCompiler,
+    retries: CompileRetries,
+    memory_pressure: MemoryPressure,
+    fgw_breaker: FgwCircuitBreaker,
+) -> Result<Vec<PeerData<CompiledClass>>, SyncError> {
+    use rayon::prelude::*;
+
+    // Don't start another memory hungry compilation while under pressure.
+    memory_pressure.relieved().await;
+
+    let (tx, rx) = oneshot::channel();
+    rayon::spawn(move || {
+        let res = peer_data
+            .into_par_iter()
+            .map(|x| {
+                let PeerData { peer, data } = x;
+                let compiled = compile_or_fetch_impl(
+                    data,
+                    &fgw,
+                    &tokio_handle,
+                    &compiler,
+                    retries,
+                    &fgw_breaker,
+                )?;
+                Ok(PeerData::new(peer, compiled))
+            })
+            .collect::<Result<Vec<PeerData<CompiledClass>>, SyncError>>();
+        tx.send(res);
+    });
+    rx.await.expect("Sender not to be dropped")
+}
+
+/// Splits each chunk of classes into two lanes. Cairo 0 classes of blocks
+/// before the first Sierra class of the chunk need no compilation and are
+/// passed through as is, while the rest of the chunk is handed to `compile`.
+/// Up to `max_in_flight` lanes are processed concurrently, so that later chunks
+/// are compiled while an expensive Sierra compilation is still running.
+///
+/// Lanes are emitted in order and the classes of a lane all belong to earlier
+/// blocks than those of the next lane, so that storing a lane never reports
+/// progress past classes which are not stored yet.
+pub(super) fn compile_with_cairo_lane<F, Fut>(
+    chunks: impl futures::Stream<Item = Result<Vec<PeerData<Class>>, SyncError>>,
+    mut compile: F,
+    max_in_flight: NonZeroUsize,
+) -> impl futures::Stream<Item = Result<Vec<PeerData<CompiledClass>>, SyncError>>
+where
+    F: FnMut(Vec<PeerData<Class>>) -> Fut,
+    Fut: futures::Future<Output = Result<Vec<PeerData<CompiledClass>>, SyncError>> + Send + 'static,
+{
+    use futures::FutureExt;
+
+    // An error in `chunks` is held back until all chunks received before it are
+    // done, so that their classes can still be stored.
+    let error = std::sync::Arc::new(std::sync::Mutex::new(None));
+    let deferred_error = error.clone();
+
+    chunks
+        .scan((), move |_, chunk| {
+            futures::future::ready(match chunk {
+                Ok(chunk) => Some(chunk),
+                Err(e) => {
+                    *error.lock().unwrap() = Some(e);
+                    None
+                }
+            })
+        })
+        .map(move |chunk| {
+            // Cairo 0 classes declared along with a Sierra class still being compiled
+            // must not be stored ahead of it.
+            let first_sierra_block = chunk
+                .iter()
+                .filter(|x| matches!(x.data.definition, ClassDefinition::Sierra(_)))
+                .map(|x| x.data.block_number)
+                .min();
+
+            let mut cairo = Vec::new();
+            let mut sierra = Vec::new();
+
+            for PeerData { peer, data: class } in chunk {
+                match class.definition {
+                    ClassDefinition::Cairo(definition)
+                        if first_sierra_block.is_none_or(|first| class.block_number < first) =>
+                    {
+                        cairo.push(PeerData::new(
+                            peer,
+                            CompiledClass {
+                                block_number: class.block_number,
+                                hash: class.hash,
+                                definition: CompiledClassDefinition::Cairo(definition),
+                            },
+                        ))
                     }
+                    _ => sierra.push(PeerData::new(peer, class)),
                 }
-                CompiledClassDefinition::Sierra { .. } => {
-                    let hash = SierraHash(class.hash.0);
-                    declared_classes
-                        .sierra
-                        .remove(&hash)
-                        .ok_or_else(|| {
-                            tracing::debug!(%peer, block_number=%class.block_number, class_hash=%class.hash, "Class hash not found in declared classes");
-                            SyncError::ClassDefinitionsDeclarationsMismatch(*peer)
-                        })?;
+            }
+
+            let cairo = (!cairo.is_empty()).then(|| futures::future::ready(Ok(cairo)).boxed());
+            let sierra = (!sierra.is_empty()).then(|| compile(sierra).boxed());
+
+            futures::stream::iter(cairo.into_iter().chain(sierra))
+        })
+        .flatten()
+        .buffered(max_in_flight.get())
+        .chain(
+            futures::stream::once(async move { deferred_error.lock().unwrap().take() })
+                .filter_map(|e| futures::future::ready(e.map(Err))),
+        )
+}
+
+/// Retries `compile` after resource errors as configured by `retries`. This
+/// blocks the calling thread while waiting for the next attempt.
+fn compile_with_retries(
+    sierra_definition: &[u8],
+    compile: impl Fn(&[u8]) -> anyhow::Result<Vec<u8>>,
+    retries: CompileRetries,
+) -> anyhow::Result<Vec<u8>> {
+    let mut attempt = 0;
+    loop {
+        match compile(sierra_definition) {
+            Err(error)
+                if attempt < retries.max_retries
+                    && pathfinder_compiler::is_resource_error(&error) =>
+            {
+                attempt += 1;
+                let delay = retries.delay + retries.delay.mul_f64(rand::random::<f64>());
+                tracing::debug!(%attempt, ?delay, %error, "Retrying Sierra class compilation");
+                thread::sleep(delay);
+            }
+            result => return result,
+        }
+    }
+}
+
+fn compile_or_fetch_impl<SequencerClient: GatewayApi + Clone + Send + 'static>(
+    class: Class,
+    fgw: &SequencerClient,
+    tokio_handle: &tokio::runtime::Handle,
+    compiler: &dyn CasmCompiler,
+    retries: CompileRetries,
+    fgw_breaker: &FgwCircuitBreaker,
+) -> Result<CompiledClass, SyncError> {
+    let Class {
+        block_number,
+        hash,
+        definition,
+    } = class;
+
+    let definition = match definition {
+        ClassDefinition::Cairo(c) => CompiledClassDefinition::Cairo(c),
+        ClassDefinition::Sierra(sierra_definition) => {
+            let casm_definition = compile_with_retries(
+                &sierra_definition,
+                |definition| compiler.compile(definition),
+                retries,
+            )
+            .context("Compiling Sierra class");
+
+            let (casm_definition, casm_source) = match casm_definition {
+                Ok(x) => (x, Some(CasmSource::Compiled)),
+                // Feeder gateway request errors are recoverable at this point because we know
+                // that the class is declared and exists so if the gateway responds with an
+                // error we should restart the sync and retry later.
+                Err(_) => {
+                    if !fgw_breaker.try_acquire() {
+                        tracing::debug!(
+                            %block_number, class_hash=%hash,
+                            "Feeder gateway circuit breaker open, skipping casm request"
+                        );
+                        return Err(SyncError::FetchingCasmFailed);
+                    }
+                    let casm_definition =
+                        fetch_casm_from_fgw(block_number, hash, fgw, tokio_handle);
+                    // The gateway not knowing the class is not an outage.
+                    fgw_breaker.record(!matches!(
+                        casm_definition,
+                        Err(SyncError::FetchingCasmFailed)
+                    ));
+                    (casm_definition?, Some(CasmSource::Gateway))
                 }
+            };
+
+            // A compiler other than the embedded one, or the gateway, may be newer than the
+            // compiled class hash code of this node, whose hash would then silently mismatch.
+            pathfinder_compiler::check_casm_compiler_version(&casm_definition).map_err(
+                |error| {
+                    tracing::warn!(class_hash=%hash, ?casm_source, %error, "Casm is not supported");
+                    SyncError::UnsupportedCasmVersion(hash)
+                },
+            )?;
+
+            CompiledClassDefinition::Sierra {
+                sierra_definition,
+                casm_definition: Some(casm_definition),
+                casm_source,
             }
         }
-        if declared_classes.cairo.is_empty() && declared_classes.sierra.is_empty() {
-            Ok(input)
-        } else {
-            let missing: Vec<ClassHash> = declared_classes
-                .cairo
-                .into_iter()
-                .chain(
-                    declared_classes
+    };
+
+    Ok(CompiledClass {
+        block_number,
+        hash,
+        definition,
+    })
+}
+
+/// Fetches the casm definition of a class that could not be compiled locally.
+///
+/// Classes declared in historical blocks are no longer pending, so the casm is
+/// fetched as of the declaring block. The pending endpoint is only used if the
+/// feeder gateway does not know about that block yet, ie. when syncing the
+/// chain tip.
+fn fetch_casm_from_fgw<SequencerClient: GatewayApi>(
+    block_number: BlockNumber,
+    hash: ClassHash,
+    fgw: &SequencerClient,
+    tokio_handle: &tokio::runtime::Handle,
+) -> Result<Vec<u8>, SyncError> {
+    let to_sync_error = |error: SequencerError| {
+        if is_starknet_error(&error, KnownStarknetErrorCode::UndeclaredClass) {
+            tracing::debug!(%block_number, class_hash=%hash, "Casm not found on feeder gateway");
+            SyncError::CasmNotFound(hash)
+        } else {
+            tracing::debug!(%block_number, class_hash=%hash, %error, "Fetching casm from feeder gateway failed");
+            SyncError::FetchingCasmFailed
+        }
+    };
+
+    match tokio_handle.block_on(fgw.casm_by_hash(hash, BlockId::Number(block_number))) {
+        Ok(casm_definition) => return Ok(casm_definition.to_vec()),
+        Err(error) if is_starknet_error(&error, KnownStarknetErrorCode::BlockNotFound) => {
+            tracing::trace!(%block_number, class_hash=%hash, "Block not yet on feeder gateway, fetching pending casm");
+        }
+        Err(error) => return Err(to_sync_error(error)),
+    }
+
+    tokio_handle
+        .block_on(fgw.pending_casm_by_hash(hash))
+        .map(|casm_definition| casm_definition.to_vec())
+        .map_err(to_sync_error)
+}
+
+/// Reads the casm hashes recorded by the locally stored state diffs for the
+/// Sierra classes in `classes`. Classes whose state diff has not been stored
+/// are left out.
+///
+/// This is read in its own transaction before the classes are written, so the
+/// write transaction never waits on anything but the database.
+fn stored_casm_hashes<'a>(
+    connection: &mut pathfinder_storage::Connection,
+    classes: impl IntoIterator<Item = &'a CompiledClass>,
+) -> anyhow::Result<HashMap<SierraHash, CasmHash>> {
+    let db = connection
+        .transaction()
+        .context("Creating database transaction")?;
+
+    let mut casm_hashes = HashMap::new();
+    for class in classes {
+        if !matches!(class.definition, CompiledClassDefinition::Sierra { .. }) {
+            continue;
+        }
+        if let Some(casm_hash) = db
+            .casm_hash(class.hash)
+            .context("Getting casm hash for sierra class")?
+        {
+            casm_hashes.insert(SierraHash(class.hash.0), casm_hash);
+        }
+    }
+
+    Ok(casm_hashes)
+}
+
+/// Marks the writes of `classes` as in progress, see [recover_interrupted_writes].
+///
+/// The markers are committed in their own transaction before the classes are
+/// written, so that they survive a crash during the write transaction. They are
+/// removed by [persist_impl] as part of the write transaction.
+pub(super) fn begin_class_writes(
+    connection: &mut pathfinder_storage::Connection,
+    classes: impl IntoIterator<Item = ClassHash>,
+) -> anyhow::Result<()> {
+    let db = connection
+        .transaction()
+        .context("Creating database transaction")?;
+
+    for class_hash in classes {
+        db.begin_class_write(class_hash)
+            .context("Marking class write as in progress")?;
+    }
+
+    db.commit().context("Committing database transaction")
+}
+
+fn is_starknet_error(error: &SequencerError, code: KnownStarknetErrorCode) -> bool {
+    matches!(error, SequencerError::StarknetError(e) if e.code == code.into())
+}
+
+pub struct Store {
+    pub connection: pathfinder_storage::Connection,
+    /// Also record whether each Sierra class' CASM was compiled locally or
+    /// fetched from the feeder gateway, see [CasmProvenance].
+    pub record_casm_provenance: bool,
+    /// Store the CASM definitions of Sierra classes, otherwise only their
+    /// Sierra definitions are stored.
+    pub store_casm_definitions: bool,
+}
+
+impl ProcessStage for Store {
+    const NAME: &'static str = "Class::Persist";
+
+    type Input = CompiledClass;
+    type Output = BlockNumber;
+
+    fn map(&mut self, _: &PeerId, input: Self::Input) -> Result<Self::Output, SyncError> {
+        let casm_hashes = stored_casm_hashes(&mut self.connection, [&input])?;
+        begin_class_writes(&mut self.connection, [input.hash])?;
+        let CompiledClass {
+            block_number,
+            hash,
+            definition,
+        } = input;
+
+        let db = self
+            .connection
+            .transaction()
+            .context("Creating database transaction")?;
+
+        persist_impl(
+            &db,
+            block_number,
+            hash,
+            &definition,
+            self.record_casm_provenance,
+            self.store_casm_definitions,
+            |_, sierra_hash| Ok(casm_hashes.get(&sierra_hash).copied()),
+        )?;
+
+        db.commit().context("Committing db transaction")?;
+
+        Ok(block_number)
+    }
+}
+
+/// Writes every [CompiledClass] passing through it to `writer` as a line of
+/// JSON before forwarding it unchanged. Placed in front of [Store] this
+/// captures the input of the storage stage, which can later be re-run on its
+/// own using [replay_store].
+pub struct Dump<W> {
+    pub writer: W,
+}
+
+impl<W: std::io::Write> ProcessStage for Dump<W> {
+    const NAME: &'static str = "Class::Dump";
+
+    type Input = CompiledClass;
+    type Output = CompiledClass;
+
+    fn map(&mut self, _: &PeerId, input: Self::Input) -> Result<Self::Output, SyncError> {
+        serde_json::to_writer(&mut self.writer, &input).context("Serializing class")?;
+        self.writer
+            .write_all(b"\n")
+            .and_then(|_| self.writer.flush())
+            .context("Writing class")?;
+
+        Ok(input)
+    }
+}
+
+/// Reads classes written by [Dump] from `reader` and runs each of them through
+/// `store`, isolating storage issues from fetching and compiling classes.
+///
+/// Returns the block number of the last class stored, if any.
+pub fn replay_store(
+    reader: impl std::io::BufRead,
+    store: &mut Store,
+) -> anyhow::Result<Option<BlockNumber>> {
+    // Store does not care about the peer, the classes were received from it long
+    // ago.
+    let peer = PeerId::random();
+    let mut tail = None;
+
+    for (i, line) in reader.lines().enumerate() {
+        let line = line.context("Reading dumped classes")?;
+        if line.is_empty() {
+            continue;
+        }
+
+        let class = serde_json::from_str::<CompiledClass>(&line)
+            .with_context(|| format!("Deserializing class on line {}", i + 1))?;
+        let block_number = store
+            .map(&peer, class)
+            .with_context(|| format!("Storing class on line {}", i + 1))?;
+        tail = Some(block_number);
+    }
+
+    Ok(tail)
+}
+
+/// How often persisting a batch of classes is retried if the database is busy.
+#[derive(Copy, Clone, Debug)]
+pub struct PersistRetries {
+    pub max_retries: usize,
+    /// Delay before the first retry, doubled for each subsequent one.
+    pub delay: std::time::Duration,
+}
+
+impl Default for PersistRetries {
+    fn default() -> Self {
+        Self {
+            max_retries: 5,
+            delay: std::time::Duration::from_millis(50),
+        }
+    }
+}
+
+/// All classes are stored in a single database transaction. Missing casm hashes
+/// are recovered from the locally stored state diffs, which are read before the
+/// transaction is opened, see [persist_impl].
+///
+/// The transaction is retried according to `retries` while the database is
+/// locked by another connection. If storing the batch fails for any other
+/// reason, or keeps failing, the error is returned so that sync does not move
+/// past classes which were not stored. With a `dead_letter` file the batch is
+/// also appended to it, so that it can be inspected and reprocessed using
+/// [replay_store].
+pub(super) async fn persist(
+    storage: Storage,
+    classes: Vec<PeerData<CompiledClass>>,
+    record_casm_provenance: bool,
+    store_casm_definitions: bool,
+    retries: PersistRetries,
+    dead_letter: Option<PathBuf>,
+) -> Result<BlockNumber, SyncError> {
+    util::task::spawn_blocking(move |_| {
+        let mut db = storage
+            .connection()
+            .context("Creating database connection")?;
+        // Batches can span several blocks, don't rely on the order of the classes.
+        let tail = classes
+            .iter()
+            .map(|x| x.data.block_number)
+            .max()
+            .context("No class definitions to persist")?;
+        let casm_hashes = stored_casm_hashes(&mut db, classes.iter().map(|x| &x.data))?;
+        begin_class_writes(&mut db, classes.iter().map(|x| x.data.hash))?;
+
+        let result = persist_with_retries(
+            || {
+                let db = db.transaction().context("Creating database transaction")?;
+                for class in &classes {
+                    let CompiledClass {
+                        block_number,
+                        hash,
+                        definition,
+                    } = &class.data;
+                    persist_impl(
+                        &db,
+                        *block_number,
+                        *hash,
+                        definition,
+                        record_casm_provenance,
+                        store_casm_definitions,
+                        |_, sierra_hash| Ok(casm_hashes.get(&sierra_hash).copied()),
+                    )?;
+                }
+                db.commit().context("Committing db transaction")
+            },
+            retries,
+        );
+
+        if let Err(error) = result {
+            if let Some(path) = dead_letter {
+                append_dead_letter(&path, &classes, &error).with_context(|| {
+                    format!("Dead-lettering class batch after persisting failed: {error:#}")
+                })?;
+                tracing::warn!(
+                    %tail, path=%path.display(), error=%format!("{error:#}"),
+                    "Persisting class batch failed, added to dead-letter log"
+                );
+                metrics::increment_counter!("class_sync_dead_lettered_batches_total");
+            }
+            return Err(error.into());
+        }
+
+        Ok(tail)
+    })
+    .await
+    .context("Joining blocking task")?
+}
+
+fn persist_with_retries(
+    mut persist: impl FnMut() -> anyhow::Result<()>,
+    retries: PersistRetries,
+) -> anyhow::Result<()> {
+    let mut attempt = 0;
+    loop {
+        match persist() {
+            Err(error)
+                if attempt < retries.max_retries
+                    && pathfinder_storage::is_transient_error(&error) =>
+            {
+                let delay = retries.delay * 2u32.saturating_pow(attempt as u32);
+                attempt += 1;
+                tracing::debug!(%attempt, ?delay, %error, "Retrying persisting class batch");
+                thread::sleep(delay);
+            }
+            result => return result,
+        }
+    }
+}
+
+/// A class of a batch which could not be persisted. The class is flattened so
+/// that every line of the dead-letter log can be read back by [replay_store].
+#[derive(serde::Serialize)]
+struct DeadLetter<'a> {
+    #[serde(flatten)]
+    class: &'a CompiledClass,
+    peer: String,
+    error: String,
+}
+
+fn append_dead_letter(
+    path: &Path,
+    classes: &[PeerData<CompiledClass>],
+    error: &anyhow::Error,
+) -> anyhow::Result<()> {
+    use std::io::Write;
+
+    let file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .context("Opening dead-letter log")?;
+    let mut writer = std::io::BufWriter::new(file);
+
+    for class in classes {
+        let entry = DeadLetter {
+            class: &class.data,
+            peer: class.peer.to_string(),
+            error: format!("{error:#}"),
+        };
+        serde_json::to_writer(&mut writer, &entry).context("Serializing class")?;
+        writer.write_all(b"\n").context("Writing class")?;
+    }
+
+    writer.flush().context("Flushing dead-letter log")
+}
+
+/// If the casm hash of a Sierra class is missing from storage, for example
+/// because the class arrived before the row was written, `recover_casm_hash` is
+/// asked for it with the declaring block. Only if that fails too is the class
+/// rejected.
+///
+/// Without `store_casm_definitions` only the Sierra definition and casm hash of
+/// a Sierra class with locally compiled CASM are stored, and its CASM is marked
+/// as pruned so that readers recompile it.
+///
+/// The write has to be marked as in progress using [begin_class_writes]
+/// beforehand, the marker is cleared once the class is written.
+pub(super) fn persist_impl(
+    db: &Transaction<'_>,
+    block_number: BlockNumber,
+    hash: ClassHash,
+    definition: &CompiledClassDefinition,
+    record_casm_provenance: bool,
+    store_casm_definitions: bool,
+    recover_casm_hash: impl FnOnce(BlockNumber, SierraHash) -> anyhow::Result<Option<CasmHash>>,
+) -> anyhow::Result<()> {
+    match definition {
+        CompiledClassDefinition::Cairo(definition) => {
+            db.update_cairo_class(hash, definition)
+                .context("Updating cairo class definition")?;
+        }
+        CompiledClassDefinition::Sierra {
+            sierra_definition,
+            casm_definition,
+            casm_source,
+        } => {
+            let sierra_hash = SierraHash(hash.0);
+            let casm_hash = match db
+                .casm_hash(hash)
+                .context("Getting casm hash for sierra class")?
+            {
+                Some(casm_hash) => casm_hash,
+                None => {
+                    let casm_hash = recover_casm_hash(block_number, sierra_hash)
+                        .context("Recovering casm hash from state update")?
+                        .context("Casm hash not found")?;
+                    tracing::debug!(
+                        %block_number, class_hash=%hash, %casm_hash,
+                        "Recovered missing casm hash"
+                    );
+                    db.insert_casm_hash(&sierra_hash, &casm_hash)
+                        .context("Inserting recovered casm hash")?;
+                    casm_hash
+                }
+            };
+
+            // Only locally compiled CASM is known to be reproducible from the Sierra
+            // definition, anything else has to be stored.
+            let casm_definition = casm_definition
+                .as_ref()
+                .filter(|_| store_casm_definitions || *casm_source != Some(CasmSource::Compiled));
+            match casm_definition {
+                Some(casm_definition) => db
+                    .update_sierra_class(
+                        &sierra_hash,
+                        sierra_definition,
+                        &casm_hash,
+                        casm_definition,
+                    )
+                    .context("Updating sierra class definition")?,
+                None => db
+                    .update_sierra_class_without_casm(&sierra_hash, sierra_definition, &casm_hash)
+                    .context("Updating sierra class definition without casm")?,
+            }
+
+            let record_casm_provenance = record_casm_provenance && casm_definition.is_some();
+            if let Some(source) = casm_source.filter(|_| record_casm_provenance) {
+                let recorded_at = std::time::SystemTime::now()
+                    .duration_since(std::time::UNIX_EPOCH)
+                    .context("Getting current time")?
+                    .as_secs();
+
+                db.insert_casm_provenance(
+                    &sierra_hash,
+                    CasmProvenance {
+                        source,
+                        recorded_at,
+                    },
+                )
+                .context("Recording casm provenance")?;
+            }
+        }
+    }
+
+    db.finish_class_write(hash)
+        .context("Clearing class write in progress marker")
+}
+
+/// Fetches a single declared class from the feeder gateway and runs it through
+/// the same verification, compilation and storage steps as synced classes,
+/// replacing any stored definition. This recovers a corrupted or missing class
+/// without re-syncing the block range declaring it.
+///
+/// Peers only serve classes by block, so the feeder gateway is the source.
+/// Whether the class is a Sierra class is taken from the fetched definition,
+/// since the stored one may be what is broken. If `trust_class_hash` is set
+/// the gateway is trusted to serve the requested class and its hash is not
+/// computed.
+///
+/// Sierra classes are compiled as configured by `config`, and CASM which fails
+/// to compile is fetched from the feeder gateway as of the declaring block, the
+/// same as during sync. Without [ClassSyncConfig::store_casm_definitions] only
+/// the Sierra definition is stored, see [persist_impl].
+pub async fn refetch_class<SequencerClient: GatewayApi + Clone + Send + 'static>(
+    storage: Storage,
+    fgw: SequencerClient,
+    class_hash: ClassHash,
+    config: &ClassSyncConfig,
+    trust_class_hash: bool,
+) -> Result<(), SyncError> {
+    let ClassSyncConfig {
+        record_casm_provenance,
+        store_casm_definitions,
+        casm_compiler,
+        compile_retries,
+        fgw_breaker,
+        ..
+    } = config.clone();
+
+    let db_storage = storage.clone();
+    let block_number = util::task::spawn_blocking(move |_| {
+        let mut db = db_storage
+            .connection()
+            .context("Creating database connection")?;
+        let db = db.transaction().context("Creating database transaction")?;
+
+        db.class_declaration_block(class_hash)
+            .context("Querying class declaration block")?
+            .context("Class is not declared")
+    })
+    .await
+    .context("Joining blocking task")??;
+
+    let definition = fgw
+        .pending_class_by_hash(class_hash)
+        .await
+        .context("Fetching class definition from feeder gateway")?
+        .to_vec();
+
+    let tokio_handle = tokio::runtime::Handle::current();
+    util::task::spawn_blocking(move |_| {
+        use pathfinder_class_hash::json::ContractDefinition;
+
+        let is_sierra = match pathfinder_class_hash::parse_contract_definition(&definition)
+            .context("Parsing fetched class definition")?
+        {
+            ContractDefinition::Sierra(_) => true,
+            ContractDefinition::Cairo(_) => false,
+        };
+        let class = if is_sierra {
+            P2PClassDefinition::Sierra {
+                block_number,
+                sierra_definition: definition,
+                hash: SierraHash(class_hash.0),
+            }
+        } else {
+            P2PClassDefinition::Cairo {
+                block_number,
+                definition,
+                hash: class_hash,
+            }
+        };
+
+        let peer = storage_adapters::local_peer();
+        let class = verify_layout_impl(&peer, class)?;
+        let class = if trust_class_hash {
+            trust_hash_impl(class)
+        } else {
+            verify_hash_impl(&peer, class)?
+        };
+        let CompiledClass {
+            block_number,
+            hash,
+            definition,
+        } = compile_or_fetch_impl(
+            class,
+            &fgw,
+            &tokio_handle,
+            &casm_compiler,
+            compile_retries,
+            &fgw_breaker,
+        )?;
+
+        let mut db = storage
+            .connection()
+            .context("Creating database connection")?;
+        begin_class_writes(&mut db, [hash])?;
+        let db = db.transaction().context("Creating database transaction")?;
+        persist_impl(
+            &db,
+            block_number,
+            hash,
+            &definition,
+            record_casm_provenance,
+            store_casm_definitions,
+            // The casm hash of a declared class is stored with its state diff.
+            |_, _| Ok(None),
+        )?;
+        db.commit().context("Committing db transaction")?;
+
+        tracing::info!(%block_number, class_hash=%hash, "Class definition refetched");
+
+        Ok(())
+    })
+    .await
+    .context("Joining blocking task")?
+}
+
+/// Runs `classes` through all stages of the class definition pipeline, from
+/// layout verification to storage, wired together the same way as when
+/// syncing classes from peers. `expected_declarations` lists the classes
+/// declared by each block, in block order, and these blocks must already be in
+/// `storage`.
+///
+/// Classes are processed in chunks of `chunk_size` classes and Sierra classes
+/// are compiled with the default [SizeBasedCompiler], falling back to `fgw`
+/// while `fgw_breaker` allows it. Up to `max_in_flight` chunks are compiled
+/// concurrently, and the run fails if no classes arrive for `stall_timeout`
+/// while more are expected. Returns the number of the last block whose classes
+/// were persisted, or `None` if there were no classes.
+///
+/// Meant for exercising the pipeline end-to-end in integration tests and
+/// benchmarks.
+#[cfg(any(test, feature = "test-utils"))]
+pub async fn run_pipeline<SequencerClient: GatewayApi + Clone + Send + 'static>(
+    classes: Vec<P2PClassDefinition>,
+    expected_declarations: Vec<ExpectedDeclarations>,
+    storage: Storage,
+    fgw: SequencerClient,
+    chunk_size: NonZeroUsize,
+    stall_timeout: Duration,
+    max_in_flight: NonZeroUsize,
+    fgw_breaker: FgwCircuitBreaker,
+) -> Result<Option<BlockNumber>, SyncError> {
+    use futures::{TryFutureExt, TryStreamExt};
+
+    let peer = storage_adapters::local_peer();
+    let memory_pressure = MemoryPressure::default();
+
+    let classes = flatten_partial_batches(
+        futures::stream::iter(classes)
+            .map(move |class| PeerData::new(peer, class))
+            .chunks(chunk_size.get())
+            .then(|x| verify_layout(x).map_err(PartialBatch::from))
+            .and_then(verify_hash),
+    )
+    .boxed();
+
+    let expected_declarations = coalesce_declarations(
+        futures::stream::iter(
+            expected_declarations
+                .into_iter()
+                .map(|x| Ok((x.block_number, x.classes))),
+        ),
+        chunk_size,
+    );
+    let classes = flatten_partial_batches(verify_declared_at(
+        expected_declarations.boxed(),
+        classes,
+        memory_pressure.clone(),
+        NonZeroUsize::MIN,
+        stall_timeout,
+    ));
+
+    let compile_fgw = fgw.clone();
+    compile_with_cairo_lane(
+        classes,
+        move |x| {
+            compile_sierra_to_casm_or_fetch(
+                x,
+                compile_fgw.clone(),
+                tokio::runtime::Handle::current(),
+                SizeBasedCompiler::default(),
+                CompileRetries::default(),
+                memory_pressure.clone(),
+                fgw_breaker.clone(),
+            )
+        },
+        max_in_flight,
+    )
+    .and_then(|x| {
+        persist(
+            storage.clone(),
+            x,
+            false,
+            true,
+            PersistRetries::default(),
+            None,
+        )
+    })
+    // Chunks can span several blocks.
+    .try_fold(None, |tail: Option<BlockNumber>, x| {
+        std::future::ready(Ok(tail.max(Some(x))))
+    })
+    .await
+}
+
+/// Expected class declarations as consumed by [VerifyClassHashes].
+#[derive(Clone, Debug)]
+pub enum DeclarationsItem {
+    /// Classes declared in the next block.
+    Block(DeclaredClasses),
+    /// The source has sent all declarations, no more class definitions are
+    /// expected.
+    Done,
+}
+
+pub struct VerifyClassHashes {
+    declarations: BoxStream<'static, DeclarationsItem>,
+    tokio_handle: tokio::runtime::Handle,
+    source_done: bool,
+}
+
+impl VerifyClassHashes {
+    pub fn new(
+        declarations: BoxStream<'static, DeclarationsItem>,
+        tokio_handle: tokio::runtime::Handle,
+    ) -> Self {
+        Self {
+            declarations,
+            tokio_handle,
+            source_done: false,
+        }
+    }
+}
+
+impl ProcessStage for VerifyClassHashes {
+    const NAME: &'static str = "Classes::VerifyHashes";
+
+    type Input = Vec<CompiledClass>;
+    type Output = Vec<CompiledClass>;
+
+    fn map(&mut self, peer: &PeerId, input: Self::Input) -> Result<Self::Output, SyncError> {
+        let item = if self.source_done {
+            Some(DeclarationsItem::Done)
+        } else {
+            self.tokio_handle.block_on(self.declarations.next())
+        };
+
+        let mut declared_classes = match item {
+            Some(DeclarationsItem::Block(declared_classes)) => declared_classes,
+            Some(DeclarationsItem::Done) => {
+                self.source_done = true;
+                tracing::debug!(%peer, "Received class definitions past the last declaring block");
+                return Err(SyncError::ClassDefinitionsDeclarationsMismatch(*peer));
+            }
+            // The source went away without signalling completion, most likely due to an
+            // upstream error. This is not the peer's fault and sync can be retried.
+            None => {
+                tracing::debug!(%peer, "Declarations source closed unexpectedly");
+                return Err(SyncError::DeclarationsSourceClosed);
+            }
+        };
+
+        for class in input.iter() {
+            match class.definition {
+                CompiledClassDefinition::Cairo(_) => {
+                    if !declared_classes.cairo.remove(&class.hash) {
+                        tracing::debug!(%peer, block_number=%class.block_number, class_hash=%class.hash, "Class hash not found in declared classes");
+                        return Err(SyncError::ClassDefinitionsDeclarationsMismatch(*peer));
+                    }
+                }
+                CompiledClassDefinition::Sierra { .. } => {
+                    let hash = SierraHash(class.hash.0);
+                    declared_classes
                         .sierra
-                        .into_values()
-                        .map(|casm_hash| ClassHash(casm_hash.0)),
+                        .remove(&hash)
+                        .ok_or_else(|| {
+                            tracing::debug!(%peer, block_number=%class.block_number, class_hash=%class.hash, "Class hash not found in declared classes");
+                            SyncError::ClassDefinitionsDeclarationsMismatch(*peer)
+                        })?;
+                }
+            }
+        }
+        if declared_classes.cairo.is_empty() && declared_classes.sierra.is_empty() {
+            Ok(input)
+        } else {
+            let missing = missing_classes(declared_classes);
+            tracing::trace!(%peer, ?missing, "Expected class definitions are missing");
+            Err(SyncError::ClassDefinitionsDeclarationsMismatch(*peer))
+        }
+    }
+}
+
+/// Lists the declared classes for which no definition was received, sorted by
+/// hash so that logs of recurring mismatches can be compared across runs.
+fn missing_classes(declared_classes: DeclaredClasses) -> Vec<ClassHash> {
+    let mut missing = declared_classes
+        .cairo
+        .into_iter()
+        .chain(
+            declared_classes
+                .sierra
+                .into_keys()
+                .map(|sierra_hash| ClassHash(sierra_hash.0)),
+        )
+        .collect::<Vec<_>>();
+    missing.sort();
+    missing
+}
+
+#[cfg(test)]
+mod tests {
+    use pathfinder_common::macro_prelude::*;
+    use pathfinder_common::StateUpdate;
+    use starknet_gateway_types::error::StarknetError;
+
+    use super::*;
+
+    /// Knows blocks up to and including `latest`, anything after that is only
+    /// available as pending.
+    #[derive(Clone)]
+    struct FakeFgw {
+        latest: BlockNumber,
+        declared: bool,
+    }
+
+    fn starknet_error(code: KnownStarknetErrorCode) -> SequencerError {
+        StarknetError {
+            code: code.into(),
+            message: String::new(),
+        }
+        .into()
+    }
+
+    #[async_trait::async_trait]
+    impl GatewayApi for FakeFgw {
+        async fn pending_casm_by_hash(&self, _: ClassHash) -> Result<bytes::Bytes, SequencerError> {
+            if self.declared {
+                Ok(bytes::Bytes::from_static(b"pending"))
+            } else {
+                Err(starknet_error(KnownStarknetErrorCode::UndeclaredClass))
+            }
+        }
+
+        async fn casm_by_hash(
+            &self,
+            _: ClassHash,
+            block: BlockId,
+        ) -> Result<bytes::Bytes, SequencerError> {
+            let BlockId::Number(block_number) = block else {
+                panic!("Expected a block number, got {block:?}");
+            };
+
+            if block_number > self.latest {
+                Err(starknet_error(KnownStarknetErrorCode::BlockNotFound))
+            } else if self.declared {
+                Ok(bytes::Bytes::from_static(b"historical"))
+            } else {
+                Err(starknet_error(KnownStarknetErrorCode::UndeclaredClass))
+            }
+        }
+    }
+
+    fn fetch(block_number: BlockNumber, fgw: FakeFgw) -> Result<Vec<u8>, SyncError> {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        fetch_casm_from_fgw(block_number, class_hash!("0x1"), &fgw, runtime.handle())
+    }
+
+    #[test]
+    fn historical_block_uses_block_endpoint() {
+        let fgw = FakeFgw {
+            latest: BlockNumber::new_or_panic(10),
+            declared: true,
+        };
+
+        assert_eq!(
+            fetch(BlockNumber::new_or_panic(5), fgw.clone()).unwrap(),
+            b"historical"
+        );
+        assert_eq!(
+            fetch(BlockNumber::new_or_panic(10), fgw).unwrap(),
+            b"historical"
+        );
+    }
+
+    #[test]
+    fn tip_falls_back_to_pending_endpoint() {
+        let fgw = FakeFgw {
+            latest: BlockNumber::new_or_panic(10),
+            declared: true,
+        };
+
+        assert_eq!(
+            fetch(BlockNumber::new_or_panic(11), fgw).unwrap(),
+            b"pending"
+        );
+    }
+
+    #[test]
+    fn class_missing_from_both_endpoints() {
+        let fgw = FakeFgw {
+            latest: BlockNumber::new_or_panic(10),
+            declared: false,
+        };
+
+        assert_eq!(
+            fetch(BlockNumber::new_or_panic(5), fgw.clone()).unwrap_err(),
+            SyncError::CasmNotFound(class_hash!("0x1"))
+        );
+        assert_eq!(
+            fetch(BlockNumber::new_or_panic(11), fgw).unwrap_err(),
+            SyncError::CasmNotFound(class_hash!("0x1"))
+        );
+    }
+
+    mod compile_with_retries {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::time::Duration;
+
+        use super::*;
+
+        const RETRIES: CompileRetries = CompileRetries {
+            max_retries: 2,
+            delay: Duration::from_millis(1),
+        };
+
+        fn out_of_memory() -> anyhow::Error {
+            anyhow::Error::new(std::io::Error::from(std::io::ErrorKind::OutOfMemory))
+                .context("Compiling to CASM")
+        }
+
+        #[test]
+        fn resource_error_is_retried_locally() {
+            let calls = AtomicUsize::new(0);
+            let result = super::compile_with_retries(
+                b"sierra",
+                |_| match calls.fetch_add(1, Ordering::Relaxed) {
+                    0 => Err(out_of_memory()),
+                    _ => Ok(b"casm".to_vec()),
+                },
+                RETRIES,
+            );
+
+            assert_eq!(result.unwrap(), b"casm");
+            assert_eq!(calls.load(Ordering::Relaxed), 2);
+        }
+
+        #[test]
+        fn retries_are_limited() {
+            let calls = AtomicUsize::new(0);
+            let result = super::compile_with_retries(
+                b"sierra",
+                |_| {
+                    calls.fetch_add(1, Ordering::Relaxed);
+                    Err(out_of_memory())
+                },
+                RETRIES,
+            );
+
+            assert!(result.is_err());
+            assert_eq!(calls.load(Ordering::Relaxed), RETRIES.max_retries + 1);
+        }
+
+        #[test]
+        fn deterministic_error_is_not_retried() {
+            let calls = AtomicUsize::new(0);
+            let result = super::compile_with_retries(
+                b"sierra",
+                |_| {
+                    calls.fetch_add(1, Ordering::Relaxed);
+                    Err(anyhow::anyhow!("Validating Sierra class"))
+                },
+                RETRIES,
+            );
+
+            assert!(result.is_err());
+            assert_eq!(calls.load(Ordering::Relaxed), 1);
+        }
+    }
+
+    #[tokio::test]
+    async fn lanes_are_emitted_in_order() {
+        let class = |block_number, hash, definition| {
+            PeerData::for_tests(Class {
+                block_number: BlockNumber::new_or_panic(block_number),
+                hash,
+                definition,
+            })
+        };
+        let chunks = futures::stream::iter([
+            Ok(vec![
+                class(
+                    1,
+                    class_hash!("0x1"),
+                    ClassDefinition::Sierra(b"sierra".to_vec()),
+                ),
+                class(
+                    0,
+                    class_hash!("0x2"),
+                    ClassDefinition::Cairo(b"cairo".to_vec()),
+                ),
+                // Declared in the same block as the Sierra class, so it waits for it.
+                class(
+                    1,
+                    class_hash!("0x4"),
+                    ClassDefinition::Cairo(b"cairo".to_vec()),
+                ),
+            ]),
+            Ok(vec![class(
+                2,
+                class_hash!("0x3"),
+                ClassDefinition::Cairo(b"cairo".to_vec()),
+            )]),
+        ]);
+
+        // Sierra compilation only finishes once we say so.
+        let (compiled_tx, compiled_rx) = tokio::sync::oneshot::channel::<()>();
+        let mut compiled_rx = Some(compiled_rx);
+        let compile = move |classes: Vec<PeerData<Class>>| {
+            let compiled_rx = compiled_rx.take().expect("Single Sierra chunk");
+            async move {
+                compiled_rx.await.unwrap();
+                Ok::<_, SyncError>(
+                    classes
+                        .into_iter()
+                        .map(|class| {
+                            class.map(|class| CompiledClass {
+                                block_number: class.block_number,
+                                hash: class.hash,
+                                definition: CompiledClassDefinition::Sierra {
+                                    sierra_definition: b"sierra".to_vec(),
+                                    casm_definition: Some(b"casm".to_vec()),
+                                    casm_source: Some(CasmSource::Compiled),
+                                },
+                            })
+                        })
+                        .collect::<Vec<_>>(),
+                )
+            }
+        };
+        let hashes = |chunk: Vec<PeerData<CompiledClass>>| {
+            chunk.into_iter().map(|x| x.data.hash).collect::<Vec<_>>()
+        };
+
+        let stream = compile_with_cairo_lane(chunks, compile, NonZeroUsize::new(4).unwrap());
+        pin_mut!(stream);
+
+        let timeout = std::time::Duration::from_secs(5);
+        let first = tokio::time::timeout(timeout, stream.next()).await.unwrap();
+        assert_eq!(hashes(first.unwrap().unwrap()), vec![class_hash!("0x2")]);
+        // The Cairo 0 classes of the next chunk are ready, but must not overtake the
+        // Sierra classes still being compiled.
+        let short = std::time::Duration::from_millis(50);
+        assert!(tokio::time::timeout(short, stream.next()).await.is_err());
+
+        compiled_tx.send(()).unwrap();
+        let second = tokio::time::timeout(timeout, stream.next()).await.unwrap();
+        assert_eq!(
+            hashes(second.unwrap().unwrap()),
+            vec![class_hash!("0x1"), class_hash!("0x4")]
+        );
+        let third = tokio::time::timeout(timeout, stream.next()).await.unwrap();
+        assert_eq!(hashes(third.unwrap().unwrap()), vec![class_hash!("0x3")]);
+        assert!(stream.next().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn classes_preceding_a_bad_hash_survive() {
+        use starknet_gateway_test_fixtures::class_definitions::{
+            CAIRO_0_10_TUPLES_INTEGRATION as CAIRO,
+            CAIRO_0_10_TUPLES_INTEGRATION_CLASS_HASH as CAIRO_HASH,
+            CAIRO_2_0_0_STACK_OVERFLOW as SIERRA,
+            CAIRO_2_0_0_STACK_OVERFLOW_CLASS_HASH as SIERRA_HASH,
+        };
+
+        let cairo = |hash| P2PClassDefinition::Cairo {
+            block_number: BlockNumber::GENESIS,
+            definition: CAIRO.to_vec(),
+            hash,
+        };
+        let sierra = P2PClassDefinition::Sierra {
+            block_number: BlockNumber::GENESIS,
+            sierra_definition: SIERRA.to_vec(),
+            hash: SIERRA_HASH,
+        };
+        let batch = [
+            cairo(CAIRO_HASH),
+            sierra,
+            cairo(class_hash!("0xbad")),
+            cairo(CAIRO_HASH),
+        ]
+        .into_iter()
+        .map(|class| {
+            let class = PeerData::for_tests(class);
+            PeerData::new(
+                class.peer,
+                verify_layout_impl(&class.peer, class.data).unwrap(),
+            )
+        })
+        .collect::<Vec<_>>();
+        let bad_peer = batch[2].peer;
+
+        let PartialBatch { processed, error } = verify_hash(batch).await.unwrap_err();
+        let processed = processed
+            .into_iter()
+            .map(|class| class.data.hash)
+            .collect::<Vec<_>>();
+        assert_eq!(processed, vec![CAIRO_HASH, ClassHash(SIERRA_HASH.0)]);
+        assert_eq!(error, SyncError::BadClassHash(bad_peer));
+
+        let flattened = flatten_partial_batches(futures::stream::iter([Err(PartialBatch {
+            processed: vec![1, 2],
+            error: SyncError::BadClassHash(bad_peer),
+        })]))
+        .collect::<Vec<_>>()
+        .await;
+        assert_eq!(
+            flattened,
+            vec![Ok(vec![1, 2]), Err(SyncError::BadClassHash(bad_peer))]
+        );
+    }
+
+    #[test]
+    fn fused_stage_matches_separate_stages() {
+        use starknet_gateway_test_fixtures::class_definitions::{
+            CAIRO_0_10_TUPLES_INTEGRATION as CAIRO,
+            CAIRO_0_10_TUPLES_INTEGRATION_CLASS_HASH as CAIRO_HASH,
+            CAIRO_2_0_0_STACK_OVERFLOW as SIERRA,
+            CAIRO_2_0_0_STACK_OVERFLOW_CLASS_HASH as SIERRA_HASH,
+        };
+
+        let cairo = |hash| P2PClassDefinition::Cairo {
+            block_number: BlockNumber::GENESIS,
+            definition: CAIRO.to_vec(),
+            hash,
+        };
+        let sierra = || P2PClassDefinition::Sierra {
+            block_number: BlockNumber::new_or_panic(1),
+            sierra_definition: SIERRA.to_vec(),
+            hash: SIERRA_HASH,
+        };
+        let garbage = || P2PClassDefinition::Cairo {
+            block_number: BlockNumber::GENESIS,
+            definition: b"{}".to_vec(),
+            hash: CAIRO_HASH,
+        };
+
+        let separate = |peer: &PeerId, batch: Vec<P2PClassDefinition>| {
+            VerifyLayout
+                .map(peer, batch)
+                .and_then(|batch| VerifyHash.map(peer, batch))
+        };
+
+        let peer = PeerId::random();
+        let batch = || vec![cairo(CAIRO_HASH), sierra(), cairo(CAIRO_HASH)];
+        let fused = VerifyLayoutAndHash.map(&peer, batch()).unwrap();
+        assert_eq!(fused, separate(&peer, batch()).unwrap());
+        assert_eq!(
+            fused.iter().map(|class| class.hash).collect::<Vec<_>>(),
+            vec![CAIRO_HASH, ClassHash(SIERRA_HASH.0), CAIRO_HASH]
+        );
+
+        // Failures are attributed to the peer the batch came from.
+        let bad_hash = || vec![sierra(), cairo(class_hash!("0xbad"))];
+        assert_eq!(
+            VerifyLayoutAndHash.map(&peer, bad_hash()),
+            Err(SyncError::BadClassHash(peer))
+        );
+        assert_eq!(
+            separate(&peer, bad_hash()),
+            Err(SyncError::BadClassHash(peer))
+        );
+
+        let bad_layout = || vec![cairo(CAIRO_HASH), garbage()];
+        assert_eq!(
+            VerifyLayoutAndHash.map(&peer, bad_layout()),
+            Err(SyncError::BadClassLayout(peer))
+        );
+        assert_eq!(
+            separate(&peer, bad_layout()),
+            Err(SyncError::BadClassLayout(peer))
+        );
+    }
+
+    #[tokio::test]
+    async fn export_stream() {
+        let storage = pathfinder_storage::StorageBuilder::in_memory().unwrap();
+        let blocks = pathfinder_storage::fake::generate::n_blocks(3);
+        pathfinder_storage::fake::fill(&storage, &blocks, None);
+
+        // (block number, class hash, definition, casm definition)
+        let expected_from = |start: usize| {
+            blocks[start..]
+                .iter()
+                .flat_map(|block| {
+                    let block_number = block.header.header.number;
+                    let mut classes = block
+                        .cairo_defs
+                        .iter()
+                        .map(|(hash, definition)| (block_number, *hash, definition.clone(), None))
+                        .chain(block.sierra_defs.iter().map(|(hash, sierra, casm)| {
+                            (
+                                block_number,
+                                ClassHash(hash.0),
+                                sierra.clone(),
+                                Some(casm.clone()),
+                            )
+                        }))
+                        .collect::<Vec<_>>();
+                    classes.sort_by_key(|(_, hash, ..)| *hash);
+                    classes
+                })
+                .collect::<Vec<_>>()
+        };
+        let export_from = |start: BlockNumber| {
+            super::export_stream(storage.clone(), start)
+                .map(|class| {
+                    let CompiledClass {
+                        block_number,
+                        hash,
+                        definition,
+                    } = class.unwrap();
+                    match definition {
+                        CompiledClassDefinition::Cairo(definition) => {
+                            (block_number, hash, definition, None)
+                        }
+                        CompiledClassDefinition::Sierra {
+                            sierra_definition,
+                            casm_definition,
+                            casm_source,
+                        } => {
+                            assert_eq!(casm_source, None);
+                            (block_number, hash, sierra_definition, casm_definition)
+                        }
+                    }
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let expected = expected_from(0);
+        assert!(!expected.is_empty());
+        assert_eq!(export_from(BlockNumber::GENESIS).await, expected);
+
+        // Resuming from a later block skips everything declared before it.
+        assert_eq!(
+            export_from(BlockNumber::GENESIS + 1).await,
+            expected_from(1)
+        );
+
+        // Pruned CASM definitions are exported as absent, the classes are still
+        // exported as Sierra classes.
+        let mut db = storage.connection().unwrap();
+        let tx = db.transaction().unwrap();
+        tx.prune_casm_definitions(BlockNumber::MAX, |_, _, _| true)
+            .unwrap();
+        tx.commit().unwrap();
+        let sierra_hashes = blocks
+            .iter()
+            .flat_map(|block| block.sierra_defs.iter().map(|(hash, ..)| ClassHash(hash.0)))
+            .collect::<HashSet<_>>();
+        let exported = super::export_stream(storage.clone(), BlockNumber::GENESIS)
+            .map(|class| class.unwrap())
+            .collect::<Vec<_>>()
+            .await;
+        for class in exported {
+            match class.definition {
+                CompiledClassDefinition::Cairo(_) => {
+                    assert!(!sierra_hashes.contains(&class.hash))
+                }
+                CompiledClassDefinition::Sierra {
+                    casm_definition, ..
+                } => {
+                    assert!(sierra_hashes.contains(&class.hash));
+                    assert_eq!(casm_definition, None);
+                }
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn run_pipeline() {
+        use starknet_gateway_test_fixtures::class_definitions::{
+            CAIRO_0_10_TUPLES_INTEGRATION as CAIRO,
+            CAIRO_0_10_TUPLES_INTEGRATION_CLASS_HASH as CAIRO_HASH,
+            CAIRO_2_0_0_STACK_OVERFLOW as SIERRA,
+            CAIRO_2_0_0_STACK_OVERFLOW_CLASS_HASH as SIERRA_HASH,
+        };
+
+        let block_number = BlockNumber::GENESIS;
+
+        let mut block = pathfinder_storage::fake::Block::default();
+        block.header.header.number = block_number;
+        block.state_update = Some(
+            StateUpdate::default()
+                .with_declared_cairo_class(CAIRO_HASH)
+                .with_declared_sierra_class(SIERRA_HASH, casm_hash!("0x1")),
+        );
+        let storage = pathfinder_storage::StorageBuilder::in_memory().unwrap();
+        pathfinder_storage::fake::fill(&storage, &[block], None);
+
+        let classes = vec![
+            P2PClassDefinition::Sierra {
+                block_number,
+                sierra_definition: SIERRA.to_vec(),
+                hash: SIERRA_HASH,
+            },
+            P2PClassDefinition::Cairo {
+                block_number,
+                definition: CAIRO.to_vec(),
+                hash: CAIRO_HASH,
+            },
+        ];
+        let expected_declarations = vec![ExpectedDeclarations {
+            block_number,
+            classes: [CAIRO_HASH, ClassHash(SIERRA_HASH.0)].into(),
+        }];
+        // Both classes are valid, the gateway is never queried.
+        let fgw = FakeFgw {
+            latest: block_number,
+            declared: false,
+        };
+
+        let tail = super::run_pipeline(
+            classes,
+            expected_declarations,
+            storage.clone(),
+            fgw,
+            NonZeroUsize::MIN,
+            Duration::from_secs(60),
+            NonZeroUsize::new(4).unwrap(),
+            FgwCircuitBreaker::new(5, Duration::from_secs(30)),
+        )
+        .await
+        .unwrap();
+        assert_eq!(tail, Some(block_number));
+
+        let mut db = storage.connection().unwrap();
+        let db = db.transaction().unwrap();
+        assert_eq!(db.class_definition(CAIRO_HASH).unwrap().unwrap(), CAIRO);
+        assert_eq!(
+            db.class_definition(ClassHash(SIERRA_HASH.0))
+                .unwrap()
+                .unwrap(),
+            SIERRA
+        );
+        let casm = db
+            .casm_definition(ClassHash(SIERRA_HASH.0))
+            .unwrap()
+            .unwrap();
+        assert!(
+            serde_json::from_slice::<serde_json::Value>(&casm).unwrap()["compiler_version"]
+                .is_string()
+        );
+    }
+
+    #[test]
+    fn casm_definition_is_not_stored() {
+        use starknet_gateway_test_fixtures::class_definitions::CAIRO_2_0_0_STACK_OVERFLOW as SIERRA;
+
+        let storage = pathfinder_storage::StorageBuilder::in_memory().unwrap();
+        let mut db = storage.connection().unwrap();
+        let db = db.transaction().unwrap();
+        let SIERRA_HASH = sierra_hash_bytes!(b"sierra");
+        let GATEWAY_HASH = sierra_hash_bytes!(b"gateway");
+        let class_hash = ClassHash(SIERRA_HASH.0);
+        let gateway_class_hash = ClassHash(GATEWAY_HASH.0);
+        db.insert_state_update(
+            BlockNumber::GENESIS,
+            &StateUpdate::default()
+                .with_declared_sierra_class(SIERRA_HASH, casm_hash!("0x1"))
+                .with_declared_sierra_class(GATEWAY_HASH, casm_hash!("0x2")),
+        )
+        .unwrap();
+
+        for (hash, casm_source) in [
+            (class_hash, CasmSource::Compiled),
+            (gateway_class_hash, CasmSource::Gateway),
+        ] {
+            persist_impl(
+                &db,
+                BlockNumber::GENESIS,
+                hash,
+                &CompiledClassDefinition::Sierra {
+                    sierra_definition: SIERRA.to_vec(),
+                    casm_definition: Some(b"casm definition".to_vec()),
+                    casm_source: Some(casm_source),
+                },
+                true,
+                false,
+                |_, _| Ok(None),
+            )
+            .unwrap();
+        }
+
+        assert_eq!(db.class_definition(class_hash).unwrap().unwrap(), SIERRA);
+        assert!(db.casm_definition_is_pruned(class_hash).unwrap());
+        assert_eq!(db.casm_definition(class_hash).unwrap(), None);
+        assert_eq!(db.casm_hash(class_hash).unwrap(), Some(casm_hash!("0x1")));
+        assert_eq!(db.casm_provenance(class_hash).unwrap(), None);
+
+        // CASM fetched from the gateway is always kept.
+        assert!(!db.casm_definition_is_pruned(gateway_class_hash).unwrap());
+        assert_eq!(
+            db.casm_definition(gateway_class_hash).unwrap().unwrap(),
+            b"casm definition"
+        );
+    }
+
+    #[test]
+    fn store_recovers_casm_hash_stored_after_the_class() {
+        let storage = pathfinder_storage::StorageBuilder::in_memory().unwrap();
+        let mut blocks = pathfinder_storage::fake::generate::n_blocks(3);
+        let block = blocks
+            .iter_mut()
+            .find(|block| !block.sierra_defs.is_empty())
+            .unwrap();
+        let block_number = block.header.header.number;
+        let (SIERRA_HASH, sierra_definition, casm_definition) = block.sierra_defs.pop().unwrap();
+        let state_update = block.state_update.as_mut().unwrap();
+        let casm_hash = state_update
+            .declared_sierra_classes
+            .remove(&SIERRA_HASH)
+            .unwrap();
+        // The class is known to have been declared in this block, but its casm hash
+        // row is missing.
+        state_update
+            .declared_cairo_classes
+            .insert(ClassHash(SIERRA_HASH.0));
+        pathfinder_storage::fake::fill(&storage, &blocks, None);
+
+        let class = || CompiledClass {
+            block_number,
+            hash: ClassHash(SIERRA_HASH.0),
+            definition: CompiledClassDefinition::Sierra {
+                sierra_definition: sierra_definition.clone(),
+                casm_definition: Some(casm_definition.clone()),
+                casm_source: None,
+            },
+        };
+        let mut store = Store {
+            connection: storage.connection().unwrap(),
+            record_casm_provenance: false,
+            store_casm_definitions: true,
+        };
+        let peer = PeerId::random();
+        let error = store.map(&peer, class()).unwrap_err();
+        assert!(format!("{error:#}").contains("Casm hash not found"));
+
+        // The state diff declaring the class is stored after the class arrived.
+        let mut db = storage.connection().unwrap();
+        let tx = db.transaction().unwrap();
+        tx.insert_casm_hash(&SIERRA_HASH, &casm_hash).unwrap();
+        tx.commit().unwrap();
+
+        assert_eq!(store.map(&peer, class()).unwrap(), block_number);
+
+        let db = db.transaction().unwrap();
+        let class_hash = ClassHash(SIERRA_HASH.0);
+        assert_eq!(db.casm_hash(class_hash).unwrap(), Some(casm_hash));
+        assert_eq!(
+            db.class_definition(class_hash).unwrap(),
+            Some(sierra_definition)
+        );
+        assert_eq!(
+            db.casm_definition(class_hash).unwrap(),
+            Some(casm_definition)
+        );
+    }
+
+    mod persist {
+        use pathfinder_storage::TransactionBehavior;
+
+        use super::*;
+
+        fn cairo_class() -> CompiledClass {
+            CompiledClass {
+                block_number: BlockNumber::GENESIS,
+                hash: class_hash!("0x123"),
+                definition: CompiledClassDefinition::Cairo(b"cairo definition".to_vec()),
+            }
+        }
+
+        /// Persisting [cairo_class] replaces its stale definition.
+        fn insert_stale_class(storage: &Storage) {
+            let mut db = storage.connection().unwrap();
+            let db = db.transaction().unwrap();
+            db.insert_cairo_class(class_hash!("0x123"), b"stale definition")
+                .unwrap();
+            db.commit().unwrap();
+        }
+
+        #[tokio::test]
+        async fn busy_database_is_retried() {
+            let storage = pathfinder_storage::StorageBuilder::in_tempdir().unwrap();
+            insert_stale_class(&storage);
+            let class = cairo_class();
+
+            // Another writer holds the database lock while the first attempts fail.
+            let (locked_tx, locked_rx) = std::sync::mpsc::channel();
+            let (release_tx, release_rx) = std::sync::mpsc::channel::<()>();
+            let holder = storage.clone();
+            let holder = std::thread::spawn(move || {
+                let mut db = holder.connection().unwrap();
+                let db = db
+                    .transaction_with_behavior(TransactionBehavior::Exclusive)
+                    .unwrap();
+                locked_tx.send(()).unwrap();
+                release_rx.recv().unwrap();
+                db.commit().unwrap();
+            });
+            locked_rx.recv().unwrap();
+
+            let persisted = tokio::spawn(persist(
+                storage.clone(),
+                vec![PeerData::for_tests(class)],
+                false,
+                true,
+                PersistRetries {
+                    max_retries: 10,
+                    delay: std::time::Duration::from_millis(10),
+                },
+                None,
+            ));
+            tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+            release_tx.send(()).unwrap();
+            holder.join().unwrap();
+
+            let tail = persisted.await.unwrap().unwrap();
+            assert_eq!(tail, BlockNumber::GENESIS);
+
+            let mut db = storage.connection().unwrap();
+            let db = db.transaction().unwrap();
+            assert_eq!(
+                db.class_definition(class_hash!("0x123")).unwrap(),
+                Some(b"cairo definition".to_vec())
+            );
+        }
+
+        #[test]
+        fn retries_are_limited_to_transient_errors() {
+            let storage = pathfinder_storage::StorageBuilder::in_tempdir().unwrap();
+            let mut holder = storage.connection().unwrap();
+            let _lock = holder
+                .transaction_with_behavior(TransactionBehavior::Exclusive)
+                .unwrap();
+            let retries = PersistRetries {
+                max_retries: 2,
+                delay: std::time::Duration::from_millis(1),
+            };
+
+            let mut calls = 0;
+            let mut db = storage.connection().unwrap();
+            let result = persist_with_retries(
+                || {
+                    calls += 1;
+                    db.transaction_with_behavior(TransactionBehavior::Immediate)
+                        .map(|_| ())
+                },
+                retries,
+            );
+            assert!(result.is_err());
+            assert_eq!(calls, retries.max_retries + 1);
+
+            let mut calls = 0;
+            let result = persist_with_retries(
+                || {
+                    calls += 1;
+                    Err(anyhow::anyhow!("Casm hash not found"))
+                },
+                retries,
+            );
+            assert!(result.is_err());
+            assert_eq!(calls, 1);
+        }
+
+        #[tokio::test]
+        async fn fatal_error_is_dead_lettered() {
+            let storage = pathfinder_storage::StorageBuilder::in_memory().unwrap();
+            insert_stale_class(&storage);
+            let dir = tempfile::tempdir().unwrap();
+            let dead_letter = dir.path().join("dead-letter.jsonl");
+            let cairo = PeerData::for_tests(cairo_class());
+            // Not declared anywhere, so its casm hash cannot be recovered.
+            let sierra = PeerData::for_tests(CompiledClass {
+                block_number: BlockNumber::new_or_panic(1),
+                hash: class_hash!("0x456"),
+                definition: CompiledClassDefinition::Sierra {
+                    sierra_definition: b"sierra definition".to_vec(),
+                    casm_definition: Some(b"casm definition".to_vec()),
+                    casm_source: None,
+                },
+            });
+            let sierra_peer = sierra.peer;
+
+            // Sync must not move past the batch, so the error is still returned.
+            persist(
+                storage.clone(),
+                vec![cairo, sierra],
+                false,
+                true,
+                PersistRetries::default(),
+                Some(dead_letter.clone()),
+            )
+            .await
+            .unwrap_err();
+
+            // The whole batch is rolled back and dead-lettered.
+            let mut db = storage.connection().unwrap();
+            let db = db.transaction().unwrap();
+            assert_eq!(
+                db.class_definition(class_hash!("0x123")).unwrap(),
+                Some(b"stale definition".to_vec())
+            );
+
+            let dead_lettered = std::fs::read_to_string(&dead_letter).unwrap();
+            let entries = dead_lettered
+                .lines()
+                .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+                .collect::<Vec<_>>();
+            assert_eq!(entries.len(), 2);
+            assert_eq!(entries[1]["peer"], sierra_peer.to_string());
+            assert!(entries[1]["error"]
+                .as_str()
+                .unwrap()
+                .contains("Casm hash not found"));
+
+            // The entries can be replayed once the problem is fixed.
+            let classes = dead_lettered
+                .lines()
+                .map(|line| serde_json::from_str::<CompiledClass>(line).unwrap().hash)
+                .collect::<Vec<_>>();
+            assert_eq!(classes, vec![class_hash!("0x123"), class_hash!("0x456")]);
+        }
+    }
+
+    #[test]
+    fn trusted_hash_is_not_computed() {
+        use starknet_gateway_test_fixtures::class_definitions::CONTRACT_DEFINITION;
+
+        let peer = PeerId::random();
+        // Not the hash of the definition, computing it results in a mismatch.
+        let class = || {
+            verify_layout_impl(
+                &peer,
+                P2PClassDefinition::Cairo {
+                    block_number: BlockNumber::GENESIS,
+                    definition: CONTRACT_DEFINITION.to_vec(),
+                    hash: class_hash!("0x1234"),
+                },
+            )
+            .unwrap()
+        };
+
+        assert_matches::assert_matches!(
+            verify_hash_impl(&peer, class()),
+            Err(SyncError::BadClassHash(_))
+        );
+        assert_eq!(trust_hash_impl(class()).hash, class_hash!("0x1234"));
+    }
+
+    #[tokio::test]
+    async fn refetch_class_replaces_stored_definition() {
+        use starknet_gateway_test_fixtures::class_definitions::CONTRACT_DEFINITION;
+
+        #[derive(Clone)]
+        struct ClassFgw;
+
+        #[async_trait::async_trait]
+        impl GatewayApi for ClassFgw {
+            async fn pending_class_by_hash(
+                &self,
+                _: ClassHash,
+            ) -> Result<bytes::Bytes, SequencerError> {
+                Ok(bytes::Bytes::from_static(CONTRACT_DEFINITION))
+            }
+        }
+
+        let storage = pathfinder_storage::StorageBuilder::in_memory().unwrap();
+        let class_hash = pathfinder_class_hash::compute_class_hash(CONTRACT_DEFINITION)
+            .unwrap()
+            .hash();
+        {
+            let mut db = storage.connection().unwrap();
+            let db = db.transaction().unwrap();
+            db.insert_state_update(
+                BlockNumber::new_or_panic(1),
+                &StateUpdate::default().with_declared_cairo_class(class_hash),
+            )
+            .unwrap();
+            db.update_cairo_class(class_hash, b"corrupted").unwrap();
+            db.commit().unwrap();
+        }
+
+        refetch_class(
+            storage.clone(),
+            ClassFgw,
+            class_hash,
+            &Default::default(),
+            false,
+        )
+        .await
+        .unwrap();
+
+        let mut db = storage.connection().unwrap();
+        let db = db.transaction().unwrap();
+        assert_eq!(
+            db.class_definition(class_hash).unwrap(),
+            Some(CONTRACT_DEFINITION.to_vec())
+        );
+
+        let undeclared = class_hash!("0x123");
+        assert_matches::assert_matches!(
+            refetch_class(storage, ClassFgw, undeclared, &Default::default(), false).await,
+            Err(SyncError::Fatal(_))
+        );
+    }
+
+    #[test]
+    fn store_replays_dumped_classes() {
+        let blocks = pathfinder_storage::fake::generate::n_blocks(5);
+        let classes = blocks
+            .iter()
+            .flat_map(|block| {
+                let block_number = block.header.header.number;
+                let cairo = block
+                    .cairo_defs
+                    .iter()
+                    .map(move |(hash, definition)| CompiledClass {
+                        block_number,
+                        hash: *hash,
+                        definition: CompiledClassDefinition::Cairo(definition.clone()),
+                    });
+                let sierra = block.sierra_defs.iter().map(
+                    move |(hash, sierra_definition, casm_definition)| CompiledClass {
+                        block_number,
+                        hash: ClassHash(hash.0),
+                        definition: CompiledClassDefinition::Sierra {
+                            sierra_definition: sierra_definition.clone(),
+                            casm_definition: Some(casm_definition.clone()),
+                            casm_source: Some(CasmSource::Compiled),
+                        },
+                    },
+                );
+                cairo.chain(sierra)
+            })
+            .collect::<Vec<_>>();
+        assert!(!classes.is_empty());
+
+        let mut dump = Dump { writer: Vec::new() };
+        let peer = PeerId::random();
+        for class in classes {
+            dump.map(&peer, class).unwrap();
+        }
+
+        // A fresh database which knows about the declarations but is missing all
+        // class definitions.
+        let storage = pathfinder_storage::StorageBuilder::in_memory().unwrap();
+        let mut stripped = blocks.clone();
+        stripped.iter_mut().for_each(|block| {
+            block.cairo_defs.clear();
+            block.sierra_defs.clear();
+        });
+        pathfinder_storage::fake::fill(&storage, &stripped, None);
+
+        let mut store = Store {
+            connection: storage.connection().unwrap(),
+            record_casm_provenance: true,
+            store_casm_definitions: true,
+        };
+        let tail = replay_store(dump.writer.as_slice(), &mut store).unwrap();
+        let expected_tail = blocks
+            .iter()
+            .rev()
+            .find(|block| !block.cairo_defs.is_empty() || !block.sierra_defs.is_empty())
+            .map(|block| block.header.header.number);
+        assert_eq!(tail, expected_tail);
+
+        let mut db = storage.connection().unwrap();
+        let db = db.transaction().unwrap();
+        for block in &blocks {
+            for (hash, definition) in &block.cairo_defs {
+                assert_eq!(
+                    db.class_definition(*hash).unwrap().as_ref(),
+                    Some(definition)
+                );
+            }
+            for (hash, sierra_definition, casm_definition) in &block.sierra_defs {
+                let hash = ClassHash(hash.0);
+                assert_eq!(
+                    db.class_definition(hash).unwrap().as_ref(),
+                    Some(sierra_definition)
+                );
+                assert_eq!(
+                    db.casm_definition(hash).unwrap().as_ref(),
+                    Some(casm_definition)
+                );
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn coalesced_declarations_span_multiple_blocks() {
+        let declarations = [
+            (BlockNumber::new_or_panic(1), vec![class_hash!("0x1")]),
+            (
+                BlockNumber::new_or_panic(2),
+                vec![class_hash!("0x2"), class_hash!("0x3")],
+            ),
+            (BlockNumber::new_or_panic(4), vec![class_hash!("0x4")]),
+            (BlockNumber::new_or_panic(5), vec![class_hash!("0x5")]),
+        ];
+        let expected = || {
+            futures::stream::iter(declarations.clone().map(|(block_number, hashes)| {
+                anyhow::Ok((block_number, hashes.into_iter().collect::<HashSet<_>>()))
+            }))
+        };
+        let class = |block_number: u64, hash: ClassHash| {
+            PeerData::for_tests(Class {
+                block_number: BlockNumber::new_or_panic(block_number),
+                hash,
+                definition: ClassDefinition::Cairo(vec![]),
+            })
+        };
+
+        let groups = coalesce_declarations(expected(), NonZeroUsize::new(3).unwrap())
+            .map(|group| {
+                group
+                    .unwrap()
+                    .into_iter()
+                    .map(|(block_number, _)| block_number.get())
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>()
+            .await;
+        assert_eq!(groups, vec![vec![1, 2], vec![4, 5]]);
+
+        // Chunks from peers don't line up with the groups.
+        let classes = vec![
+            Ok(vec![
+                class(1, class_hash!("0x1")),
+                class(2, class_hash!("0x3")),
+            ]),
+            Ok(vec![
+                class(2, class_hash!("0x2")),
+                class(4, class_hash!("0x4")),
+                class(5, class_hash!("0x5")),
+            ]),
+        ];
+        let batches = verify_declared_at(
+            coalesce_declarations(expected(), NonZeroUsize::new(3).unwrap()).boxed(),
+            futures::stream::iter(classes).boxed(),
+            Default::default(),
+            NonZeroUsize::MIN,
+            Duration::from_secs(60),
+        )
+        .map(|batch| {
+            batch
+                .unwrap()
+                .into_iter()
+                .map(|x| (x.data.block_number.get(), x.data.hash))
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>()
+        .await;
+        assert_eq!(
+            batches,
+            vec![
+                vec![
+                    (1, class_hash!("0x1")),
+                    (2, class_hash!("0x3")),
+                    (2, class_hash!("0x2"))
+                ],
+                vec![(4, class_hash!("0x4")), (5, class_hash!("0x5"))],
+            ]
+        );
+
+        // Membership is still checked per block: a class declared in the same group
+        // but at a different block is rejected, keeping the classes before it.
+        let classes = vec![Ok(vec![
+            class(1, class_hash!("0x1")),
+            class(1, class_hash!("0x2")),
+        ])];
+        let mut batches = verify_declared_at(
+            coalesce_declarations(expected(), NonZeroUsize::new(3).unwrap()).boxed(),
+            futures::stream::iter(classes).boxed(),
+            Default::default(),
+            NonZeroUsize::MIN,
+            Duration::from_secs(60),
+        );
+        let PartialBatch { processed, error } = batches.next().await.unwrap().unwrap_err();
+        assert_eq!(processed.len(), 1);
+        assert_eq!(processed[0].data.hash, class_hash!("0x1"));
+        assert_matches::assert_matches!(error, SyncError::UnexpectedClass(_));
+        assert!(batches.next().await.is_none());
+    }
+
+    #[test]
+    fn circuit_breaker_opens_and_half_opens() {
+        let cooldown = std::time::Duration::from_millis(50);
+        let breaker = FgwCircuitBreaker::new(3, cooldown);
+
+        // A success resets the count of consecutive failures.
+        breaker.record(false);
+        breaker.record(false);
+        breaker.record(true);
+        for _ in 0..2 {
+            assert!(breaker.try_acquire());
+            breaker.record(false);
+        }
+        assert!(breaker.try_acquire());
+        breaker.record(false);
+
+        // Open after the third consecutive failure.
+        assert!(!breaker.try_acquire());
+
+        // Half-open after the cooldown, with a single trial request.
+        thread::sleep(cooldown);
+        assert!(breaker.try_acquire());
+        assert!(!breaker.try_acquire());
+
+        // A failed trial re-opens it right away.
+        breaker.record(false);
+        assert!(!breaker.try_acquire());
+
+        // A successful trial closes it.
+        thread::sleep(cooldown);
+        assert!(breaker.try_acquire());
+        breaker.record(true);
+        assert!(breaker.try_acquire());
+        assert!(breaker.try_acquire());
+    }
+
+    #[test]
+    fn open_circuit_breaker_skips_fgw() {
+        struct Failing;
+
+        impl CasmCompiler for Failing {
+            fn compile(&self, _: &[u8]) -> anyhow::Result<Vec<u8>> {
+                anyhow::bail!("Compilation failed")
+            }
+        }
+
+        let breaker = FgwCircuitBreaker::new(1, Duration::from_secs(60));
+        breaker.record(false);
+        let fgw = FakeFgw {
+            latest: BlockNumber::new_or_panic(10),
+            declared: true,
+        };
+        let class = Class {
+            block_number: BlockNumber::new_or_panic(1),
+            hash: class_hash!("0x1"),
+            definition: ClassDefinition::Sierra(b"sierra".to_vec()),
+        };
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+
+        // The gateway would serve the casm, but isn't asked while the breaker is open.
+        let error = compile_or_fetch_impl(
+            class,
+            &fgw,
+            runtime.handle(),
+            &Failing,
+            Default::default(),
+            &breaker,
+        )
+        .unwrap_err();
+        assert_eq!(error, SyncError::FetchingCasmFailed);
+    }
+
+    mod verify_layout {
+        use super::*;
+
+        #[test]
+        fn garbage_is_a_bad_layout() {
+            let inputs: [&[u8]; 6] = [
+                b"",
+                b"null",
+                b"{}",
+                b"{\"abi\": [}",
+                // Invalid UTF-8 inside a JSON string.
+                b"{\"contract_class_version\": \"\xff\xfe\"}",
+                b"\xc3\x28",
+            ];
+
+            for input in inputs {
+                assert!(!fuzz_verify_layout(input.to_vec(), false));
+                assert!(!fuzz_verify_layout(input.to_vec(), true));
+            }
+        }
+
+        #[test]
+        fn valid_classes_are_accepted() {
+            use starknet_gateway_test_fixtures::class_definitions::{
+                CAIRO_0_11_SIERRA,
+                CONTRACT_DEFINITION,
+            };
+
+            assert!(fuzz_verify_layout(CONTRACT_DEFINITION.to_vec(), false));
+            assert!(fuzz_verify_layout(CAIRO_0_11_SIERRA.to_vec(), true));
+            // The layouts are not interchangeable.
+            assert!(!fuzz_verify_layout(CONTRACT_DEFINITION.to_vec(), true));
+            assert!(!fuzz_verify_layout(CAIRO_0_11_SIERRA.to_vec(), false));
+        }
+
+        #[test]
+        fn nonstandard_abi_is_accepted() {
+            use starknet_gateway_test_fixtures::class_definitions::CAIRO_0_11_SIERRA;
+
+            let with = |f: &dyn Fn(&mut serde_json::Map<String, serde_json::Value>)| {
+                let mut class: serde_json::Value =
+                    serde_json::from_slice(CAIRO_0_11_SIERRA).unwrap();
+                f(class.as_object_mut().unwrap());
+                serde_json::to_vec(&class).unwrap()
+            };
+
+            let inlined = with(&|class| {
+                let abi = serde_json::from_str(class["abi"].as_str().unwrap()).unwrap();
+                class["abi"] = abi;
+            });
+            assert!(fuzz_verify_layout(inlined, true));
+            let null = with(&|class| {
+                class["abi"] = serde_json::Value::Null;
+            });
+            assert!(fuzz_verify_layout(null, true));
+            let missing = with(&|class| {
+                class.remove("abi");
+            });
+            assert!(fuzz_verify_layout(missing, true));
+
+            // The hash relevant fields are still strict.
+            let bad_program = with(&|class| {
+                class["sierra_program"] = "0x1".into();
+            });
+            assert!(!fuzz_verify_layout(bad_program, true));
+        }
+
+        #[test]
+        fn oversized_definition_is_a_bad_layout() {
+            use starknet_gateway_test_fixtures::class_definitions::CAIRO_0_11_SIERRA;
+
+            // A valid class padded with whitespace past the size limit.
+            let mut definition = CAIRO_0_11_SIERRA.to_vec();
+            definition.resize(CLASS_DEFINITION_MAX_ALLOWED_SIZE as usize + 1, b' ');
+            assert!(!fuzz_verify_layout(definition, true));
+
+            let mut definition = CAIRO_0_11_SIERRA.to_vec();
+            definition.resize(CLASS_DEFINITION_MAX_ALLOWED_SIZE as usize, b' ');
+            assert!(fuzz_verify_layout(definition, true));
+        }
+
+        #[test]
+        fn deeply_nested_program_is_a_bad_layout() {
+            let cairo = |program: &str| {
+                let entry_points = r#"{"CONSTRUCTOR":[],"EXTERNAL":[],"L1_HANDLER":[]}"#;
+                format!(r#"{{"abi":[],"program":{program},"entry_points_by_type":{entry_points}}}"#)
+                    .into_bytes()
+            };
+            let nested = |depth: usize| format!("{}{}", "[".repeat(depth), "]".repeat(depth));
+
+            // Brackets inside strings don't count towards the depth.
+            let program = format!(r#"{{"a":"{}\"{}"}}"#, "[".repeat(1000), "{".repeat(1000));
+            assert!(fuzz_verify_layout(cairo(&program), false));
+            // `program` is already nested in the definition object.
+            assert!(fuzz_verify_layout(
+                cairo(&nested(CLASS_DEFINITION_MAX_NESTING_DEPTH - 1)),
+                false
+            ));
+            assert!(!fuzz_verify_layout(
+                cairo(&nested(CLASS_DEFINITION_MAX_NESTING_DEPTH)),
+                false
+            ));
+            assert!(!fuzz_verify_layout(cairo(&nested(100_000)), false));
+        }
+    }
+
+    #[tokio::test]
+    async fn memory_pressure_pauses_pulling_classes() {
+        let (pressure_tx, memory_pressure) = MemoryPressure::channel();
+        pressure_tx.send(true).unwrap();
+
+        let block_number = BlockNumber::new_or_panic(1);
+        let expected = futures::stream::iter([anyhow::Ok(vec![(
+            block_number,
+            HashSet::from([class_hash!("0x1")]),
+        )])]);
+        let classes = futures::stream::iter([Ok(vec![PeerData::for_tests(Class {
+            block_number,
+            hash: class_hash!("0x1"),
+            definition: ClassDefinition::Cairo(vec![]),
+        })])]);
+
+        let mut batches = verify_declared_at(
+            expected.boxed(),
+            classes.boxed(),
+            memory_pressure,
+            NonZeroUsize::MIN,
+            Duration::from_secs(60),
+        );
+
+        let paused =
+            tokio::time::timeout(std::time::Duration::from_millis(100), batches.next()).await;
+        assert!(paused.is_err(), "No classes expected under memory pressure");
+
+        pressure_tx.send(false).unwrap();
+        let batch = tokio::time::timeout(std::time::Duration::from_secs(5), batches.next())
+            .await
+            .expect("Classes to be pulled once pressure subsides")
+            .unwrap()
+            .unwrap();
+        assert_eq!(batch.len(), 1);
+        assert_eq!(batch[0].data.hash, class_hash!("0x1"));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn stalled_class_stream_times_out() {
+        let block_number = BlockNumber::new_or_panic(1);
+        let class = move |hash| {
+            Ok(vec![PeerData::for_tests(Class {
+                block_number,
+                hash,
+                definition: ClassDefinition::Cairo(vec![]),
+            })])
+        };
+        let expected = || {
+            futures::stream::iter([anyhow::Ok(vec![(
+                block_number,
+                HashSet::from([class_hash!("0x1"), class_hash!("0x2"), class_hash!("0x3")]),
+            )])])
+            .boxed()
+        };
+        let timeout = Duration::from_secs(100);
+
+        // Each class arrives well within the timeout, even though all of them
+        // together take longer.
+        let slow =
+            futures::stream::iter([class_hash!("0x1"), class_hash!("0x2"), class_hash!("0x3")])
+                .then(|hash| async move {
+                    tokio::time::sleep(Duration::from_secs(60)).await;
+                    class(hash)
+                });
+        let batch = verify_declared_at(
+            expected(),
+            slow.boxed(),
+            Default::default(),
+            NonZeroUsize::MIN,
+            timeout,
+        )
+        .next()
+        .await
+        .unwrap()
+        .unwrap();
+        assert_eq!(batch.len(), 3);
+
+        // Peers stop delivering after the first class.
+        let stalled =
+            futures::stream::iter([class(class_hash!("0x1"))]).chain(futures::stream::pending());
+        let mut batches = verify_declared_at(
+            expected(),
+            stalled.boxed(),
+            Default::default(),
+            NonZeroUsize::MIN,
+            timeout,
+        );
+        let PartialBatch { processed, error } = batches.next().await.unwrap().unwrap_err();
+        assert_eq!(processed.len(), 1);
+        assert_eq!(processed[0].data.hash, class_hash!("0x1"));
+        assert_eq!(error, SyncError::ClassStreamStalled);
+        assert!(batches.next().await.is_none());
+    }
+
+    /// [verify_declared_at] checks classes against the declarations in storage
+    /// while [VerifyClassHashes] uses the declarations from the state diff. For
+    /// the same block both must accept and reject the same set of classes.
+    #[tokio::test]
+    async fn verify_declared_at_and_verify_class_hashes_agree() {
+        let storage = pathfinder_storage::StorageBuilder::in_memory().unwrap();
+        let blocks = pathfinder_storage::fake::generate::n_blocks(3);
+        pathfinder_storage::fake::fill(&storage, &blocks, None);
+
+        // Returns whether verify_declared_at and VerifyClassHashes accept `classes`.
+        let verify = |block_number: BlockNumber,
+                      declared: DeclaredClasses,
+                      classes: Vec<(ClassHash, bool)>| {
+            let storage = storage.clone();
+            async move {
+                let to_class = |(hash, is_sierra): (ClassHash, bool)| Class {
+                    block_number,
+                    hash,
+                    definition: if is_sierra {
+                        ClassDefinition::Sierra(vec![])
+                    } else {
+                        ClassDefinition::Cairo(vec![])
+                    },
+                };
+                let to_compiled = |(hash, is_sierra): (ClassHash, bool)| CompiledClass {
+                    block_number,
+                    hash,
+                    definition: if is_sierra {
+                        CompiledClassDefinition::Sierra {
+                            sierra_definition: vec![],
+                            casm_definition: Some(vec![]),
+                            casm_source: None,
+                        }
+                    } else {
+                        CompiledClassDefinition::Cairo(vec![])
+                    },
+                };
+
+                let expected = expected_declarations_stream(
+                    storage,
+                    block_number,
+                    block_number,
+                    NonZeroUsize::MIN,
                 )
-                .collect();
-            tracing::trace!(%peer, ?missing, "Expected class definitions are missing");
-            Err(SyncError::ClassDefinitionsDeclarationsMismatch(*peer))
+                .boxed();
+                let chunk = classes
+                    .iter()
+                    .copied()
+                    .map(to_class)
+                    .map(PeerData::for_tests)
+                    .collect::<Vec<_>>();
+                let declared_at_results = flatten_partial_batches(verify_declared_at(
+                    coalesce_declarations(expected, NonZeroUsize::new(1).unwrap()).boxed(),
+                    futures::stream::iter([Ok(chunk)]).boxed(),
+                    Default::default(),
+                    NonZeroUsize::MIN,
+                    Duration::from_secs(60),
+                ))
+                .flat_map(|batch| {
+                    futures::stream::iter(match batch {
+                        Ok(classes) => classes.into_iter().map(Ok).collect(),
+                        Err(e) => vec![Err(e)],
+                    })
+                })
+                .collect::<Vec<_>>()
+                .await;
+                let declared_at_ok = declared_at_results.len() == classes.len()
+                    && declared_at_results.iter().all(Result::is_ok);
+
+                let mut stage = VerifyClassHashes::new(
+                    futures::stream::iter([DeclarationsItem::Block(declared)]).boxed(),
+                    tokio::runtime::Handle::current(),
+                );
+                let input = classes.into_iter().map(to_compiled).collect::<Vec<_>>();
+                let class_hashes_ok =
+                    tokio::task::spawn_blocking(move || stage.map(&PeerId::random(), input))
+                        .await
+                        .unwrap()
+                        .is_ok();
+
+                (declared_at_ok, class_hashes_ok)
+            }
+        };
+
+        let mut checked = 0;
+        for block in blocks
+            .iter()
+            .filter(|block| !block.cairo_defs.is_empty() || !block.sierra_defs.is_empty())
+        {
+            let block_number = block.header.header.number;
+            let declared = block.state_update.as_ref().unwrap().declared_classes();
+            let classes = block
+                .cairo_defs
+                .iter()
+                .map(|(hash, _)| (*hash, false))
+                .chain(
+                    block
+                        .sierra_defs
+                        .iter()
+                        .map(|(hash, ..)| (ClassHash(hash.0), true)),
+                )
+                .collect::<Vec<_>>();
+
+            // Exactly the declared classes.
+            assert_eq!(
+                verify(block_number, declared.clone(), classes.clone()).await,
+                (true, true)
+            );
+
+            // An undeclared class in front of the declared ones.
+            let mut with_extra = vec![(class_hash!("0xdeadbeef"), false)];
+            with_extra.extend(classes.iter().copied());
+            assert_eq!(
+                verify(block_number, declared.clone(), with_extra).await,
+                (false, false)
+            );
+
+            checked += 1;
+        }
+        assert!(checked > 0);
+    }
+
+    mod verify_class_hashes_source_closed {
+        use super::*;
+
+        fn cairo_class(hash: ClassHash) -> CompiledClass {
+            CompiledClass {
+                block_number: BlockNumber::GENESIS,
+                hash,
+                definition: CompiledClassDefinition::Cairo(vec![]),
+            }
+        }
+
+        fn declared(hash: ClassHash) -> DeclarationsItem {
+            DeclarationsItem::Block(DeclaredClasses {
+                cairo: [hash].into_iter().collect(),
+                sierra: Default::default(),
+            })
+        }
+
+        async fn map_all(
+            declarations: Vec<DeclarationsItem>,
+            inputs: Vec<Vec<CompiledClass>>,
+        ) -> Vec<Result<Vec<CompiledClass>, SyncError>> {
+            let mut stage = VerifyClassHashes::new(
+                futures::stream::iter(declarations).boxed(),
+                tokio::runtime::Handle::current(),
+            );
+            tokio::task::spawn_blocking(move || {
+                let peer = PeerId::random();
+                inputs
+                    .into_iter()
+                    .map(|input| stage.map(&peer, input))
+                    .collect()
+            })
+            .await
+            .unwrap()
+        }
+
+        #[tokio::test]
+        async fn after_done_is_a_mismatch() {
+            let results = map_all(
+                vec![declared(class_hash!("0x1")), DeclarationsItem::Done],
+                vec![
+                    vec![cairo_class(class_hash!("0x1"))],
+                    vec![cairo_class(class_hash!("0x2"))],
+                    vec![cairo_class(class_hash!("0x3"))],
+                ],
+            )
+            .await;
+
+            assert!(results[0].is_ok());
+            assert_matches::assert_matches!(
+                results[1],
+                Err(SyncError::ClassDefinitionsDeclarationsMismatch(_))
+            );
+            assert_matches::assert_matches!(
+                results[2],
+                Err(SyncError::ClassDefinitionsDeclarationsMismatch(_))
+            );
+        }
+
+        #[tokio::test]
+        async fn without_done_is_not_fatal() {
+            let results = map_all(
+                vec![declared(class_hash!("0x1"))],
+                vec![
+                    vec![cairo_class(class_hash!("0x1"))],
+                    vec![cairo_class(class_hash!("0x2"))],
+                ],
+            )
+            .await;
+
+            assert!(results[0].is_ok());
+            assert_matches::assert_matches!(results[1], Err(SyncError::DeclarationsSourceClosed));
+        }
+    }
+
+    #[test]
+    fn missing_classes_are_sorted() {
+        let declared_classes = DeclaredClasses {
+            sierra: [
+                (sierra_hash!("0x4"), casm_hash!("0x1")),
+                (sierra_hash!("0x1"), casm_hash!("0x2")),
+            ]
+            .into_iter()
+            .collect(),
+            cairo: [class_hash!("0x3"), class_hash!("0x5"), class_hash!("0x2")]
+                .into_iter()
+                .collect(),
+        };
+
+        // Sierra classes are reported by their class hash, not their casm hash.
+        assert_eq!(
+            missing_classes(declared_classes),
+            vec![
+                class_hash!("0x1"),
+                class_hash!("0x2"),
+                class_hash!("0x3"),
+                class_hash!("0x4"),
+                class_hash!("0x5"),
+            ]
+        );
+    }
+
+    #[test]
+    fn declared_class_count_mismatch() {
+        // The state update of the second block declares one class fewer than
+        // counted.
+        let declared = [(BlockNumber::GENESIS, 1), (BlockNumber::GENESIS + 1, 1)]
+            .into_iter()
+            .collect();
+        let error = check_counts(BlockNumber::GENESIS, [1, 2, 0].into(), &declared).unwrap_err();
+
+        assert_eq!(
+            error.downcast_ref::<DeclaredClassCountMismatch>(),
+            Some(&DeclaredClassCountMismatch {
+                block_number: BlockNumber::GENESIS + 1,
+                counted: 2,
+                declared: 1,
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn expected_declarations_reject_duplicates() {
+        let storage = pathfinder_storage::StorageBuilder::in_memory().unwrap();
+        let blocks = pathfinder_storage::fake::generate::n_blocks(2);
+        pathfinder_storage::fake::fill(&storage, &blocks, None);
+
+        let stream = expected_declarations_stream_impl(
+            storage,
+            BlockNumber::GENESIS,
+            BlockNumber::GENESIS + 1,
+            NonZeroUsize::MIN,
+            |_, block_number| {
+                Ok(Some(if block_number == BlockNumber::GENESIS {
+                    vec![class_hash!("0x1"), class_hash!("0x2")]
+                } else {
+                    vec![class_hash!("0x3"), class_hash!("0x4"), class_hash!("0x3")]
+                }))
+            },
+        );
+        let mut results = stream.collect::<Vec<_>>().await;
+
+        assert_eq!(results.len(), 2);
+        let error = results.pop().unwrap().unwrap_err();
+        assert_eq!(
+            error.downcast_ref::<DuplicateDeclaration>(),
+            Some(&DuplicateDeclaration {
+                block_number: BlockNumber::GENESIS + 1,
+                class_hash: class_hash!("0x3"),
+            })
+        );
+        assert_eq!(
+            results.pop().unwrap().unwrap(),
+            (
+                BlockNumber::GENESIS,
+                [class_hash!("0x1"), class_hash!("0x2")].into()
+            )
+        );
+    }
+
+    #[rstest::rstest]
+    #[case::unbuffered(1)]
+    #[case::buffered(4)]
+    #[tokio::test]
+    async fn expected_declarations_read_ahead_of_slow_consumer(#[case] buffer: usize) {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::sync::Arc;
+
+        let storage = pathfinder_storage::StorageBuilder::in_memory().unwrap();
+        let reads = Arc::new(AtomicUsize::new(0));
+        let reads_clone = reads.clone();
+
+        let mut stream = expected_declarations_stream_impl(
+            storage,
+            BlockNumber::GENESIS,
+            BlockNumber::new_or_panic(100),
+            NonZeroUsize::new(buffer).unwrap(),
+            move |_, block_number| {
+                reads_clone.fetch_add(1, Ordering::SeqCst);
+                Ok(Some(vec![ClassHash(block_number.get().into())]))
+            },
+        )
+        .boxed();
+
+        // The consumer has not asked for anything yet, so the producer fills the
+        // buffer and then blocks on the next block it has read.
+        tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+        assert_eq!(reads.load(Ordering::SeqCst), buffer + 1);
+
+        let (block_number, _) = stream.next().await.unwrap().unwrap();
+        assert_eq!(block_number, BlockNumber::GENESIS);
+    }
+
+    #[test]
+    fn cancelling_mid_batch_stops_verification() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        // A single thread makes the order in which items are picked up
+        // deterministic.
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(1)
+            .build()
+            .unwrap();
+        let cancellation_token = CancellationToken::new();
+        let processed = AtomicUsize::new(0);
+
+        let result = pool.install(|| {
+            map_cancellable((0..100).collect(), &cancellation_token, |item: usize| {
+                if processed.fetch_add(1, Ordering::SeqCst) == 1 {
+                    cancellation_token.cancel();
+                }
+                Ok(item)
+            })
+        });
+
+        assert!(result.is_none());
+        assert_eq!(processed.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn interrupted_write_is_downloaded_again() {
+        let storage = pathfinder_storage::StorageBuilder::in_memory().unwrap();
+        let mut blocks = pathfinder_storage::fake::generate::n_blocks(3);
+        let head = blocks.last().unwrap().header.header.number;
+        // The last Sierra class is the one being written when the node crashes.
+        let block = blocks
+            .iter_mut()
+            .rev()
+            .find(|block| !block.sierra_defs.is_empty())
+            .unwrap();
+        let block_number = block.header.header.number;
+        let (sierra_hash, sierra_definition, _) = block.sierra_defs.pop().unwrap();
+        pathfinder_storage::fake::fill(&storage, &blocks, None);
+
+        let mut db = storage.connection().unwrap();
+        let tx = db.transaction().unwrap();
+        // Pruned CASM definitions are not mistaken for interrupted writes.
+        tx.prune_casm_definitions(BlockNumber::MAX, |_, _, _| true)
+            .unwrap();
+        tx.begin_class_write(ClassHash(sierra_hash.0)).unwrap();
+        tx.update_sierra_definition(&sierra_hash, &sierra_definition)
+            .unwrap();
+        tx.commit().unwrap();
+        drop(db);
+
+        // The Sierra definition hides the missing CASM definition.
+        assert_eq!(next_missing(storage.clone(), head).await.unwrap(), None);
+
+        assert_eq!(
+            recover_interrupted_writes(storage.clone()).await.unwrap(),
+            1
+        );
+        assert_eq!(
+            next_missing(storage.clone(), head).await.unwrap(),
+            Some(block_number)
+        );
+        // Nothing left to recover.
+        assert_eq!(
+            recover_interrupted_writes(storage.clone()).await.unwrap(),
+            0
+        );
+    }
+
+    #[test]
+    fn class_write_marker_outlives_the_write_transaction() {
+        let storage = pathfinder_storage::StorageBuilder::in_memory().unwrap();
+        let mut connection = storage.connection().unwrap();
+        let class_hash = class_hash!("0x123");
+        let definition = CompiledClassDefinition::Cairo(b"cairo definition".to_vec());
+
+        begin_class_writes(&mut connection, [class_hash]).unwrap();
+        // The write transaction is rolled back, as if the node crashed.
+        let tx = connection.transaction().unwrap();
+        persist_impl(
+            &tx,
+            BlockNumber::GENESIS,
+            class_hash,
+            &definition,
+            false,
+            true,
+            |_, _| Ok(None),
+        )
+        .unwrap();
+        drop(tx);
+
+        let tx = connection.transaction().unwrap();
+        assert_eq!(tx.interrupted_class_writes().unwrap(), vec![class_hash]);
+        persist_impl(
+            &tx,
+            BlockNumber::GENESIS,
+            class_hash,
+            &definition,
+            false,
+            true,
+            |_, _| Ok(None),
+        )
+        .unwrap();
+        tx.commit().unwrap();
+
+        let tx = connection.transaction().unwrap();
+        assert!(tx.interrupted_class_writes().unwrap().is_empty());
+    }
+
+    #[test]
+    fn size_based_compiler_routes_by_size() {
+        struct Fixed(&'static [u8]);
+
+        impl CasmCompiler for Fixed {
+            fn compile(&self, _: &[u8]) -> anyhow::Result<Vec<u8>> {
+                Ok(self.0.to_vec())
+            }
+        }
+
+        let compiler =
+            SizeBasedCompiler::new(Arc::new(Fixed(b"small")), Arc::new(Fixed(b"large")), 4);
+
+        assert_eq!(compiler.compile(b"").unwrap(), b"small");
+        assert_eq!(compiler.compile(b"1234").unwrap(), b"small");
+        assert_eq!(compiler.compile(b"12345").unwrap(), b"large");
+    }
+
+    #[test]
+    fn unsupported_casm_version_is_an_error() {
+        struct Newer;
+
+        impl CasmCompiler for Newer {
+            fn compile(&self, _: &[u8]) -> anyhow::Result<Vec<u8>> {
+                Ok(br#"{"compiler_version": "999.0.0"}"#.to_vec())
+            }
+        }
+
+        let fgw = FakeFgw {
+            latest: BlockNumber::new_or_panic(10),
+            declared: true,
+        };
+        let class = Class {
+            block_number: BlockNumber::new_or_panic(1),
+            hash: class_hash!("0x1"),
+            definition: ClassDefinition::Sierra(b"sierra".to_vec()),
+        };
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+
+        let error = compile_or_fetch_impl(
+            class,
+            &fgw,
+            runtime.handle(),
+            &Newer,
+            Default::default(),
+            &FgwCircuitBreaker::new(5, Duration::from_secs(30)),
+        )
+        .unwrap_err();
+        assert_eq!(error, SyncError::UnsupportedCasmVersion(class_hash!("0x1")));
+    }
+
+    #[test]
+    fn unsupported_gateway_casm_version_is_an_error() {
+        struct Failing;
+
+        impl CasmCompiler for Failing {
+            fn compile(&self, _: &[u8]) -> anyhow::Result<Vec<u8>> {
+                anyhow::bail!("Compilation failed")
+            }
+        }
+
+        #[derive(Clone)]
+        struct NewerFgw;
+
+        #[async_trait::async_trait]
+        impl GatewayApi for NewerFgw {
+            async fn casm_by_hash(
+                &self,
+                _: ClassHash,
+                _: BlockId,
+            ) -> Result<bytes::Bytes, SequencerError> {
+                Ok(bytes::Bytes::from_static(
+                    br#"{"compiler_version": "999.0.0"}"#,
+                ))
+            }
         }
+
+        let class = Class {
+            block_number: BlockNumber::new_or_panic(1),
+            hash: class_hash!("0x1"),
+            definition: ClassDefinition::Sierra(b"sierra".to_vec()),
+        };
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+
+        let error = compile_or_fetch_impl(
+            class,
+            &NewerFgw,
+            runtime.handle(),
+            &Failing,
+            Default::default(),
+            &FgwCircuitBreaker::new(5, Duration::from_secs(30)),
+        )
+        .unwrap_err();
+        assert_eq!(error, SyncError::UnsupportedCasmVersion(class_hash!("0x1")));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn process_compiler() {
+        let echo = ProcessCompiler::new("cat".into());
+        assert_eq!(echo.compile(b"sierra").unwrap(), b"sierra");
+
+        let failing = ProcessCompiler::new("false".into());
+        failing.compile(b"sierra").unwrap_err();
     }
 }
@@ -22,10 +22,16 @@ pub(super) enum SyncError {
     BadTransactionHash(PeerId),
     #[error("Incorrect cairo definition")]
     CairoDefinitionError(PeerId),
+    #[error("Casm for class {0} not found on the feeder gateway")]
+    CasmNotFound(ClassHash),
     #[error("Class definitions and declarations mismatch")]
     ClassDefinitionsDeclarationsMismatch(PeerId),
     #[error("Class hash computation failed")]
     ClassHashComputationError(PeerId),
+    #[error("No class definitions received from peers before timing out")]
+    ClassStreamStalled,
+    #[error("Source of expected class declarations closed unexpectedly")]
+    DeclarationsSourceClosed,
     #[error("Contract's class is missing")]
     ContractClassMissing(PeerId),
     #[error("Discontinuity in header chain")]
@@ -60,6 +66,8 @@ pub(super) enum SyncError {
     TransactionCommitmentMismatch(PeerId),
     #[error("Unexpected class definition")]
     UnexpectedClass(PeerId),
+    #[error("Casm for class {0} has an unsupported compiler version")]
+    UnsupportedCasmVersion(ClassHash),
 }
 
 impl PartialEq for SyncError {
@@ -70,6 +78,7 @@ impl PartialEq for SyncError {
             (SyncError::BadClassLayout(x), SyncError::BadClassLayout(y)) => x == y,
             (SyncError::BadHeaderSignature(x), SyncError::BadHeaderSignature(y)) => x == y,
             (SyncError::CairoDefinitionError(x), SyncError::CairoDefinitionError(y)) => x == y,
+            (SyncError::CasmNotFound(x), SyncError::CasmNotFound(y)) => x == y,
             (
                 SyncError::ClassDefinitionsDeclarationsMismatch(x),
                 SyncError::ClassDefinitionsDeclarationsMismatch(y),
@@ -77,6 +86,8 @@ impl PartialEq for SyncError {
             (SyncError::ClassHashComputationError(x), SyncError::ClassHashComputationError(y)) => {
                 x == y
             }
+            (SyncError::ClassStreamStalled, SyncError::ClassStreamStalled) => true,
+            (SyncError::DeclarationsSourceClosed, SyncError::DeclarationsSourceClosed) => true,
             (SyncError::Discontinuity(x), SyncError::Discontinuity(y)) => x == y,
             (SyncError::EventCommitmentMismatch(x), SyncError::EventCommitmentMismatch(y)) => {
                 x == y
@@ -109,6 +120,7 @@ impl PartialEq for SyncError {
                 SyncError::TransactionCommitmentMismatch(y),
             ) => x == y,
             (SyncError::UnexpectedClass(x), SyncError::UnexpectedClass(y)) => x == y,
+            (SyncError::UnsupportedCasmVersion(x), SyncError::UnsupportedCasmVersion(y)) => x == y,
             _ => false,
         }
     }
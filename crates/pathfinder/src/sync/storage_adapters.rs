@@ -2,8 +2,11 @@ use std::collections::VecDeque;
 use std::num::NonZeroUsize;
 
 use anyhow::Context;
-use pathfinder_common::BlockNumber;
-use pathfinder_storage::Storage;
+use p2p::libp2p::PeerId;
+use p2p::sync::client::types::ClassDefinition as P2PClassDefinition;
+use p2p::PeerData;
+use pathfinder_common::{BlockNumber, ClassHash, SierraHash};
+use pathfinder_storage::{Storage, Transaction};
 use tokio::sync::mpsc;
 use tokio_stream::wrappers::ReceiverStream;
 
@@ -81,6 +84,51 @@ pub fn counts_stream(
     })
 }
 
+/// The peer that classes read from local storage are attributed to, see
+/// [stored_class_definition]. It is not the identity of any real peer.
+pub fn local_peer() -> PeerId {
+    static LOCAL_PEER: std::sync::OnceLock<PeerId> = std::sync::OnceLock::new();
+    *LOCAL_PEER.get_or_init(PeerId::random)
+}
+
+/// Reads the stored definition of `class_hash` as if it had been received from
+/// [local_peer], so that the stages verifying classes from other peers can be
+/// reused to check the integrity of the local database.
+///
+/// Returns `None` if the class is not stored or not declared in any block.
+pub fn stored_class_definition(
+    db: &Transaction<'_>,
+    class_hash: ClassHash,
+) -> anyhow::Result<Option<PeerData<P2PClassDefinition>>> {
+    let Some((Some(block_number), definition)) = db
+        .class_definition_with_block_number(class_hash)
+        .context("Querying class definition")?
+    else {
+        return Ok(None);
+    };
+
+    let is_sierra = db
+        .is_sierra(class_hash)
+        .context("Querying class type")?
+        .context("Class type missing")?;
+
+    let class = if is_sierra {
+        P2PClassDefinition::Sierra {
+            block_number,
+            sierra_definition: definition,
+            hash: SierraHash(class_hash.0),
+        }
+    } else {
+        P2PClassDefinition::Cairo {
+            block_number,
+            definition,
+            hash: class_hash,
+        }
+    };
+
+    Ok(Some(PeerData::new(local_peer(), class)))
+}
+
 #[cfg(test)]
 mod tests {
     use futures::StreamExt;
@@ -88,6 +136,7 @@ mod tests {
     use pathfinder_storage::fake::Block;
 
     use super::*;
+    use crate::sync::stream::ProcessStage;
     use crate::sync::{class_definitions, events, state_updates, transactions};
 
     fn expected_transaction_counts(b: Block) -> usize {
@@ -153,6 +202,7 @@ mod tests {
             (transactions::get_counts, expected_transaction_counts),
             (state_updates::get_state_diff_lengths, expected_state_diff_lengths),
             (class_definitions::get_counts, expected_class_definition_counts),
+            (class_definitions::get_counts_checked, expected_class_definition_counts),
             (events::get_counts, expected_event_counts))]
         case: (
             impl Fn(
@@ -198,4 +248,64 @@ mod tests {
             assert!(remainder.is_empty());
         }
     }
+
+    #[test]
+    fn stored_classes_pass_peer_verification() {
+        use pathfinder_common::macro_prelude::*;
+        use starknet_gateway_test_fixtures::class_definitions::{
+            CAIRO_0_11_SIERRA,
+            CONTRACT_DEFINITION,
+        };
+
+        let storage = pathfinder_storage::StorageBuilder::in_memory().unwrap();
+        let mut db = storage.connection().unwrap();
+        let db = db.transaction().unwrap();
+
+        let cairo_hash = pathfinder_class_hash::compute_class_hash(CONTRACT_DEFINITION)
+            .unwrap()
+            .hash();
+        let sierra_hash = pathfinder_class_hash::compute_class_hash(CAIRO_0_11_SIERRA)
+            .unwrap()
+            .hash();
+        let casm_hash = casm_hash!("0x1");
+        db.insert_cairo_class(cairo_hash, CONTRACT_DEFINITION)
+            .unwrap();
+        db.insert_sierra_class(
+            &SierraHash(sierra_hash.0),
+            CAIRO_0_11_SIERRA,
+            &casm_hash,
+            b"casm",
+        )
+        .unwrap();
+        let block_number = BlockNumber::new_or_panic(1);
+        db.insert_state_update(
+            block_number,
+            &StateUpdate::default()
+                .with_declared_cairo_class(cairo_hash)
+                .with_declared_sierra_class(SierraHash(sierra_hash.0), casm_hash),
+        )
+        .unwrap();
+
+        // Not declared in any block.
+        let pending_hash = class_hash!("0x123");
+        db.insert_cairo_class(pending_hash, CONTRACT_DEFINITION)
+            .unwrap();
+        assert!(stored_class_definition(&db, pending_hash)
+            .unwrap()
+            .is_none());
+
+        for class_hash in [cairo_hash, sierra_hash] {
+            let PeerData { peer, data } =
+                stored_class_definition(&db, class_hash).unwrap().unwrap();
+            assert_eq!(peer, local_peer());
+
+            let layout = class_definitions::VerifyLayout
+                .map(&peer, vec![data])
+                .unwrap();
+            let classes = class_definitions::VerifyHash.map(&peer, layout).unwrap();
+            assert_eq!(classes.len(), 1);
+            assert_eq!(classes[0].hash, class_hash);
+            assert_eq!(classes[0].block_number, block_number);
+        }
+    }
 }
@@ -1,8 +1,10 @@
+use std::sync::Arc;
 use std::time::Duration;
 
 use futures::{Future, Stream, StreamExt, TryFutureExt, TryStream, TryStreamExt};
 use p2p::libp2p::PeerId;
 use p2p::PeerData;
+use pathfinder_rpc::ClassSyncStats;
 use tokio::sync::mpsc::Receiver;
 use tokio_stream::wrappers::ReceiverStream;
 
@@ -36,7 +38,7 @@ impl<T: Send + 'static> ChunkSyncReceiver<T> {
         S: ProcessStage<Input = Vec<T>> + Send + 'static,
         S::Output: Send,
     {
-        self.0.pipe_impl(stage, buffer, |x| x.len())
+        self.0.pipe_impl(stage, buffer, None, |x| x.len())
     }
 }
 
@@ -63,7 +65,22 @@ impl<T: Send + 'static> SyncReceiver<T> {
         S: ProcessStage<Input = T> + Send + 'static,
         S::Output: Send,
     {
-        self.pipe_impl(stage, buffer, |_| 1)
+        self.pipe_impl(stage, buffer, None, |_| 1)
+    }
+
+    /// Same as [SyncReceiver::pipe], but additionally records the number of
+    /// items the stage processed and the time it took in `stats`.
+    pub fn pipe_with_stats<S>(
+        self,
+        stage: S,
+        buffer: usize,
+        stats: Arc<ClassSyncStats>,
+    ) -> SyncReceiver<S::Output>
+    where
+        S: ProcessStage<Input = T> + Send + 'static,
+        S::Output: Send,
+    {
+        self.pipe_impl(stage, buffer, Some(stats), |_| 1)
     }
 
     /// A private impl which hides the ugly `count_fn` used to differentiate
@@ -73,6 +90,7 @@ impl<T: Send + 'static> SyncReceiver<T> {
         mut self,
         mut stage: S,
         buffer: usize,
+        stats: Option<Arc<ClassSyncStats>>,
         count_fn: C,
     ) -> SyncReceiver<S::Output>
     where
@@ -105,7 +123,11 @@ impl<T: Send + 'static> SyncReceiver<T> {
                             });
 
                         // Log trace and metrics.
-                        let elements_per_sec = count as f32 / t.elapsed().as_secs_f32();
+                        let elapsed = t.elapsed();
+                        if let (Some(stats), Ok(_)) = (&stats, &output) {
+                            stats.record(S::NAME, count, elapsed);
+                        }
+                        let elements_per_sec = count as f32 / elapsed.as_secs_f32();
                         let queue_fullness = queue_capacity - self.inner.capacity();
                         let input_queue = Fullness(queue_fullness, queue_capacity);
                         tracing::trace!(stage=%S::NAME, %input_queue, %elements_per_sec,
@@ -1,5 +1,7 @@
 use std::collections::{HashMap, HashSet};
+use std::num::NonZeroUsize;
 use std::pin;
+use std::sync::Arc;
 
 use anyhow::Context;
 use futures::stream::BoxStream;
@@ -21,13 +23,15 @@ use pathfinder_common::receipt::Receipt;
 use pathfinder_common::state_update::{DeclaredClasses, StateUpdateData};
 use pathfinder_common::transaction::{Transaction, TransactionVariant};
 use pathfinder_merkle_tree::starknet_state::update_starknet_state;
+use pathfinder_rpc::ClassSyncStats;
 use pathfinder_storage::Storage;
 use starknet_gateway_client::GatewayApi;
 use tokio_stream::wrappers::ReceiverStream;
 
 use super::class_definitions::CompiledClass;
 use super::{state_updates, transactions};
-use crate::sync::class_definitions::{self, ClassWithLayout};
+use crate::monitoring::MemoryPressure;
+use crate::sync::class_definitions::{self, ClassWithLayout, DeclarationsItem};
 use crate::sync::error::SyncError;
 use crate::sync::stream::{ProcessStage, SyncReceiver, SyncResult};
 use crate::sync::{events, headers};
@@ -40,6 +44,14 @@ pub struct Sync<L, P> {
     pub public_key: PublicKey,
     pub block_hash_db: Option<pathfinder_block_hashes::BlockHashDb>,
     pub verify_tree_hashes: bool,
+    pub record_casm_provenance: bool,
+    pub store_casm_definitions: bool,
+    pub casm_compiler: class_definitions::SizeBasedCompiler,
+    pub compile_retries: class_definitions::CompileRetries,
+    pub fgw_breaker: class_definitions::FgwCircuitBreaker,
+    pub memory_pressure: MemoryPressure,
+    pub class_pipeline_buffer: NonZeroUsize,
+    pub class_sync_stats: Arc<ClassSyncStats>,
 }
 
 impl<L, P> Sync<L, P> {
@@ -120,19 +132,31 @@ impl<L, P> Sync<L, P> {
             p2p: self.p2p.clone(),
             declarations: declarations_1,
             start: *next,
+            buffer: self.class_pipeline_buffer,
         }
         .spawn()
-        .pipe(class_definitions::VerifyLayout, 10)
-        .pipe(class_definitions::VerifyHash, 10)
-        .pipe(
-            class_definitions::CompileSierraToCasm::new(fgw, tokio::runtime::Handle::current()),
+        .pipe_with_stats(
+            class_definitions::VerifyLayoutAndHash,
+            10,
+            self.class_sync_stats.clone(),
+        )
+        .pipe_with_stats(
+            class_definitions::CompileSierraToCasm::new(
+                fgw,
+                tokio::runtime::Handle::current(),
+                self.casm_compiler.clone(),
+                self.compile_retries,
+                self.memory_pressure.clone(),
+                self.fgw_breaker.clone(),
+            ),
             10,
+            self.class_sync_stats.clone(),
         )
         .pipe(
-            class_definitions::VerifyClassHashes {
-                declarations: declarations_2,
-                tokio_handle: tokio::runtime::Handle::current(),
-            },
+            class_definitions::VerifyClassHashes::new(
+                declarations_2,
+                tokio::runtime::Handle::current(),
+            ),
             10,
         );
 
@@ -149,6 +173,8 @@ impl<L, P> Sync<L, P> {
                 storage_connection,
                 self.storage.clone(),
                 self.verify_tree_hashes,
+                self.record_casm_provenance,
+                self.store_casm_definitions,
             ),
             10,
         )
@@ -209,7 +235,7 @@ impl<L, P> HeaderSource<L, P> {
 struct StateDiffFanout {
     state_diff: SyncReceiver<StateUpdateData>,
     declarations_1: BoxStream<'static, DeclaredClasses>,
-    declarations_2: BoxStream<'static, DeclaredClasses>,
+    declarations_2: BoxStream<'static, DeclarationsItem>,
 }
 
 impl StateDiffFanout {
@@ -244,10 +270,18 @@ impl StateDiffFanout {
                     return;
                 }
 
-                if d2_tx.send(class_declarations).await.is_err() {
+                if d2_tx
+                    .send(DeclarationsItem::Block(class_declarations))
+                    .await
+                    .is_err()
+                {
                     return;
                 }
             }
+
+            // Only reached if the source ended without an error, so that class hash
+            // verification can tell completion apart from an early closure.
+            let _ = d2_tx.send(DeclarationsItem::Done).await;
         });
 
         Self {
@@ -570,6 +604,8 @@ struct ClassSource<P> {
     p2p: P,
     declarations: BoxStream<'static, DeclaredClasses>,
     start: BlockNumber,
+    /// How many blocks' class definitions may be fetched ahead of verification.
+    buffer: NonZeroUsize,
 }
 
 impl<P> ClassSource<P> {
@@ -577,13 +613,14 @@ impl<P> ClassSource<P> {
     where
         P: Clone + BlockClient + Send + 'static,
     {
-        let (tx, rx) = tokio::sync::mpsc::channel(1);
+        let (tx, rx) = tokio::sync::mpsc::channel(self.buffer.get());
 
         util::task::spawn(async move {
             let Self {
                 p2p,
                 mut declarations,
                 start: mut block_number,
+                buffer: _,
             } = self;
 
             while let Some(declared_classes) = declarations.next().await {
@@ -723,6 +760,10 @@ struct StoreBlock {
     storage: Storage,
     // Verify trie node hashes when loading tries from DB.
     verify_tree_hashes: bool,
+    // Record where each Sierra class' CASM definition came from.
+    record_casm_provenance: bool,
+    // Store CASM definitions, or only the Sierra definitions.
+    store_casm_definitions: bool,
 }
 
 impl StoreBlock {
@@ -730,11 +771,15 @@ impl StoreBlock {
         connection: pathfinder_storage::Connection,
         storage: pathfinder_storage::Storage,
         verify_tree_hashes: bool,
+        record_casm_provenance: bool,
+        store_casm_definitions: bool,
     ) -> Self {
         Self {
             connection,
             storage,
             verify_tree_hashes,
+            record_casm_provenance,
+            store_casm_definitions,
         }
     }
 }
@@ -755,6 +800,11 @@ impl ProcessStage for StoreBlock {
 
         let block_number = header.number;
 
+        class_definitions::begin_class_writes(
+            &mut self.connection,
+            classes.iter().map(|class| class.hash),
+        )?;
+
         let db = self.connection.transaction().with_context(|| {
             format!("Creating database connection, block_number: {block_number}")
         })?;
@@ -827,28 +877,21 @@ impl ProcessStage for StoreBlock {
                  hash,
                  definition,
              }| {
-                match definition {
-                    class_definitions::CompiledClassDefinition::Cairo(cairo) => db
-                        .update_cairo_class(hash, &cairo)
-                        .context("Inserting cairo class definition"),
-                    class_definitions::CompiledClassDefinition::Sierra {
-                        sierra_definition,
-                        casm_definition,
-                    } => {
-                        let sierra_hash = SierraHash(hash.0);
-                        let casm_hash = db
-                            .casm_hash(hash)
-                            .context("Getting casm hash")?
-                            .context("Casm not found")?;
-                        db.update_sierra_class(
-                            &sierra_hash,
-                            &sierra_definition,
-                            &casm_hash,
-                            &casm_definition,
-                        )
-                        .context("Inserting sierra class definition")
-                    }
-                }
+                class_definitions::persist_impl(
+                    &db,
+                    block_number,
+                    hash,
+                    &definition,
+                    self.record_casm_provenance,
+                    self.store_casm_definitions,
+                    |_, sierra_hash| {
+                        Ok(state_diff
+                            .declared_sierra_classes
+                            .get(&sierra_hash)
+                            .copied())
+                    },
+                )
+                .context("Inserting class definition")
             },
         )?;
 
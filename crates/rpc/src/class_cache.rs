@@ -0,0 +1,153 @@
+use std::num::NonZeroUsize;
+use std::sync::{Arc, Mutex};
+
+use cached::{Cached, SizedCache};
+use pathfinder_common::ClassHash;
+
+use crate::RpcVersion;
+
+/// Least recently used cache of serialized class definitions served by
+/// `starknet_getClass` and `starknet_getClassAt`.
+///
+/// The definition of a class never changes for a given class hash, so entries
+/// are never invalidated. The serialized form does depend on the RPC version,
+/// which is why it is part of the key.
+///
+/// Classes are kept as the [serde_json::Value] the RPC response is built from,
+/// so serving a cached class does not parse it again.
+#[derive(Clone, Debug)]
+pub struct ClassCache(Arc<Mutex<SizedCache<(ClassHash, RpcVersion), Arc<SerializedClass>>>>);
+
+/// A class serialized for an RPC version.
+#[derive(Debug, PartialEq)]
+pub struct SerializedClass {
+    pub value: serde_json::Value,
+    /// Length of the class in bytes once written out as JSON.
+    pub len: usize,
+}
+
+impl SerializedClass {
+    pub fn new(value: serde_json::Value) -> Self {
+        let len = value.to_string().len();
+        Self { value, len }
+    }
+}
+
+impl ClassCache {
+    pub fn new(size: NonZeroUsize) -> Self {
+        Self(Arc::new(Mutex::new(SizedCache::with_size(size.get()))))
+    }
+
+    pub fn get(&self, class_hash: ClassHash, version: RpcVersion) -> Option<Arc<SerializedClass>> {
+        let mut cache = self.0.lock().unwrap();
+        cache.cache_get(&(class_hash, version)).cloned()
+    }
+
+    /// Returns the cached class or, on a miss, caches and returns the class
+    /// produced by `load`.
+    pub fn get_or_load<E>(
+        &self,
+        class_hash: ClassHash,
+        version: RpcVersion,
+        load: impl FnOnce() -> Result<SerializedClass, E>,
+    ) -> Result<Arc<SerializedClass>, E> {
+        if let Some(class) = self.get(class_hash, version) {
+            metrics::increment_counter!("rpc_class_cache_total", "result" => "hit");
+            return Ok(class);
+        }
+        metrics::increment_counter!("rpc_class_cache_total", "result" => "miss");
+
+        // The lock is not held while loading, concurrent misses for the same
+        // class load it more than once but never block each other.
+        let class = Arc::new(load()?);
+        let mut cache = self.0.lock().unwrap();
+        cache.cache_set((class_hash, version), class.clone());
+
+        Ok(class)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pathfinder_common::macro_prelude::*;
+
+    use super::*;
+
+    fn class(json: &str) -> SerializedClass {
+        SerializedClass::new(serde_json::from_str(json).unwrap())
+    }
+
+    #[test]
+    fn miss_then_hit() {
+        let cache = ClassCache::new(NonZeroUsize::new(2).unwrap());
+        let hash = class_hash!("0x1");
+
+        let loaded = cache
+            .get_or_load(hash, RpcVersion::V08, || Ok::<_, ()>(class(r#""class""#)))
+            .unwrap();
+        assert_eq!(loaded.value, "class");
+
+        let cached = cache
+            .get_or_load(hash, RpcVersion::V08, || -> Result<_, ()> {
+                panic!("Class should be cached")
+            })
+            .unwrap();
+        assert_eq!(cached.value, "class");
+    }
+
+    #[test]
+    fn len_is_the_serialized_length() {
+        let class = class(r#"{"abi": [1, 2]}"#);
+        assert_eq!(class.len, r#"{"abi":[1,2]}"#.len());
+    }
+
+    #[test]
+    fn failed_load_is_not_cached() {
+        let cache = ClassCache::new(NonZeroUsize::new(2).unwrap());
+        let hash = class_hash!("0x1");
+
+        cache
+            .get_or_load(hash, RpcVersion::V08, || Err("missing"))
+            .unwrap_err();
+
+        assert!(cache.get(hash, RpcVersion::V08).is_none());
+    }
+
+    #[test]
+    fn keyed_by_version() {
+        let cache = ClassCache::new(NonZeroUsize::new(2).unwrap());
+        let hash = class_hash!("0x1");
+
+        cache
+            .get_or_load(hash, RpcVersion::V07, || Ok::<_, ()>(class(r#""v07""#)))
+            .unwrap();
+        let v08 = cache
+            .get_or_load(hash, RpcVersion::V08, || Ok::<_, ()>(class(r#""v08""#)))
+            .unwrap();
+
+        assert_eq!(v08.value, "v08");
+        assert_eq!(cache.get(hash, RpcVersion::V07).unwrap().value, "v07");
+    }
+
+    #[test]
+    fn least_recently_used_is_evicted() {
+        let cache = ClassCache::new(NonZeroUsize::new(2).unwrap());
+        let load = || Ok::<_, ()>(class(r#""class""#));
+
+        cache
+            .get_or_load(class_hash!("0x1"), RpcVersion::V08, load)
+            .unwrap();
+        cache
+            .get_or_load(class_hash!("0x2"), RpcVersion::V08, load)
+            .unwrap();
+        // Touch the first class so that the second one is evicted.
+        cache.get(class_hash!("0x1"), RpcVersion::V08).unwrap();
+        cache
+            .get_or_load(class_hash!("0x3"), RpcVersion::V08, load)
+            .unwrap();
+
+        assert!(cache.get(class_hash!("0x1"), RpcVersion::V08).is_some());
+        assert!(cache.get(class_hash!("0x2"), RpcVersion::V08).is_none());
+        assert!(cache.get(class_hash!("0x3"), RpcVersion::V08).is_some());
+    }
+}
@@ -8,6 +8,7 @@ use pathfinder_storage::Storage;
 use primitive_types::H160;
 use util::percentage::Percentage;
 
+use crate::class_cache::ClassCache;
 pub use crate::jsonrpc::websocket::WebsocketContext;
 use crate::jsonrpc::Notifications;
 use crate::pending::{PendingData, PendingWatcher};
@@ -67,12 +68,26 @@ impl EthContractAddresses {
 pub struct RpcConfig {
     pub batch_concurrency_limit: NonZeroUsize,
     pub get_events_event_filter_block_range_limit: NonZeroUsize,
+    /// Maximum `chunk_size` accepted by `starknet_getEvents`.
+    pub get_events_page_size_limit: NonZeroUsize,
+    /// Maximum total number of keys, across all positions, accepted in a
+    /// `starknet_getEvents` filter.
+    pub get_events_key_filter_size_limit: NonZeroUsize,
     pub fee_estimation_epsilon: Percentage,
     pub versioned_constants_map: VersionedConstantsMap,
     pub native_execution: bool,
     pub native_class_cache_size: NonZeroUsize,
     pub submission_tracker_time_limit: NonZeroU64,
     pub submission_tracker_size_limit: NonZeroUsize,
+    /// Sync fetches CASM from the feeder gateway instead of compiling Sierra
+    /// classes locally.
+    pub fetch_casm_from_fgw: bool,
+    /// Number of serialized classes kept in memory for `starknet_getClass` and
+    /// `starknet_getClassAt`.
+    pub class_cache_size: NonZeroUsize,
+    /// Serialize the block fields of pending receipts as `null` instead of
+    /// omitting them.
+    pub pending_receipt_block_fields_as_null: bool,
 }
 
 #[derive(Clone)]
@@ -91,6 +106,7 @@ pub struct RpcContext {
     pub ethereum: EthereumClient,
     pub config: RpcConfig,
     pub native_class_cache: Option<NativeClassCache>,
+    pub class_cache: ClassCache,
 }
 
 impl RpcContext {
@@ -117,6 +133,7 @@ impl RpcContext {
         } else {
             None
         };
+        let class_cache = ClassCache::new(config.class_cache_size);
         Self {
             cache: Default::default(),
             storage,
@@ -132,6 +149,7 @@ impl RpcContext {
             ethereum,
             config,
             native_class_cache,
+            class_cache,
         }
     }
 
@@ -219,12 +237,23 @@ impl RpcContext {
         let config = RpcConfig {
             batch_concurrency_limit: NonZeroUsize::new(8).unwrap(),
             get_events_event_filter_block_range_limit: NonZeroUsize::new(1000).unwrap(),
+            get_events_page_size_limit: NonZeroUsize::new(
+                crate::method::get_events::EVENT_PAGE_SIZE_LIMIT,
+            )
+            .unwrap(),
+            get_events_key_filter_size_limit: NonZeroUsize::new(
+                crate::method::get_events::EVENT_KEY_FILTER_SIZE_LIMIT,
+            )
+            .unwrap(),
             fee_estimation_epsilon: Percentage::new(10),
             versioned_constants_map: Default::default(),
             native_execution: true,
             native_class_cache_size: NonZeroUsize::new(10).unwrap(),
             submission_tracker_time_limit: NonZeroU64::new(300).unwrap(),
             submission_tracker_size_limit: NonZeroUsize::new(30000).unwrap(),
+            fetch_casm_from_fgw: false,
+            class_cache_size: NonZeroUsize::new(16).unwrap(),
+            pending_receipt_block_fields_as_null: false,
         };
 
         let ethereum =
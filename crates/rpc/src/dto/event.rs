@@ -3,6 +3,10 @@ use pathfinder_common::{ContractAddress, EventData, EventKey};
 use crate::dto;
 use crate::dto::{SerializeForVersion, Serializer};
 
+/// An emitted event.
+///
+/// `keys` and `data` are serialized exactly in emission order; the event hash
+/// commits to this order so they must never be sorted or deduplicated.
 pub struct Event<'a> {
     pub address: &'a ContractAddress,
     pub keys: &'a [EventKey],
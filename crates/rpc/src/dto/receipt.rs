@@ -8,10 +8,13 @@ use super::H256Hex;
 use crate::dto::{SerializeForVersion, Serializer};
 use crate::{dto, RpcVersion};
 
-#[derive(Copy, Clone)]
+#[derive(Clone)]
 pub enum TxnStatus {
     Received,
-    Rejected,
+    Rejected {
+        // Failure reason optional for backward compatibility with gateway.
+        failure_reason: Option<String>,
+    },
     Candidate,
     PreConfirmed,
     AcceptedOnL2,
@@ -55,6 +58,9 @@ pub struct TxnReceiptWithBlockInfo<'a> {
     pub transaction: &'a Transaction,
     pub events: &'a [Event],
     pub finality: TxnFinalityStatus,
+    /// Serialize `block_hash` and `block_number` as `null` instead of omitting
+    /// them for receipts from the pending block.
+    pub pending_block_fields_as_null: bool,
 }
 
 pub struct TxnReceipt<'a> {
@@ -64,6 +70,15 @@ pub struct TxnReceipt<'a> {
     pub finality: TxnFinalityStatus,
 }
 
+/// Serializes the wrapped receipt as `version`, regardless of the version of
+/// the [Serializer] it is given. Lets internal tooling, eg. response caches
+/// keyed by version, produce the JSON of a specific RPC version without going
+/// through the RPC router.
+pub struct ReceiptAtVersion<R> {
+    pub version: RpcVersion,
+    pub receipt: R,
+}
+
 pub struct InvokeTxnReceipt<'a>(pub &'a TxnReceipt<'a>);
 pub struct L1HandlerTxnReceipt<'a>(pub &'a TxnReceipt<'a>);
 pub struct DeclareTxnReceipt<'a>(pub &'a TxnReceipt<'a>);
@@ -87,7 +102,7 @@ impl SerializeForVersion for TxnStatus {
     fn serialize(&self, serializer: Serializer) -> Result<crate::dto::Ok, crate::dto::Error> {
         match self {
             TxnStatus::Received => "RECEIVED",
-            TxnStatus::Rejected => "REJECTED",
+            TxnStatus::Rejected { .. } => "REJECTED",
             TxnStatus::Candidate => "CANDIDATE",
             TxnStatus::PreConfirmed => "PRE_CONFIRMED",
             TxnStatus::AcceptedOnL2 => "ACCEPTED_ON_L2",
@@ -152,8 +167,16 @@ impl SerializeForVersion for TxnReceiptWithBlockInfo<'_> {
             transaction,
             events,
             finality,
+            pending_block_fields_as_null,
         } = self;
 
+        // Only receipts from a pending block lack a block hash, and such a block
+        // cannot have been accepted on L1 yet, see `PendingBlockVariant::finality_status`.
+        debug_assert!(
+            block_hash.is_some() || !matches!(finality, TxnFinalityStatus::AcceptedOnL1),
+            "Pending receipt cannot be accepted on L1"
+        );
+
         let mut serializer = serializer.serialize_struct()?;
 
         serializer.flatten(&TxnReceipt {
@@ -163,13 +186,30 @@ impl SerializeForVersion for TxnReceiptWithBlockInfo<'_> {
             finality: *finality,
         })?;
 
-        serializer.serialize_optional("block_hash", block_hash.cloned())?;
-        serializer.serialize_optional("block_number", *block_number)?;
+        if *pending_block_fields_as_null {
+            serializer.serialize_optional_with_null("block_hash", block_hash.cloned())?;
+            serializer.serialize_optional_with_null("block_number", *block_number)?;
+        } else {
+            serializer.serialize_optional("block_hash", block_hash.cloned())?;
+            serializer.serialize_optional("block_number", *block_number)?;
+        }
 
         serializer.end()
     }
 }
 
+impl<R: SerializeForVersion> ReceiptAtVersion<R> {
+    pub fn to_json(&self) -> Result<crate::dto::Ok, crate::dto::Error> {
+        self.serialize(Serializer::new(self.version))
+    }
+}
+
+impl<R: SerializeForVersion> SerializeForVersion for ReceiptAtVersion<R> {
+    fn serialize(&self, _serializer: Serializer) -> Result<crate::dto::Ok, crate::dto::Error> {
+        self.receipt.serialize(Serializer::new(self.version))
+    }
+}
+
 impl SerializeForVersion for TxnReceipt<'_> {
     fn serialize(&self, serializer: Serializer) -> Result<crate::dto::Ok, crate::dto::Error> {
         match self.transaction.variant.kind() {
@@ -312,11 +352,14 @@ impl SerializeForVersion for CommonReceiptProperties<'_> {
             },
         )?;
         serializer.serialize_field("finality_status", &self.0.finality)?;
+        // Both lists are required by the spec, so they are serialized as empty
+        // arrays rather than skipped when there is nothing to report.
         serializer.serialize_iter(
             "messages_sent",
             self.0.receipt.l2_to_l1_messages.len(),
             &mut self.0.receipt.l2_to_l1_messages.iter().map(MsgToL1),
         )?;
+        // Events, and their keys and data, are passed through in emission order.
         serializer.serialize_iter(
             "events",
             self.0.events.len(),
@@ -401,10 +444,10 @@ impl SerializeForVersion for ExecutionResources<'_> {
 
 impl SerializeForVersion for PriceUnit<'_> {
     fn serialize(&self, serializer: Serializer) -> Result<crate::dto::Ok, crate::dto::Error> {
-        match self.0 {
-            &TransactionVersion::ZERO | &TransactionVersion::ONE | &TransactionVersion::TWO => {
-                "WEI"
-            }
+        // Mask the query bit so that query variants are priced like the version
+        // they are derived from.
+        match self.0.without_query_version() {
+            0..=2 => "WEI",
             _ => "FRI",
         }
         .serialize(serializer)
@@ -457,7 +500,11 @@ mod tests {
 
     #[rstest]
     #[case::received(TxnStatus::Received, "RECEIVED")]
-    #[case::rejected(TxnStatus::Rejected, "REJECTED")]
+    #[case::rejected(TxnStatus::Rejected { failure_reason: None }, "REJECTED")]
+    #[case::rejected_with_reason(
+        TxnStatus::Rejected { failure_reason: Some("Invalid nonce".to_string()) },
+        "REJECTED"
+    )]
     #[case::accepted_on_l2(TxnStatus::AcceptedOnL2, "ACCEPTED_ON_L2")]
     #[case::accepted_on_l1(TxnStatus::AcceptedOnL1, "ACCEPTED_ON_L1")]
     fn txn_status(#[case] input: TxnStatus, #[case] expected: &str) {
@@ -475,6 +522,83 @@ mod tests {
         assert_eq!(encoded, expected);
     }
 
+    #[rstest]
+    #[case::v0(TransactionVersion::ZERO, "WEI")]
+    #[case::v0_query(TransactionVersion::ZERO_WITH_QUERY_VERSION, "WEI")]
+    #[case::v1(TransactionVersion::ONE, "WEI")]
+    #[case::v1_query(TransactionVersion::ONE_WITH_QUERY_VERSION, "WEI")]
+    #[case::v2(TransactionVersion::TWO, "WEI")]
+    #[case::v2_query(TransactionVersion::TWO_WITH_QUERY_VERSION, "WEI")]
+    #[case::v3(TransactionVersion::THREE, "FRI")]
+    #[case::v3_query(TransactionVersion::THREE_WITH_QUERY_VERSION, "FRI")]
+    fn price_unit(#[case] version: TransactionVersion, #[case] expected: &str) {
+        let expected = json!(expected);
+        let encoded = PriceUnit(&version)
+            .serialize(Serializer::default())
+            .unwrap();
+        assert_eq!(encoded, expected);
+    }
+
+    #[test]
+    fn receipt_at_version_overrides_serializer_version() {
+        use pathfinder_common::macro_prelude::*;
+        use pathfinder_common::receipt::{ExecutionResources, L1Gas, L2Gas};
+
+        let transaction = Transaction {
+            hash: transaction_hash!("0x1234"),
+            variant: TransactionVariant::InvokeV3(Default::default()),
+        };
+        let receipt = Receipt {
+            transaction_hash: transaction.hash,
+            actual_fee: fee!("0x64"),
+            execution_resources: ExecutionResources {
+                n_steps: 100,
+                total_gas_consumed: L1Gas {
+                    l1_gas: 10,
+                    l1_data_gas: 20,
+                },
+                l2_gas: L2Gas(30),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let txn_receipt = || TxnReceipt {
+            receipt: &receipt,
+            transaction: &transaction,
+            events: &[],
+            finality: TxnFinalityStatus::AcceptedOnL2,
+        };
+        let at_version = |version| ReceiptAtVersion {
+            version,
+            receipt: txn_receipt(),
+        };
+
+        let v06 = at_version(RpcVersion::V06).to_json().unwrap();
+        let v08 = at_version(RpcVersion::V08).to_json().unwrap();
+
+        for (version, expected) in [(RpcVersion::V06, &v06), (RpcVersion::V08, &v08)] {
+            assert_eq!(
+                &txn_receipt().serialize(Serializer::new(version)).unwrap(),
+                expected
+            );
+            // The version of the serializer is ignored.
+            for serializer_version in [RpcVersion::V07, RpcVersion::V09] {
+                assert_eq!(
+                    &at_version(version)
+                        .serialize(Serializer::new(serializer_version))
+                        .unwrap(),
+                    expected
+                );
+            }
+        }
+
+        assert_eq!(v06["execution_resources"]["steps"], json!(100));
+        assert_eq!(
+            v08["execution_resources"],
+            json!({"l1_gas": 10, "l1_data_gas": 20, "l2_gas": 30})
+        );
+    }
+
     #[rstest]
     #[case::succeeded(TxnExecutionStatus::Succeeded, "SUCCEEDED")]
     #[case::reverted_missing_reason(TxnExecutionStatus::Reverted { reason: None }, "REVERTED")]
@@ -502,4 +626,444 @@ mod tests {
         let encoded = input.serialize(Serializer::default()).unwrap();
         assert_eq!(encoded, expected);
     }
+
+    /// Fields every receipt has, regardless of the transaction type.
+    const COMMON_RECEIPT_FIELDS: [&str; 8] = [
+        "type",
+        "transaction_hash",
+        "actual_fee",
+        "finality_status",
+        "messages_sent",
+        "events",
+        "execution_resources",
+        "execution_status",
+    ];
+
+    #[rstest]
+    #[case::declare_v0(TransactionVariant::DeclareV0(Default::default()), "DECLARE", &[])]
+    #[case::declare_v1(TransactionVariant::DeclareV1(Default::default()), "DECLARE", &[])]
+    #[case::declare_v2(TransactionVariant::DeclareV2(Default::default()), "DECLARE", &[])]
+    #[case::declare_v3(TransactionVariant::DeclareV3(Default::default()), "DECLARE", &[])]
+    #[case::deploy_v0(
+        TransactionVariant::DeployV0(Default::default()),
+        "DEPLOY",
+        &["contract_address"]
+    )]
+    #[case::deploy_v1(
+        TransactionVariant::DeployV1(Default::default()),
+        "DEPLOY",
+        &["contract_address"]
+    )]
+    #[case::deploy_account_v1(
+        TransactionVariant::DeployAccountV1(Default::default()),
+        "DEPLOY_ACCOUNT",
+        &["contract_address"]
+    )]
+    #[case::deploy_account_v3(
+        TransactionVariant::DeployAccountV3(Default::default()),
+        "DEPLOY_ACCOUNT",
+        &["contract_address"]
+    )]
+    #[case::invoke_v0(TransactionVariant::InvokeV0(Default::default()), "INVOKE", &[])]
+    #[case::invoke_v1(TransactionVariant::InvokeV1(Default::default()), "INVOKE", &[])]
+    #[case::invoke_v3(TransactionVariant::InvokeV3(Default::default()), "INVOKE", &[])]
+    #[case::l1_handler(
+        TransactionVariant::L1Handler(Default::default()),
+        "L1_HANDLER",
+        &["message_hash"]
+    )]
+    fn receipt_fields_per_transaction_variant(
+        #[case] variant: TransactionVariant,
+        #[case] expected_type: &str,
+        #[case] type_specific_fields: &[&str],
+        #[values(false, true)] reverted: bool,
+    ) {
+        use std::collections::BTreeSet;
+
+        use pathfinder_common::receipt::ExecutionStatus;
+
+        let transaction = Transaction {
+            hash: Default::default(),
+            variant,
+        };
+        let receipt = Receipt {
+            execution_status: if reverted {
+                ExecutionStatus::Reverted {
+                    reason: "reason".to_owned(),
+                }
+            } else {
+                ExecutionStatus::Succeeded
+            },
+            ..Default::default()
+        };
+        let input = TxnReceipt {
+            receipt: &receipt,
+            transaction: &transaction,
+            events: &[],
+            finality: TxnFinalityStatus::AcceptedOnL2,
+        };
+
+        let encoded = input.serialize(Serializer::default()).unwrap();
+
+        let fields = encoded
+            .as_object()
+            .unwrap()
+            .keys()
+            .map(String::as_str)
+            .collect::<BTreeSet<_>>();
+        let mut expected = COMMON_RECEIPT_FIELDS
+            .iter()
+            .chain(type_specific_fields)
+            .copied()
+            .collect::<BTreeSet<_>>();
+        if reverted {
+            expected.insert("revert_reason");
+        }
+        assert_eq!(fields, expected);
+        assert_eq!(encoded["type"], expected_type);
+    }
+
+    #[rstest]
+    fn empty_events_and_messages_are_empty_arrays(
+        #[values(false, true)] reverted: bool,
+        #[values(RpcVersion::V07, RpcVersion::V08, RpcVersion::V09)] version: RpcVersion,
+    ) {
+        use pathfinder_common::receipt::ExecutionStatus;
+
+        let transaction = Transaction {
+            hash: Default::default(),
+            variant: TransactionVariant::InvokeV1(Default::default()),
+        };
+        let receipt = Receipt {
+            execution_status: if reverted {
+                ExecutionStatus::Reverted {
+                    reason: "reason".to_owned(),
+                }
+            } else {
+                ExecutionStatus::Succeeded
+            },
+            l2_to_l1_messages: vec![],
+            ..Default::default()
+        };
+        let input = TxnReceipt {
+            receipt: &receipt,
+            transaction: &transaction,
+            events: &[],
+            finality: TxnFinalityStatus::AcceptedOnL2,
+        };
+
+        let encoded = input.serialize(Serializer::new(version)).unwrap();
+
+        assert_eq!(encoded["events"], json!([]));
+        assert_eq!(encoded["messages_sent"], json!([]));
+    }
+
+    /// Validates receipts of every transaction variant against the `TXN_RECEIPT`
+    /// schema of the checked-in specification. The schema is a `oneOf` over the
+    /// receipt types, so this also checks the `type` discriminator.
+    #[rstest]
+    fn receipts_match_spec_schema(
+        #[values(
+            TransactionVariant::DeclareV0(Default::default()),
+            TransactionVariant::DeclareV3(Default::default()),
+            TransactionVariant::DeployV0(Default::default()),
+            TransactionVariant::DeployAccountV1(Default::default()),
+            TransactionVariant::DeployAccountV3(Default::default()),
+            TransactionVariant::InvokeV0(Default::default()),
+            TransactionVariant::InvokeV3(Default::default()),
+            TransactionVariant::L1Handler(Default::default())
+        )]
+        variant: TransactionVariant,
+        #[values(false, true)] reverted: bool,
+        #[values(RpcVersion::V06, RpcVersion::V07, RpcVersion::V08, RpcVersion::V09)]
+        version: RpcVersion,
+    ) {
+        use pathfinder_common::receipt::ExecutionStatus;
+
+        let dir = match version {
+            RpcVersion::V06 => "v06",
+            RpcVersion::V07 => "v07",
+            RpcVersion::V08 => "v08",
+            RpcVersion::V09 => "v09",
+            RpcVersion::PathfinderV01 => unreachable!(),
+        };
+        let spec = std::fs::read(format!(
+            "{}/../../specs/rpc/{dir}/starknet_api_openrpc.json",
+            env!("CARGO_MANIFEST_DIR")
+        ))
+        .unwrap();
+        let spec = serde_json::from_slice::<serde_json::Value>(&spec).unwrap();
+        // References in the spec are relative to the document root.
+        let schema = json!({
+            "$ref": "#/components/schemas/TXN_RECEIPT",
+            "components": spec["components"],
+        });
+        let validator = jsonschema::validator_for(&schema).unwrap();
+
+        let transaction = Transaction {
+            hash: Default::default(),
+            variant,
+        };
+        let receipt = Receipt {
+            execution_status: if reverted {
+                ExecutionStatus::Reverted {
+                    reason: "reason".to_owned(),
+                }
+            } else {
+                ExecutionStatus::Succeeded
+            },
+            ..Default::default()
+        };
+        let encoded = TxnReceipt {
+            receipt: &receipt,
+            transaction: &transaction,
+            events: &[],
+            finality: TxnFinalityStatus::AcceptedOnL2,
+        }
+        .serialize(Serializer::new(version))
+        .unwrap();
+
+        let errors = validator
+            .iter_errors(&encoded)
+            .map(|e| format!("{}: {e}", e.instance_path))
+            .collect::<Vec<_>>();
+        assert!(errors.is_empty(), "{encoded:#}\n{errors:#?}");
+    }
+
+    /// Representative receipts for every transaction variant, both succeeded
+    /// and reverted, keyed by a descriptive name.
+    fn golden_receipts() -> Vec<(String, Transaction, Receipt, Vec<Event>)> {
+        use pathfinder_common::macro_prelude::*;
+        use pathfinder_common::receipt::{
+            BuiltinCounters,
+            ExecutionResources,
+            ExecutionStatus,
+            L1Gas,
+            L2Gas,
+            L2ToL1Message,
+        };
+        use pathfinder_common::transaction::*;
+        use pathfinder_common::TransactionIndex;
+        use pathfinder_crypto::Felt;
+
+        let variants = [
+            (
+                "declare_v0",
+                TransactionVariant::DeclareV0(Default::default()),
+            ),
+            (
+                "declare_v1",
+                TransactionVariant::DeclareV1(Default::default()),
+            ),
+            (
+                "declare_v2",
+                TransactionVariant::DeclareV2(Default::default()),
+            ),
+            (
+                "declare_v3",
+                TransactionVariant::DeclareV3(Default::default()),
+            ),
+            (
+                "deploy_v0",
+                TransactionVariant::DeployV0(DeployTransactionV0 {
+                    contract_address: contract_address!("0xdea"),
+                    ..Default::default()
+                }),
+            ),
+            (
+                "deploy_v1",
+                TransactionVariant::DeployV1(DeployTransactionV1 {
+                    contract_address: contract_address!("0xdea"),
+                    ..Default::default()
+                }),
+            ),
+            (
+                "deploy_account_v1",
+                TransactionVariant::DeployAccountV1(DeployAccountTransactionV1 {
+                    contract_address: contract_address!("0xacc"),
+                    ..Default::default()
+                }),
+            ),
+            (
+                "deploy_account_v3",
+                TransactionVariant::DeployAccountV3(DeployAccountTransactionV3 {
+                    contract_address: contract_address!("0xacc"),
+                    ..Default::default()
+                }),
+            ),
+            (
+                "invoke_v0",
+                TransactionVariant::InvokeV0(Default::default()),
+            ),
+            (
+                "invoke_v1",
+                TransactionVariant::InvokeV1(Default::default()),
+            ),
+            (
+                "invoke_v3",
+                TransactionVariant::InvokeV3(Default::default()),
+            ),
+            (
+                "l1_handler",
+                TransactionVariant::L1Handler(L1HandlerTransaction {
+                    contract_address: contract_address!("0x1c0"),
+                    entry_point_selector: entry_point!("0x2"),
+                    nonce: transaction_nonce!("0x3"),
+                    calldata: vec![
+                        call_param!("0xe7e1"),
+                        call_param!("0x4"),
+                        call_param!("0x5"),
+                    ],
+                }),
+            ),
+        ];
+
+        let mut receipts = Vec::new();
+        for (name, variant) in variants {
+            for reverted in [false, true] {
+                let index = receipts.len() as u64;
+                let transaction = Transaction {
+                    hash: TransactionHash(Felt::from_u64(index + 1)),
+                    variant: variant.clone(),
+                };
+
+                // Reverted transactions don't emit events or messages.
+                let (execution_status, messages, events, status) = if reverted {
+                    let status = ExecutionStatus::Reverted {
+                        reason: "Execution failed".to_owned(),
+                    };
+                    (status, vec![], vec![], "reverted")
+                } else {
+                    let messages = vec![L2ToL1Message {
+                        from_address: contract_address!("0x100"),
+                        payload: vec![
+                            l2_to_l1_message_payload_elem!("0x1"),
+                            l2_to_l1_message_payload_elem!("0x2"),
+                        ],
+                        to_address: contract_address!("0xe7e1"),
+                    }];
+                    let events = vec![Event {
+                        from_address: contract_address!("0x100"),
+                        keys: vec![event_key!("0xa")],
+                        data: vec![event_data!("0xb"), event_data!("0xc")],
+                    }];
+                    (ExecutionStatus::Succeeded, messages, events, "succeeded")
+                };
+
+                let receipt = Receipt {
+                    actual_fee: fee!("0x2540be400"),
+                    execution_resources: ExecutionResources {
+                        builtins: BuiltinCounters {
+                            pedersen: 4,
+                            range_check: 12,
+                            ..Default::default()
+                        },
+                        n_steps: 1000,
+                        n_memory_holes: 20,
+                        data_availability: L1Gas {
+                            l1_gas: 0,
+                            l1_data_gas: 128,
+                        },
+                        total_gas_consumed: L1Gas {
+                            l1_gas: 2000,
+                            l1_data_gas: 128,
+                        },
+                        l2_gas: L2Gas(50000),
+                    },
+                    l2_to_l1_messages: messages,
+                    execution_status,
+                    transaction_hash: transaction.hash,
+                    transaction_index: TransactionIndex::new_or_panic(index),
+                };
+
+                receipts.push((format!("{name}_{status}"), transaction, receipt, events));
+            }
+        }
+
+        receipts
+    }
+
+    #[rstest]
+    fn receipts_match_golden_files(
+        #[values(RpcVersion::V06, RpcVersion::V07, RpcVersion::V08, RpcVersion::V09)]
+        version: RpcVersion,
+    ) {
+        let receipts = golden_receipts()
+            .iter()
+            .map(|(name, transaction, receipt, events)| {
+                let encoded = TxnReceipt {
+                    receipt,
+                    transaction,
+                    events,
+                    finality: TxnFinalityStatus::AcceptedOnL2,
+                }
+                .serialize(Serializer::new(version))
+                .unwrap();
+                (name.clone(), encoded)
+            })
+            .collect::<serde_json::Map<_, _>>();
+
+        crate::test_utils::assert_matches_golden_file(
+            &receipts.into(),
+            version,
+            "receipts/golden.json",
+        );
+    }
+
+    #[test]
+    fn event_keys_and_data_preserve_emission_order() {
+        use pathfinder_common::macro_prelude::*;
+
+        let events = vec![
+            Event {
+                from_address: contract_address!("0x10"),
+                keys: vec![
+                    event_key!("0x3"),
+                    event_key!("0x1"),
+                    event_key!("0x2"),
+                    event_key!("0x1"),
+                ],
+                data: vec![
+                    event_data!("0x9"),
+                    event_data!("0x9"),
+                    event_data!("0x0"),
+                    event_data!("0x5"),
+                ],
+            },
+            Event {
+                from_address: contract_address!("0x5"),
+                keys: vec![event_key!("0x2"), event_key!("0x1")],
+                data: vec![],
+            },
+        ];
+        let transaction = Transaction {
+            hash: transaction_hash!("0x1"),
+            variant: TransactionVariant::default(),
+        };
+        let receipt = Receipt::default();
+        let input = TxnReceipt {
+            receipt: &receipt,
+            transaction: &transaction,
+            events: &events,
+            finality: TxnFinalityStatus::AcceptedOnL2,
+        };
+
+        let encoded = CommonReceiptProperties(&input)
+            .serialize(Serializer::default())
+            .unwrap();
+
+        let expected = json!([
+            {
+                "from_address": "0x10",
+                "keys": ["0x3", "0x1", "0x2", "0x1"],
+                "data": ["0x9", "0x9", "0x0", "0x5"],
+            },
+            {
+                "from_address": "0x5",
+                "keys": ["0x2", "0x1"],
+                "data": [],
+            },
+        ]);
+        assert_eq!(encoded["events"], expected);
+    }
 }
@@ -1,6 +1,6 @@
 use anyhow::Context;
 use pathfinder_common::transaction::TransactionVariant;
-use pathfinder_common::{ChainId, StarknetVersion};
+use pathfinder_common::{ChainId, ClassHash, StarknetVersion};
 use pathfinder_executor::types::to_starknet_api_transaction;
 use pathfinder_executor::{ClassInfo, IntoStarkFelt};
 use starknet_api::contract_class::SierraVersion;
@@ -227,6 +227,25 @@ pub(crate) fn map_broadcasted_transaction(
     Ok(tx)
 }
 
+/// Fetches the CASM definition of a Sierra class, recompiling it from the Sierra
+/// definition if storing it was skipped.
+fn stored_or_recompiled_casm(
+    db_transaction: &pathfinder_storage::Transaction<'_>,
+    class_hash: ClassHash,
+) -> anyhow::Result<Vec<u8>> {
+    if db_transaction.casm_definition_is_pruned(class_hash)? {
+        let sierra_definition = db_transaction
+            .class_definition(class_hash)?
+            .context("Fetching class definition")?;
+        return pathfinder_compiler::compile_to_casm(&sierra_definition)
+            .context("Recompiling pruned CASM definition");
+    }
+
+    db_transaction
+        .casm_definition(class_hash)?
+        .context("Fetching class CASM definition")
+}
+
 /// Build the executor transaction out of the gateway one
 /// while pulling necessary data from the DB along the way.
 pub fn compose_executor_transaction(
@@ -265,9 +284,7 @@ pub fn compose_executor_transaction(
             )?)
         }
         TransactionVariant::DeclareV2(tx) => {
-            let casm_definition = db_transaction
-                .casm_definition(tx.class_hash)?
-                .context("Fetching class CASM definition")?;
+            let casm_definition = stored_or_recompiled_casm(db_transaction, tx.class_hash)?;
             let class_definition = db_transaction
                 .class_definition(tx.class_hash)?
                 .context("Fetching class definition")?;
@@ -287,9 +304,7 @@ pub fn compose_executor_transaction(
             )?)
         }
         TransactionVariant::DeclareV3(tx) => {
-            let casm_definition = db_transaction
-                .casm_definition(tx.class_hash)?
-                .context("Fetching class CASM definition")?;
+            let casm_definition = stored_or_recompiled_casm(db_transaction, tx.class_hash)?;
             let class_definition = db_transaction
                 .class_definition(tx.class_hash)?
                 .context("Fetching class definition")?;
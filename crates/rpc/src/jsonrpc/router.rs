@@ -70,6 +70,11 @@ impl RpcRouterBuilder {
         self
     }
 
+    /// Returns the names of the registered methods and subscriptions, sorted.
+    pub fn method_names(&self) -> Vec<&'static str> {
+        sorted_names(&self.method_endpoints, &self.subscription_endpoints)
+    }
+
     pub fn build(self, context: RpcContext) -> RpcRouter {
         // Intentionally leak the hashmaps to give them a static lifetime.
         // Since the router is expected to be long lived, this shouldn't be an issue.
@@ -99,6 +104,11 @@ impl RpcRouter {
         RpcRouterBuilder::new(version)
     }
 
+    /// Returns the names of the registered methods and subscriptions, sorted.
+    pub fn method_names(&self) -> Vec<&'static str> {
+        sorted_names(self.method_endpoints, self.subscription_endpoints)
+    }
+
     /// Parses and executes a request. Returns [None] if its a notification.
     async fn run_request(&self, request: &str) -> Option<RpcResponse> {
         tracing::trace!(%request, "Running request");
@@ -152,6 +162,19 @@ impl RpcRouter {
     }
 }
 
+fn sorted_names(
+    methods: &HashMap<&'static str, Box<dyn RpcMethodEndpoint>>,
+    subscriptions: &HashMap<&'static str, Box<dyn RpcSubscriptionEndpoint>>,
+) -> Vec<&'static str> {
+    let mut names = methods
+        .keys()
+        .chain(subscriptions.keys())
+        .copied()
+        .collect::<Vec<_>>();
+    names.sort_unstable();
+    names
+}
+
 // A slight variation on the axum json extractor.
 fn is_utf8_encoded_json(headers: http::HeaderMap) -> bool {
     let Some(content_type) = headers.get(http::header::CONTENT_TYPE) else {
@@ -1,4 +1,5 @@
 //! Starknet node JSON-RPC related modules.
+mod class_cache;
 pub mod context;
 mod dto;
 mod error;
@@ -18,8 +19,11 @@ pub mod v07;
 pub mod v08;
 pub mod v09;
 
+use std::collections::BTreeMap;
 use std::net::SocketAddr;
 use std::result::Result;
+use std::sync::Arc;
+use std::time::Duration;
 
 use anyhow::Context;
 use axum::error_handling::HandleErrorLayer;
@@ -43,7 +47,7 @@ use crate::types::syncing::Syncing;
 
 const DEFAULT_MAX_CONNECTIONS: usize = 1024;
 
-#[derive(Copy, Clone, Debug, Default, PartialEq, PartialOrd)]
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash, PartialOrd)]
 pub enum RpcVersion {
     V06,
     #[default]
@@ -240,16 +244,56 @@ impl RpcServer {
 
 pub struct SyncState {
     pub status: RwLock<Syncing>,
+    /// Updated by the class definition sync pipeline, see
+    /// [ClassSyncStats::record].
+    pub class_sync_stats: Arc<ClassSyncStats>,
 }
 
 impl Default for SyncState {
     fn default() -> Self {
         Self {
             status: RwLock::new(Syncing::False),
+            class_sync_stats: Default::default(),
         }
     }
 }
 
+/// Cumulative statistics of the class definition sync pipeline stages, keyed
+/// by stage name.
+#[derive(Debug, Default)]
+pub struct ClassSyncStats(std::sync::Mutex<BTreeMap<&'static str, StageStats>>);
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct StageStats {
+    pub items: u64,
+    pub total_duration: Duration,
+}
+
+impl StageStats {
+    /// Average processing duration of a single item.
+    pub fn average_duration(&self) -> Duration {
+        if self.items == 0 {
+            return Duration::ZERO;
+        }
+
+        self.total_duration.div_f64(self.items as f64)
+    }
+}
+
+impl ClassSyncStats {
+    /// Records that `stage` processed `items` in `duration`.
+    pub fn record(&self, stage: &'static str, items: usize, duration: Duration) {
+        let mut stats = self.0.lock().unwrap();
+        let stage = stats.entry(stage).or_default();
+        stage.items += items as u64;
+        stage.total_duration += duration;
+    }
+
+    pub fn snapshot(&self) -> BTreeMap<&'static str, StageStats> {
+        self.0.lock().unwrap().clone()
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, serde::Deserialize)]
 pub(crate) struct SubscriptionId(pub u32);
 
@@ -332,6 +376,49 @@ pub mod test_utils {
         }};
     }
 
+    /// Compares `output` to the golden file at `fixtures/<version>/<file_name>`.
+    ///
+    /// Run the test with `UPDATE_GOLDEN_FILES=1` to write `output` to the golden
+    /// file instead. This is how new golden files are created and how intended
+    /// output changes are accepted.
+    pub fn assert_matches_golden_file(
+        output: &serde_json::Value,
+        version: crate::RpcVersion,
+        file_name: &str,
+    ) {
+        let version_dir = match version {
+            crate::RpcVersion::V06 => "0.6.0",
+            crate::RpcVersion::V07 => "0.7.0",
+            crate::RpcVersion::V08 => "0.8.0",
+            crate::RpcVersion::V09 => "0.9.0",
+            crate::RpcVersion::PathfinderV01 => unreachable!(),
+        };
+        let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("fixtures")
+            .join(version_dir)
+            .join(file_name);
+
+        if std::env::var_os("UPDATE_GOLDEN_FILES").is_some() {
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            let mut contents = serde_json::to_string_pretty(output).unwrap();
+            contents.push('\n');
+            std::fs::write(&path, contents).unwrap();
+            return;
+        }
+
+        let expected = std::fs::read_to_string(&path)
+            .unwrap_or_else(|e| panic!("Reading golden file {}: {e}", path.display()));
+        let expected: serde_json::Value = serde_json::from_str(&expected).unwrap();
+
+        pretty_assertions_sorted::assert_eq!(
+            output,
+            &expected,
+            "\nOutput differs from golden file {}, rerun with UPDATE_GOLDEN_FILES=1 if this \
+             is intended",
+            path.display()
+        );
+    }
+
     // Creates storage for tests
     pub fn setup_storage(trie_prune_mode: pathfinder_storage::TriePruneMode) -> Storage {
         use pathfinder_merkle_tree::contract_state::update_contract_state;
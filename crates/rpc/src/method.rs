@@ -5,6 +5,10 @@ pub mod block_hash_and_number;
 pub mod block_number;
 pub mod call;
 pub mod chain_id;
+pub mod class_storage_info;
+pub mod class_sync_stats;
+pub mod classes_trie_root;
+pub mod compiler_info;
 pub mod estimate_fee;
 pub mod estimate_message_fee;
 pub mod get_block_transaction_count;
@@ -25,6 +29,7 @@ pub mod get_transaction_by_block_id_and_index;
 pub mod get_transaction_by_hash;
 pub mod get_transaction_receipt;
 pub mod get_transaction_status;
+pub mod methods;
 pub mod simulate_transactions;
 pub mod subscribe_events;
 pub mod subscribe_new_heads;
@@ -33,6 +38,7 @@ pub mod subscribe_transaction_status;
 pub mod syncing;
 pub mod trace_block_transactions;
 pub mod trace_transaction;
+pub mod verify_transaction_receipt;
 
 pub use add_declare_transaction::add_declare_transaction;
 pub use add_deploy_account_transaction::add_deploy_account_transaction;
@@ -41,6 +47,10 @@ pub use block_hash_and_number::block_hash_and_number;
 pub use block_number::block_number;
 pub use call::call;
 pub use chain_id::chain_id;
+pub use class_storage_info::class_storage_info;
+pub use class_sync_stats::class_sync_stats;
+pub use classes_trie_root::classes_trie_root;
+pub use compiler_info::compiler_info;
 pub use estimate_fee::estimate_fee;
 pub use estimate_message_fee::estimate_message_fee;
 pub use get_block_transaction_count::get_block_transaction_count;
@@ -61,9 +71,11 @@ pub use get_transaction_by_block_id_and_index::get_transaction_by_block_id_and_i
 pub use get_transaction_by_hash::get_transaction_by_hash;
 pub use get_transaction_receipt::get_transaction_receipt;
 pub use get_transaction_status::get_transaction_status;
+pub use methods::methods;
 pub use simulate_transactions::simulate_transactions;
 pub use syncing::syncing;
 pub use trace_block_transactions::trace_block_transactions;
 pub use trace_transaction::trace_transaction;
+pub use verify_transaction_receipt::verify_transaction_receipt;
 
 const REORG_SUBSCRIPTION_NAME: &str = "starknet_subscriptionReorg";
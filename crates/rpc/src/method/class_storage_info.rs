@@ -0,0 +1,162 @@
+use anyhow::Context;
+use pathfinder_common::ClassHash;
+use pathfinder_storage::ClassStorageInfo;
+
+use crate::context::RpcContext;
+
+crate::error::generate_rpc_error_subset!(Error: ClassHashNotFound);
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Input {
+    pub class_hash: ClassHash,
+}
+
+impl crate::dto::DeserializeForVersion for Input {
+    fn deserialize(value: crate::dto::Value) -> Result<Self, serde_json::Error> {
+        value.deserialize_map(|value| {
+            Ok(Self {
+                class_hash: ClassHash(value.deserialize("class_hash")?),
+            })
+        })
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Output(ClassStorageInfo);
+
+/// Get the size of the stored definitions of a class, for sizing disks.
+///
+/// Definitions are stored compressed, so the sizes reported are the
+/// compressed ones. Definitions that have not been downloaded yet, or CASM
+/// definitions that were pruned, are reported as `null`.
+pub async fn class_storage_info(context: RpcContext, input: Input) -> Result<Output, Error> {
+    let span = tracing::Span::current();
+    util::task::spawn_blocking(move |_| {
+        let _g = span.enter();
+        let mut db = context
+            .storage
+            .connection()
+            .context("Opening database connection")?;
+
+        let tx = db.transaction().context("Creating database transaction")?;
+
+        let info = tx
+            .class_storage_info(input.class_hash)
+            .context("Querying class storage info")?
+            .ok_or(Error::ClassHashNotFound)?;
+
+        Ok(Output(info))
+    })
+    .await
+    .context("Joining blocking task")?
+}
+
+struct StoredDefinition {
+    size: u64,
+    compression: &'static str,
+}
+
+impl crate::dto::SerializeForVersion for StoredDefinition {
+    fn serialize(
+        &self,
+        serializer: crate::dto::Serializer,
+    ) -> Result<crate::dto::Ok, crate::dto::Error> {
+        let mut serializer = serializer.serialize_struct()?;
+        serializer.serialize_field("size", &self.size)?;
+        serializer.serialize_field("compression", &self.compression)?;
+        serializer.end()
+    }
+}
+
+impl crate::dto::SerializeForVersion for Output {
+    fn serialize(
+        &self,
+        serializer: crate::dto::Serializer,
+    ) -> Result<crate::dto::Ok, crate::dto::Error> {
+        let definition = self.0.definition_size.map(|size| StoredDefinition {
+            size,
+            compression: if self.0.dictionary_compressed {
+                "ZSTD_DICTIONARY"
+            } else {
+                "ZSTD"
+            },
+        });
+        let casm_definition = self.0.casm_size.map(|size| StoredDefinition {
+            size,
+            compression: "ZSTD",
+        });
+
+        let mut serializer = serializer.serialize_struct()?;
+        serializer.serialize_optional_with_null("definition", definition)?;
+        serializer.serialize_optional_with_null("casm_definition", casm_definition)?;
+        serializer.end()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use assert_matches::assert_matches;
+    use pathfinder_common::macro_prelude::*;
+    use pathfinder_storage::StorageBuilder;
+    use starknet_gateway_test_fixtures::class_definitions::{
+        CAIRO_1_1_0_BALANCE_CASM_JSON,
+        CAIRO_1_1_0_BALANCE_SIERRA_JSON,
+    };
+
+    use super::*;
+    use crate::dto::SerializeForVersion;
+    use crate::RpcVersion;
+
+    #[tokio::test]
+    async fn sierra_class() {
+        let storage = StorageBuilder::in_memory().unwrap();
+        let mut db = storage.connection().unwrap();
+        let tx = db.transaction().unwrap();
+        tx.insert_sierra_class(
+            &sierra_hash_bytes!(b"sierra"),
+            CAIRO_1_1_0_BALANCE_SIERRA_JSON,
+            &casm_hash_bytes!(b"casm"),
+            CAIRO_1_1_0_BALANCE_CASM_JSON,
+        )
+        .unwrap();
+        tx.commit().unwrap();
+        let context = RpcContext::for_tests().with_storage(storage);
+
+        let input = Input {
+            class_hash: class_hash_bytes!(b"sierra"),
+        };
+        let output = class_storage_info(context, input)
+            .await
+            .unwrap()
+            .serialize(crate::dto::Serializer::new(RpcVersion::V08))
+            .unwrap();
+
+        // Storage compresses definitions at this level.
+        let compressed_size =
+            |definition: &[u8]| zstd::bulk::compress(definition, 10).unwrap().len() as u64;
+        assert_eq!(
+            output,
+            serde_json::json!({
+                "definition": {
+                    "size": compressed_size(CAIRO_1_1_0_BALANCE_SIERRA_JSON),
+                    "compression": "ZSTD",
+                },
+                "casm_definition": {
+                    "size": compressed_size(CAIRO_1_1_0_BALANCE_CASM_JSON),
+                    "compression": "ZSTD",
+                },
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn unknown_class() {
+        let input = Input {
+            class_hash: class_hash_bytes!(b"unknown"),
+        };
+        let error = class_storage_info(RpcContext::for_tests(), input)
+            .await
+            .unwrap_err();
+        assert_matches!(error, Error::ClassHashNotFound);
+    }
+}
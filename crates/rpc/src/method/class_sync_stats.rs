@@ -0,0 +1,89 @@
+use std::collections::BTreeMap;
+
+use crate::context::RpcContext;
+use crate::StageStats;
+
+crate::error::generate_rpc_error_subset!(Error);
+
+pub struct Output(BTreeMap<&'static str, StageStats>);
+
+/// Get the number of items processed by each class definition sync stage and
+/// the average time it took per item, since the node was started.
+pub async fn class_sync_stats(context: RpcContext) -> Result<Output, Error> {
+    Ok(Output(context.sync_status.class_sync_stats.snapshot()))
+}
+
+impl crate::dto::SerializeForVersion for Output {
+    fn serialize(
+        &self,
+        serializer: crate::dto::Serializer,
+    ) -> Result<crate::dto::Ok, crate::dto::Error> {
+        let mut serializer = serializer.serialize_struct()?;
+        for (stage, stats) in &self.0 {
+            serializer.serialize_field(*stage, stats)?;
+        }
+        serializer.end()
+    }
+}
+
+impl crate::dto::SerializeForVersion for StageStats {
+    fn serialize(
+        &self,
+        serializer: crate::dto::Serializer,
+    ) -> Result<crate::dto::Ok, crate::dto::Error> {
+        let average_duration_us =
+            u64::try_from(self.average_duration().as_micros()).unwrap_or(u64::MAX);
+
+        let mut serializer = serializer.serialize_struct()?;
+        serializer.serialize_field("items_processed", &self.items)?;
+        serializer.serialize_field("average_duration_us", &average_duration_us)?;
+        serializer.end()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use serde_json::json;
+
+    use super::*;
+    use crate::dto::SerializeForVersion;
+    use crate::RpcVersion;
+
+    #[tokio::test]
+    async fn stats_update_after_processing() {
+        let context = RpcContext::for_tests();
+
+        let output = class_sync_stats(context.clone())
+            .await
+            .unwrap()
+            .serialize(crate::dto::Serializer::new(RpcVersion::V08))
+            .unwrap();
+        assert_eq!(output, json!({}));
+
+        let stats = &context.sync_status.class_sync_stats;
+        stats.record("Class::VerifyLayout", 1, Duration::from_micros(10));
+        stats.record("Class::VerifyLayout", 1, Duration::from_micros(30));
+        stats.record("Class::Persist", 4, Duration::from_micros(100));
+
+        let output = class_sync_stats(context)
+            .await
+            .unwrap()
+            .serialize(crate::dto::Serializer::new(RpcVersion::V08))
+            .unwrap();
+        assert_eq!(
+            output,
+            json!({
+                "Class::VerifyLayout": {
+                    "items_processed": 2,
+                    "average_duration_us": 20,
+                },
+                "Class::Persist": {
+                    "items_processed": 4,
+                    "average_duration_us": 25,
+                },
+            })
+        );
+    }
+}
@@ -0,0 +1,220 @@
+use anyhow::Context;
+use pathfinder_common::{BlockHash, BlockId, BlockNumber, ClassCommitment};
+
+use crate::context::RpcContext;
+
+crate::error::generate_rpc_error_subset!(
+    Error: BlockNotFound,
+    ProofMissing,
+    StorageProofNotSupported
+);
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Input {
+    pub block_id: BlockId,
+}
+
+impl crate::dto::DeserializeForVersion for Input {
+    fn deserialize(value: crate::dto::Value) -> Result<Self, serde_json::Error> {
+        value.deserialize_map(|value| {
+            Ok(Self {
+                block_id: value.deserialize("block_id")?,
+            })
+        })
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Output {
+    block_hash: BlockHash,
+    block_number: BlockNumber,
+    classes_tree_root: ClassCommitment,
+}
+
+/// Get the root of the class trie at a block, as committed to by the block's
+/// `class_commitment`.
+///
+/// This is the same root `starknet_getStorageProof` returns as part of its
+/// `global_roots`, without having to build any proofs.
+pub async fn classes_trie_root(context: RpcContext, input: Input) -> Result<Output, Error> {
+    let block_id = match input.block_id {
+        // The class trie is only updated once a block is closed.
+        BlockId::Pending => return Err(Error::ProofMissing),
+        other => other.to_finalized_or_panic(),
+    };
+
+    let span = tracing::Span::current();
+    util::task::spawn_blocking(move |_| {
+        let _g = span.enter();
+        let mut db = context
+            .storage
+            .connection()
+            .context("Opening database connection")?;
+
+        let tx = db.transaction().context("Creating database transaction")?;
+
+        let header = tx
+            .block_header(block_id)
+            .context("Fetching block header")?
+            .ok_or(Error::BlockNotFound)?;
+
+        let root_index = tx
+            .class_root_index(header.number)
+            .context("Querying class root index")?;
+
+        let classes_tree_root = match root_index {
+            Some(root_index) => tx
+                .class_trie_node_hash(root_index)
+                .context("Querying class root hash")?
+                .map(ClassCommitment)
+                .context("Class root hash missing")?,
+            // The root of this block may have been pruned.
+            None if tx.trie_pruning_enabled() => return Err(Error::StorageProofNotSupported),
+            // No classes have been declared up to and including this block.
+            None => ClassCommitment::ZERO,
+        };
+
+        Ok(Output {
+            block_hash: header.hash,
+            block_number: header.number,
+            classes_tree_root,
+        })
+    })
+    .await
+    .context("Joining blocking task")?
+}
+
+impl crate::dto::SerializeForVersion for Output {
+    fn serialize(
+        &self,
+        serializer: crate::dto::Serializer,
+    ) -> Result<crate::dto::Ok, crate::dto::Error> {
+        let mut serializer = serializer.serialize_struct()?;
+        serializer.serialize_field("block_hash", &self.block_hash)?;
+        serializer.serialize_field("block_number", &self.block_number)?;
+        serializer.serialize_field("classes_tree_root", &self.classes_tree_root)?;
+        serializer.end()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pathfinder_common::macro_prelude::*;
+    use pathfinder_common::{BlockHeader, StorageCommitment};
+    use pathfinder_merkle_tree::ClassCommitmentTree;
+    use pathfinder_storage::StorageBuilder;
+
+    use super::*;
+
+    /// Block 0 declares no classes, block 1 declares a single one and block 2
+    /// leaves the class trie unchanged.
+    fn setup() -> RpcContext {
+        let storage = StorageBuilder::in_memory().unwrap();
+        let mut db = storage.connection().unwrap();
+        let tx = db.transaction().unwrap();
+
+        let genesis = BlockHeader::builder()
+            .calculated_state_commitment(StorageCommitment::ZERO, ClassCommitment::ZERO)
+            .finalize_with_hash(block_hash_bytes!(b"block 0"));
+        tx.insert_block_header(&genesis).unwrap();
+
+        let block1 = BlockNumber::GENESIS + 1;
+        let casm_hash = casm_hash_bytes!(b"casm hash");
+        let leaf_hash = pathfinder_common::calculate_class_commitment_leaf_hash(casm_hash);
+        tx.insert_class_commitment_leaf(block1, &leaf_hash, &casm_hash)
+            .unwrap();
+        let mut tree = ClassCommitmentTree::load(&tx, block1).unwrap();
+        tree.set(sierra_hash_bytes!(b"sierra hash"), leaf_hash)
+            .unwrap();
+        let (class_commitment, trie_update) = tree.commit().unwrap();
+        let root_index = tx.insert_class_trie(&trie_update, block1).unwrap();
+        tx.insert_class_root(block1, root_index).unwrap();
+
+        let header1 = genesis
+            .child_builder()
+            .calculated_state_commitment(StorageCommitment::ZERO, class_commitment)
+            .finalize_with_hash(block_hash_bytes!(b"block 1"));
+        tx.insert_block_header(&header1).unwrap();
+
+        let header2 = header1
+            .child_builder()
+            .calculated_state_commitment(StorageCommitment::ZERO, class_commitment)
+            .finalize_with_hash(block_hash_bytes!(b"block 2"));
+        tx.insert_block_header(&header2).unwrap();
+
+        tx.commit().unwrap();
+
+        RpcContext::for_tests().with_storage(storage)
+    }
+
+    #[tokio::test]
+    async fn root_matches_class_commitment() {
+        let context = setup();
+
+        for block_number in 0..3 {
+            let block_number = BlockNumber::new_or_panic(block_number);
+            let header = context
+                .storage
+                .connection()
+                .unwrap()
+                .transaction()
+                .unwrap()
+                .block_header(block_number.into())
+                .unwrap()
+                .unwrap();
+
+            let output = classes_trie_root(
+                context.clone(),
+                Input {
+                    block_id: BlockId::Number(block_number),
+                },
+            )
+            .await
+            .unwrap();
+
+            assert_eq!(
+                output,
+                Output {
+                    block_hash: header.hash,
+                    block_number,
+                    classes_tree_root: header.class_commitment,
+                }
+            );
+        }
+
+        // Sanity check that the test covers a non-empty trie.
+        let output = classes_trie_root(
+            context,
+            Input {
+                block_id: BlockId::Latest,
+            },
+        )
+        .await
+        .unwrap();
+        assert_ne!(output.classes_tree_root, ClassCommitment::ZERO);
+    }
+
+    #[tokio::test]
+    async fn pending_block() {
+        let context = setup();
+        let input = Input {
+            block_id: BlockId::Pending,
+        };
+
+        let output = classes_trie_root(context, input).await;
+
+        assert_matches::assert_matches!(output, Err(Error::ProofMissing));
+    }
+
+    #[tokio::test]
+    async fn block_not_found() {
+        let context = setup();
+        let input = Input {
+            block_id: BlockId::Number(BlockNumber::MAX),
+        };
+
+        let output = classes_trie_root(context, input).await;
+
+        assert_matches::assert_matches!(output, Err(Error::BlockNotFound));
+    }
+}
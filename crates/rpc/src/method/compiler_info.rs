@@ -0,0 +1,86 @@
+use crate::context::RpcContext;
+
+crate::error::generate_rpc_error_subset!(Error);
+
+pub struct Output {
+    legacy_sierra_versions: Vec<String>,
+    latest_sierra_version: String,
+    compiler_version: String,
+    compiles_locally: bool,
+    gateway_fallback: bool,
+}
+
+/// Get the Sierra versions this node can compile to CASM, and whether it
+/// relies on the feeder gateway for CASM it cannot compile.
+pub async fn compiler_info(context: RpcContext) -> Result<Output, Error> {
+    let compiles_locally = !context.config.fetch_casm_from_fgw;
+
+    Ok(Output {
+        legacy_sierra_versions: pathfinder_compiler::LEGACY_SIERRA_VERSIONS
+            .into_iter()
+            .map(ToOwned::to_owned)
+            .collect(),
+        latest_sierra_version: pathfinder_compiler::latest_sierra_version(),
+        compiler_version: pathfinder_compiler::compiler_version(),
+        compiles_locally,
+        // If local compilation fails sync falls back to fetching the CASM from
+        // the feeder gateway, which is also where it comes from otherwise.
+        gateway_fallback: true,
+    })
+}
+
+impl crate::dto::SerializeForVersion for Output {
+    fn serialize(
+        &self,
+        serializer: crate::dto::Serializer,
+    ) -> Result<crate::dto::Ok, crate::dto::Error> {
+        let mut serializer = serializer.serialize_struct()?;
+        serializer.serialize_iter(
+            "legacy_sierra_versions",
+            self.legacy_sierra_versions.len(),
+            &mut self.legacy_sierra_versions.iter().cloned(),
+        )?;
+        serializer.serialize_field("latest_sierra_version", &self.latest_sierra_version)?;
+        serializer.serialize_field("compiler_version", &self.compiler_version)?;
+        serializer.serialize_field("compiles_locally", &self.compiles_locally)?;
+        serializer.serialize_field("gateway_fallback", &self.gateway_fallback)?;
+        serializer.end()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dto::SerializeForVersion;
+    use crate::RpcVersion;
+
+    #[tokio::test]
+    async fn reports_compiler_versions() {
+        let context = RpcContext::for_tests();
+        let output = compiler_info(context).await.unwrap();
+
+        let output = output
+            .serialize(crate::dto::Serializer::new(RpcVersion::V08))
+            .unwrap();
+        assert_eq!(
+            output,
+            serde_json::json!({
+                "legacy_sierra_versions": ["0.1.0", "1.0.0", "1.1.0"],
+                "latest_sierra_version": pathfinder_compiler::latest_sierra_version(),
+                "compiler_version": pathfinder_compiler::compiler_version(),
+                "compiles_locally": true,
+                "gateway_fallback": true,
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn fetching_casm_from_gateway_disables_local_compilation() {
+        let mut context = RpcContext::for_tests();
+        context.config.fetch_casm_from_fgw = true;
+
+        let output = compiler_info(context).await.unwrap();
+        assert!(!output.compiles_locally);
+        assert!(output.gateway_fallback);
+    }
+}
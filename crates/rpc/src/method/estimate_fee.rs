@@ -1088,6 +1088,117 @@ mod tests {
         pretty_assertions_sorted::assert_eq!(expected_json, output_json);
     }
 
+    #[tokio::test]
+    async fn historical_blocks_use_their_own_state() {
+        let starknet_version = StarknetVersion::new(0, 13, 5, 0);
+        let (context, block1, _account_contract_address, _universal_deployer_address) =
+            crate::test_setup::test_context_with_starknet_version(starknet_version).await;
+
+        // Add a block on top which only changes gas prices. Estimating at the
+        // older block must not pick these up.
+        let block2 = BlockHeader::child_builder(&block1)
+            .timestamp(BlockTimestamp::new_or_panic(2))
+            .eth_l1_gas_price(block1.eth_l1_gas_price)
+            .strk_l1_gas_price(block1.strk_l1_gas_price)
+            .eth_l1_data_gas_price(block1.eth_l1_data_gas_price)
+            .strk_l1_data_gas_price(GasPrice(3))
+            .eth_l2_gas_price(block1.eth_l2_gas_price)
+            .strk_l2_gas_price(GasPrice(2))
+            .l1_da_mode(block1.l1_da_mode)
+            .sequencer_address(block1.sequencer_address)
+            .starknet_version(starknet_version)
+            .finalize_with_hash(block_hash!("0xb02"));
+        {
+            let mut db = context.storage.connection().unwrap();
+            let tx = db.transaction().unwrap();
+            tx.insert_block_header(&block2).unwrap();
+            tx.insert_state_update(
+                block2.number,
+                &StateUpdate::default().with_block_hash(block2.hash),
+            )
+            .unwrap();
+            tx.commit().unwrap();
+        }
+
+        let deploy_account = crate::types::request::BroadcastedDeployAccountTransaction::V3(
+            BroadcastedDeployAccountTransactionV3 {
+                version: TransactionVersion::THREE,
+                signature: vec![],
+                nonce: transaction_nonce!("0x0"),
+                resource_bounds: ResourceBounds {
+                    l1_gas: ResourceBound {
+                        max_amount: ResourceAmount(0),
+                        max_price_per_unit: ResourcePricePerUnit(0),
+                    },
+                    l2_gas: ResourceBound {
+                        max_amount: ResourceAmount(0),
+                        max_price_per_unit: ResourcePricePerUnit(0),
+                    },
+                    l1_data_gas: Some(ResourceBound {
+                        max_amount: ResourceAmount(0),
+                        max_price_per_unit: ResourcePricePerUnit(0),
+                    }),
+                },
+                tip: Tip(0),
+                paymaster_data: vec![],
+                nonce_data_availability_mode: DataAvailabilityMode::L1,
+                fee_data_availability_mode: DataAvailabilityMode::L1,
+                contract_address_salt: contract_address_salt!("0x1"),
+                constructor_calldata: vec![call_param!("0xdeadbeef")],
+                class_hash: crate::test_setup::OPENZEPPELIN_ACCOUNT_CLASS_HASH,
+            },
+        );
+
+        let estimate_at = |block_id| {
+            let input = Input {
+                request: vec![BroadcastedTransaction::DeployAccount(deploy_account.clone())],
+                simulation_flags: vec![],
+                block_id,
+            };
+            super::estimate_fee(context.clone(), input, RPC_VERSION)
+        };
+
+        let at_block1 = estimate_at(BlockId::Number(block1.number)).await.unwrap();
+        let at_block2 = estimate_at(BlockId::Number(block2.number)).await.unwrap();
+        let at_latest = estimate_at(BlockId::Latest).await.unwrap();
+
+        let at_block1 = at_block1
+            .serialize(Serializer {
+                version: RpcVersion::V08,
+            })
+            .unwrap();
+        let at_block2 = at_block2
+            .serialize(Serializer {
+                version: RpcVersion::V08,
+            })
+            .unwrap();
+        let at_latest = at_latest
+            .serialize(Serializer {
+                version: RpcVersion::V08,
+            })
+            .unwrap();
+
+        // Identical to `deploy_account_starknet_0_13_5` which runs on the same state.
+        let expected_block1 = serde_json::json!([
+            {
+                "l1_data_gas_consumed": "0x1c0",
+                "l1_data_gas_price": "0x2",
+                "l1_gas_consumed": "0x0",
+                "l1_gas_price": "0x2",
+                "l2_gas_consumed": "0xb5842",
+                "l2_gas_price": "0x1",
+                "overall_fee": "0xb5bc2",
+                "unit": "FRI"
+            }
+        ]);
+        pretty_assertions_sorted::assert_eq!(expected_block1, at_block1);
+
+        assert_eq!(at_block2[0]["l1_data_gas_price"], "0x3");
+        assert_eq!(at_block2[0]["l2_gas_price"], "0x2");
+        assert_ne!(at_block2[0]["overall_fee"], at_block1[0]["overall_fee"]);
+        assert_eq!(at_latest, at_block2);
+    }
+
     #[test_log::test(tokio::test)]
     async fn calldata_limit_exceeded() {
         let starknet_version = StarknetVersion::new(0, 13, 1, 0);
@@ -1,9 +1,12 @@
+use std::sync::Arc;
+
 use anyhow::Context;
 use pathfinder_common::ClassHash;
 
+use crate::class_cache::SerializedClass;
 use crate::context::RpcContext;
 use crate::dto::SerializeForVersion;
-use crate::types::{CairoContractClass, ContractClass, SierraContractClass};
+use crate::types::ContractClass;
 use crate::{dto, RpcVersion};
 
 crate::error::generate_rpc_error_subset!(Error: BlockNotFound, ClassHashNotFound);
@@ -25,18 +28,14 @@ impl crate::dto::DeserializeForVersion for Input {
     }
 }
 
-#[derive(Debug)]
-pub enum Output {
-    DeprecatedClass(CairoContractClass),
-    Class(SierraContractClass),
-}
+/// The serialized class, shared with [ClassCache](crate::class_cache::ClassCache).
+#[derive(Debug, PartialEq)]
+pub struct Output(Arc<SerializedClass>);
 
-impl From<ContractClass> for Output {
-    fn from(value: ContractClass) -> Self {
-        match value {
-            ContractClass::Cairo(x) => Self::DeprecatedClass(x),
-            ContractClass::Sierra(x) => Self::Class(x),
-        }
+impl Output {
+    /// Length of the class in bytes once written out as JSON.
+    pub(super) fn serialized_len(&self) -> usize {
+        self.0.len
     }
 }
 
@@ -73,20 +72,24 @@ pub async fn get_class(
 
         // If the class is declared in the pending block, then we shouldn't check the
         // class's declaration point.
-        let definition = if is_pending {
-            tx.class_definition(input.class_hash)
+        let is_declared = if is_pending {
+            tx.class_definitions_exist(&[input.class_hash])
+                .context("Querying class existence")?[0]
         } else {
-            tx.class_definition_at(block_id, input.class_hash)
-        }
-        .context("Fetching class definition")?;
-
-        let Some(definition) = definition else {
-            return Err(Error::ClassHashNotFound);
+            let declared_at = tx
+                .class_declaration_block(input.class_hash)
+                .context("Querying class declaration block")?;
+            let block_number = tx.block_id(block_id).context("Querying block number")?;
+            matches!(
+                (declared_at, block_number),
+                (Some(declared_at), Some((block_number, _))) if declared_at <= block_number
+            )
         };
+        if !is_declared {
+            return Err(Error::ClassHashNotFound);
+        }
 
-        let class = ContractClass::from_definition_bytes(&definition)
-            .context("Parsing class definition")?
-            .into();
+        let class = cached_class(&context, &tx, input.class_hash, rpc_version)?;
 
         Ok(class)
     });
@@ -94,12 +97,40 @@ pub async fn get_class(
     jh.await.context("Reading class from database")?
 }
 
+/// Returns the serialized class from the class cache, reading and serializing
+/// it on a miss. The caller must have checked that the class is declared.
+pub(super) fn cached_class(
+    context: &RpcContext,
+    tx: &pathfinder_storage::Transaction<'_>,
+    class_hash: ClassHash,
+    rpc_version: RpcVersion,
+) -> anyhow::Result<Output> {
+    context
+        .class_cache
+        .get_or_load(class_hash, rpc_version, || {
+            let definition = tx
+                .class_definition(class_hash)
+                .context("Fetching class definition")?
+                .context("Class definition missing from database")?;
+
+            let serializer = dto::Serializer::new(rpc_version);
+            let class = match ContractClass::from_definition_bytes(&definition)
+                .context("Parsing class definition")?
+            {
+                ContractClass::Cairo(cairo) => cairo.serialize(serializer),
+                ContractClass::Sierra(sierra) => sierra.serialize(serializer),
+            }
+            .context("Serializing class definition")?;
+
+            Ok(SerializedClass::new(class))
+        })
+        .map(Output)
+}
+
 impl SerializeForVersion for Output {
-    fn serialize(&self, serializer: dto::Serializer) -> Result<dto::Ok, dto::Error> {
-        match self {
-            Output::DeprecatedClass(cairo) => cairo.serialize(serializer),
-            Output::Class(sierra) => sierra.serialize(serializer),
-        }
+    fn serialize(&self, _serializer: dto::Serializer) -> Result<dto::Ok, dto::Error> {
+        // Already serialized for the RPC version the class was requested with.
+        Ok(self.0.value.clone())
     }
 }
 
@@ -196,6 +227,89 @@ mod tests {
         assert_matches!(error, Error::ClassHashNotFound);
     }
 
+    #[tokio::test]
+    async fn contract_class_version() {
+        let context = RpcContext::for_tests();
+
+        let get_class = |class_hash| {
+            let context = context.clone();
+            async move {
+                super::get_class(
+                    context,
+                    Input {
+                        block_id: BlockId::Latest,
+                        class_hash,
+                    },
+                    RPC_VERSION,
+                )
+                .await
+                .unwrap()
+                .serialize(dto::Serializer::new(RPC_VERSION))
+                .unwrap()
+            }
+        };
+
+        let sierra = get_class(class_hash_bytes!(b"class 2 hash (sierra)")).await;
+        let stored = serde_json::from_slice::<serde_json::Value>(
+            starknet_gateway_test_fixtures::class_definitions::CAIRO_0_11_SIERRA,
+        )
+        .unwrap();
+        assert_eq!(
+            sierra["contract_class_version"],
+            stored["contract_class_version"]
+        );
+        assert_eq!(sierra["contract_class_version"], "0.1.0");
+
+        let cairo = get_class(class_hash_bytes!(b"class 0 hash")).await;
+        assert!(cairo.get("contract_class_version").is_none());
+    }
+
+    #[tokio::test]
+    async fn class_is_cached_per_version() {
+        let context = RpcContext::for_tests();
+        let class_hash = class_hash_bytes!(b"class 2 hash (sierra)");
+        let input = || Input {
+            block_id: BlockId::Latest,
+            class_hash,
+        };
+
+        assert!(context.class_cache.get(class_hash, RPC_VERSION).is_none());
+        let first = super::get_class(context.clone(), input(), RPC_VERSION)
+            .await
+            .unwrap();
+        let cached = context.class_cache.get(class_hash, RPC_VERSION).unwrap();
+        assert!(Arc::ptr_eq(&first.0, &cached));
+
+        // A hit returns the very same serialized class.
+        let second = super::get_class(context.clone(), input(), RPC_VERSION)
+            .await
+            .unwrap();
+        assert!(Arc::ptr_eq(&second.0, &cached));
+
+        // Other versions are cached separately.
+        assert!(context
+            .class_cache
+            .get(class_hash, RpcVersion::V07)
+            .is_none());
+        let v07 = super::get_class(context.clone(), input(), RpcVersion::V07)
+            .await
+            .unwrap();
+        assert!(!Arc::ptr_eq(&v07.0, &cached));
+
+        // Declaration checks still apply to cached classes.
+        let error = super::get_class(
+            context,
+            Input {
+                block_id: BlockId::Number(pathfinder_common::BlockNumber::GENESIS),
+                class_hash,
+            },
+            RPC_VERSION,
+        )
+        .await
+        .unwrap_err();
+        assert_matches!(error, Error::ClassHashNotFound);
+    }
+
     #[tokio::test]
     async fn latest() {
         let context = RpcContext::for_tests();
@@ -344,6 +458,31 @@ mod tests {
         assert_matches!(error, Error::BlockNotFound);
     }
 
+    #[tokio::test]
+    async fn replaced_class() {
+        use pathfinder_common::BlockNumber;
+
+        let (context, _, original, replacement) = crate::test_setup::replaced_class_context();
+        let get_class = |block_number, class_hash| {
+            super::get_class(
+                context.clone(),
+                Input {
+                    block_id: BlockId::Number(BlockNumber::new_or_panic(block_number)),
+                    class_hash,
+                },
+                RPC_VERSION,
+            )
+        };
+
+        // The replacement is declared in block 1, and the original class stays
+        // declared after it was replaced.
+        get_class(0, original).await.unwrap();
+        get_class(2, original).await.unwrap();
+        let error = get_class(0, replacement).await.unwrap_err();
+        assert_matches!(error, Error::ClassHashNotFound);
+        get_class(1, replacement).await.unwrap();
+    }
+
     #[tokio::test]
     async fn read_at_hash() {
         let context = RpcContext::for_tests();
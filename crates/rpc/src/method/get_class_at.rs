@@ -1,10 +1,10 @@
 use anyhow::Context;
 use pathfinder_common::{BlockId, ContractAddress};
 
+use super::get_class::cached_class;
+pub use super::get_class::Output;
 use crate::context::RpcContext;
-use crate::dto::SerializeForVersion;
-use crate::types::{CairoContractClass, ContractClass, SierraContractClass};
-use crate::{dto, RpcVersion};
+use crate::RpcVersion;
 
 crate::error::generate_rpc_error_subset!(Error: BlockNotFound, ContractNotFound);
 
@@ -25,30 +25,6 @@ impl crate::dto::DeserializeForVersion for Input {
     }
 }
 
-#[derive(Debug)]
-pub enum Output {
-    DeprecatedClass(CairoContractClass),
-    Class(SierraContractClass),
-}
-
-impl From<ContractClass> for Output {
-    fn from(value: ContractClass) -> Self {
-        match value {
-            ContractClass::Cairo(x) => Self::DeprecatedClass(x),
-            ContractClass::Sierra(x) => Self::Class(x),
-        }
-    }
-}
-
-impl SerializeForVersion for Output {
-    fn serialize(&self, serializer: dto::Serializer) -> Result<dto::Ok, dto::Error> {
-        match self {
-            Output::DeprecatedClass(cairo) => cairo.serialize(serializer),
-            Output::Class(sierra) => sierra.serialize(serializer),
-        }
-    }
-}
-
 /// Get a contract class.
 pub async fn get_class_at(
     context: RpcContext,
@@ -89,28 +65,22 @@ pub async fn get_class_at(
                 .ok_or(Error::ContractNotFound)?,
         };
 
-        let definition = tx
-            .class_definition(class_hash)
-            .context("Fetching class definition")?
-            .context("Class definition missing from database")?;
-
-        let class = ContractClass::from_definition_bytes(&definition)
-            .context("Parsing class definition")?;
+        let class = cached_class(&context, &tx, class_hash, rpc_version)?;
 
         Ok(class)
     });
 
-    let class = jh.await.context("Reading class from database")??;
-    Ok(Output::from(class))
+    jh.await.context("Reading class from database")?
 }
 
 #[cfg(test)]
 mod tests {
     use assert_matches::assert_matches;
     use pathfinder_common::macro_prelude::*;
+    use pathfinder_common::BlockNumber;
 
     use super::*;
-    use crate::dto::{SerializeForVersion, Serializer};
+    use crate::dto::{self, SerializeForVersion, Serializer};
     use crate::RpcVersion;
 
     mod parsing {
@@ -167,7 +137,7 @@ mod tests {
             contract_address: contract_address_bytes!(b"contract 1"),
         };
 
-        let output = get_class_at(context, input, RPC_VERSION)
+        let output = get_class_at(context, input, version)
             .await
             .unwrap()
             .serialize(Serializer { version })
@@ -189,7 +159,7 @@ mod tests {
             contract_address: contract_address_bytes!(b"contract 2 (sierra)"),
         };
 
-        let output = get_class_at(context, input, RPC_VERSION)
+        let output = get_class_at(context, input, version)
             .await
             .unwrap()
             .serialize(Serializer { version })
@@ -221,4 +191,25 @@ mod tests {
         let error = get_class_at(context, input, RPC_VERSION).await.unwrap_err();
         assert_matches!(error, Error::BlockNotFound);
     }
+
+    #[tokio::test]
+    async fn replaced_class() {
+        let (context, contract, ..) = crate::test_setup::replaced_class_context();
+
+        // The Cairo 0 class is replaced by a Sierra class in block 1.
+        for (block_number, is_sierra) in [(0, false), (1, true), (2, true)] {
+            let input = Input {
+                block_id: BlockNumber::new_or_panic(block_number).into(),
+                contract_address: contract,
+            };
+
+            let output = get_class_at(context.clone(), input, RPC_VERSION)
+                .await
+                .unwrap()
+                .serialize(Serializer::new(RPC_VERSION))
+                .unwrap();
+            assert_eq!(output.get("program").is_none(), is_sierra);
+            assert_eq!(output.get("sierra_program").is_some(), is_sierra);
+        }
+    }
 }
@@ -210,6 +210,31 @@ mod tests {
         assert_eq!(result.0, expected);
     }
 
+    #[tokio::test]
+    async fn replaced_class() {
+        use pathfinder_common::BlockNumber;
+        let (context, contract, original, replacement) =
+            crate::test_setup::replaced_class_context();
+
+        // The class is replaced in block 1.
+        for (block_id, expected) in [
+            (BlockNumber::new_or_panic(0).into(), original),
+            (block_hash_bytes!(b"block 0").into(), original),
+            (BlockNumber::new_or_panic(1).into(), replacement),
+            (BlockNumber::new_or_panic(2).into(), replacement),
+            (BlockId::Latest, replacement),
+        ] {
+            let input = Input {
+                block_id,
+                contract_address: contract,
+            };
+            let result = get_class_hash_at(context.clone(), input, RPC_VERSION)
+                .await
+                .unwrap();
+            assert_eq!(result.0, expected, "{block_id:?}");
+        }
+    }
+
     #[tokio::test]
     async fn pending_defaults_to_latest() {
         let context = RpcContext::for_tests();
@@ -83,12 +83,29 @@ pub async fn get_compiled_casm(context: RpcContext, input: Input) -> Result<Outp
             .context("Creating database transaction")
             .map_err(Error::Internal)?;
 
-        // Get the class definition
-        let casm_definition = tx
-            .casm_definition(input.class_hash)
-            .context("Fetching class definition")
-            .map_err(Error::Internal)?
-            .ok_or(Error::ClassHashNotFound(input.class_hash))?;
+        let pruned = tx
+            .casm_definition_is_pruned(input.class_hash)
+            .context("Checking for pruned casm definition")
+            .map_err(Error::Internal)?;
+
+        let casm_definition = if pruned {
+            // The CASM is not stored, recompile it from the Sierra definition.
+            let sierra_definition = tx
+                .class_definition(input.class_hash)
+                .context("Fetching sierra definition")
+                .map_err(Error::Internal)?
+                .ok_or(Error::ClassHashNotFound(input.class_hash))?;
+
+            pathfinder_compiler::compile_to_casm(&sierra_definition).map_err(|error| {
+                tracing::warn!(class_hash=%input.class_hash, %error, "Recompiling pruned casm failed");
+                Error::CompilationFailed
+            })?
+        } else {
+            tx.casm_definition(input.class_hash)
+                .context("Fetching class definition")
+                .map_err(Error::Internal)?
+                .ok_or(Error::ClassHashNotFound(input.class_hash))?
+        };
 
         // Convert to JSON string
         let casm_definition_str = String::from_utf8_lossy(&casm_definition);
@@ -112,7 +129,18 @@ mod tests {
         CasmContractEntryPoint,
         CasmContractEntryPoints,
     };
-    use pathfinder_common::{casm_hash, class_hash, felt, sierra_hash, EntryPoint};
+    use pathfinder_common::{
+        casm_hash,
+        class_hash,
+        felt,
+        sierra_hash,
+        BlockHeader,
+        BlockNumber,
+        CasmHash,
+        EntryPoint,
+        SierraHash,
+        StateUpdate,
+    };
     use serde_json::json;
     use starknet_gateway_test_fixtures::class_definitions::{
         CAIRO_1_1_0_BALANCE_CASM_JSON,
@@ -144,6 +172,36 @@ mod tests {
         assert_matches::assert_matches!(result, Error::ClassHashNotFound(_));
     }
 
+    #[tokio::test]
+    async fn pruned_casm_is_recompiled() {
+        let rpc = setup().await.unwrap();
+        {
+            let mut db = rpc.storage.connection().unwrap();
+            let tx = db.transaction().unwrap();
+            let header = BlockHeader::default();
+            tx.insert_block_header(&header).unwrap();
+            tx.insert_state_update(
+                header.number,
+                &StateUpdate::default().with_declared_sierra_class(sierra_hash(), casm_hash()),
+            )
+            .unwrap();
+            assert_eq!(
+                tx.prune_casm_definitions(BlockNumber::new_or_panic(1), |_, _, _| true)
+                    .unwrap(),
+                1
+            );
+            tx.commit().unwrap();
+        }
+
+        let result = get_compiled_casm(rpc, input()).await.unwrap();
+
+        let recompiled =
+            pathfinder_compiler::compile_to_casm(CAIRO_1_1_0_BALANCE_SIERRA_JSON).unwrap();
+        let recompiled =
+            CasmContractClass::try_from(String::from_utf8(recompiled).unwrap().as_str()).unwrap();
+        assert_eq!(result.0, recompiled);
+    }
+
     #[tokio::test]
     async fn serialization() {
         let rpc = setup().await.unwrap();
@@ -170,9 +228,9 @@ mod tests {
             let tx = db.transaction().expect("tx");
 
             tx.insert_sierra_class(
-                &sierra_hash!("0x0484c163658bcce5f9916f486171ac60143a92897533aa7ff7ac800b16c63311"),
+                &sierra_hash(),
                 CAIRO_1_1_0_BALANCE_SIERRA_JSON,
-                &casm_hash!("0x0484c163658bcce5f9916f486171ac60143a92897533aa7ff7ac800b16c63311"),
+                &casm_hash(),
                 CAIRO_1_1_0_BALANCE_CASM_JSON,
             )
             .expect("insert class");
@@ -185,6 +243,14 @@ mod tests {
         Ok(rpc)
     }
 
+    fn sierra_hash() -> SierraHash {
+        sierra_hash!("0x0484c163658bcce5f9916f486171ac60143a92897533aa7ff7ac800b16c63311")
+    }
+
+    fn casm_hash() -> CasmHash {
+        casm_hash!("0x0484c163658bcce5f9916f486171ac60143a92897533aa7ff7ac800b16c63311")
+    }
+
     fn input() -> Input {
         Input {
             class_hash: class_hash!(
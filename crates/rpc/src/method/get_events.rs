@@ -11,7 +11,11 @@ use crate::dto::{self, SerializeForVersion, Serializer};
 use crate::pending::{PendingBlockVariant, PendingData};
 use crate::RpcVersion;
 
+/// Default for [RpcConfig::get_events_page_size_limit](crate::context::RpcConfig).
 pub const EVENT_PAGE_SIZE_LIMIT: usize = 1024;
+/// Default for
+/// [RpcConfig::get_events_key_filter_size_limit](crate::context::RpcConfig).
+pub const EVENT_KEY_FILTER_SIZE_LIMIT: usize = 1024;
 
 #[derive(Debug)]
 pub enum GetEventsError {
@@ -135,7 +139,17 @@ pub async fn get_events(
             requested: request.keys.len(),
         });
     }
-    if request.chunk_size > EVENT_PAGE_SIZE_LIMIT {
+    // Each position may list any number of alternative keys, so the total has to be
+    // bounded separately from the number of positions.
+    let key_filter_size = request.keys.iter().map(Vec::len).sum::<usize>();
+    let key_filter_size_limit = context.config.get_events_key_filter_size_limit.get();
+    if key_filter_size > key_filter_size_limit {
+        return Err(GetEventsError::TooManyKeysInFilter {
+            limit: key_filter_size_limit,
+            requested: key_filter_size,
+        });
+    }
+    if request.chunk_size > context.config.get_events_page_size_limit.get() {
         return Err(GetEventsError::PageSizeTooBig);
     }
 
@@ -607,6 +621,8 @@ impl SerializeForVersion for GetEventsResult {
 
 #[cfg(test)]
 mod tests {
+    use std::num::NonZeroUsize;
+
     use pathfinder_common::macro_prelude::*;
     use pathfinder_storage::test_utils;
     use pretty_assertions_sorted::assert_eq;
@@ -857,6 +873,74 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn get_events_page_size_limit_is_configurable() {
+        let (mut context, _) = setup();
+        context.config.get_events_page_size_limit = NonZeroUsize::new(5).unwrap();
+
+        let input = GetEventsInput {
+            filter: EventFilter {
+                chunk_size: 5,
+                ..Default::default()
+            },
+        };
+        get_events(context.clone(), input, RPC_VERSION)
+            .await
+            .unwrap();
+
+        let input = GetEventsInput {
+            filter: EventFilter {
+                chunk_size: 6,
+                ..Default::default()
+            },
+        };
+        let error = get_events(context, input, RPC_VERSION).await.unwrap_err();
+
+        assert_eq!(GetEventsError::PageSizeTooBig, error);
+    }
+
+    #[tokio::test]
+    async fn get_events_with_too_many_alternative_keys_in_filter() {
+        let (mut context, _) = setup();
+        let limit = 4;
+        context.config.get_events_key_filter_size_limit = NonZeroUsize::new(limit).unwrap();
+
+        // Few positions, but many alternatives per position.
+        let input = GetEventsInput {
+            filter: EventFilter {
+                keys: vec![
+                    vec![event_key!("01"), event_key!("02")],
+                    vec![event_key!("03"), event_key!("04")],
+                ],
+                chunk_size: 10,
+                ..Default::default()
+            },
+        };
+        get_events(context.clone(), input, RPC_VERSION)
+            .await
+            .unwrap();
+
+        let input = GetEventsInput {
+            filter: EventFilter {
+                keys: vec![
+                    vec![event_key!("01"), event_key!("02"), event_key!("03")],
+                    vec![event_key!("04"), event_key!("05")],
+                ],
+                chunk_size: 10,
+                ..Default::default()
+            },
+        };
+        let error = get_events(context, input, RPC_VERSION).await.unwrap_err();
+
+        assert_eq!(
+            GetEventsError::TooManyKeysInFilter {
+                limit,
+                requested: limit + 1
+            },
+            error
+        );
+    }
+
     #[tokio::test]
     async fn get_events_from_block_greater_than_to_block_returns_empty_page() {
         let (context, _) = setup();
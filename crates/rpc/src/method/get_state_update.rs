@@ -1,13 +1,23 @@
 use std::sync::Arc;
 
 use anyhow::Context;
-use pathfinder_common::{BlockId, StateUpdate};
+use pathfinder_common::{BlockId, ClassHash, StateUpdate};
 
+use super::get_class;
+use crate::dto::SerializeForVersion;
 use crate::{dto, RpcContext, RpcVersion};
 
+/// Upper bound on the serialized size of the class definitions inlined into a
+/// single state update. Classes that do not fit are listed as omitted and have
+/// to be fetched with `starknet_getClass`.
+const MAX_INLINED_CLASSES_SIZE: usize = 16 * 1024 * 1024;
+
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct Input {
     block_id: BlockId,
+    /// Pathfinder extension which inlines the definitions of the classes
+    /// declared in the block. Not part of the spec, so off by default.
+    include_declared_classes: bool,
 }
 
 impl crate::dto::DeserializeForVersion for Input {
@@ -15,6 +25,9 @@ impl crate::dto::DeserializeForVersion for Input {
         value.deserialize_map(|value| {
             Ok(Self {
                 block_id: value.deserialize("block_id")?,
+                include_declared_classes: value
+                    .deserialize_optional("pathfinder_include_declared_classes")?
+                    .unwrap_or_default(),
             })
         })
     }
@@ -24,19 +37,111 @@ crate::error::generate_rpc_error_subset!(Error: BlockNotFound);
 
 #[derive(PartialEq, Debug)]
 pub enum Output {
-    Full(Box<StateUpdate>),
+    /// The declared class definitions are only present if they were requested.
+    Full(Box<StateUpdate>, Option<DeclaredClassDefinitions>),
     Pending(Arc<StateUpdate>),
 }
 
 impl dto::SerializeForVersion for Output {
     fn serialize(&self, serializer: dto::Serializer) -> Result<dto::Ok, dto::Error> {
         match self {
-            Output::Full(full) => dto::StateUpdate(full).serialize(serializer),
+            Output::Full(full, None) => dto::StateUpdate(full).serialize(serializer),
+            Output::Full(full, Some(classes)) => {
+                let mut serializer = serializer.serialize_struct()?;
+                serializer.flatten(&dto::StateUpdate(full))?;
+                serializer.serialize_field("pathfinder_declared_classes", classes)?;
+                serializer.end()
+            }
             Output::Pending(pending) => dto::PendingStateUpdate(pending).serialize(serializer),
         }
     }
 }
 
+/// Definitions of the classes declared in a block, ordered by class hash.
+#[derive(PartialEq, Debug, Default)]
+pub struct DeclaredClassDefinitions {
+    definitions: Vec<(ClassHash, get_class::Output)>,
+    /// Declared classes left out because of [MAX_INLINED_CLASSES_SIZE].
+    omitted: Vec<ClassHash>,
+}
+
+impl DeclaredClassDefinitions {
+    /// Loads the definitions of the classes declared in `state_update` until
+    /// their serialized size exceeds `max_size`, the rest are omitted.
+    fn load(
+        context: &RpcContext,
+        tx: &pathfinder_storage::Transaction<'_>,
+        state_update: &StateUpdate,
+        rpc_version: RpcVersion,
+        max_size: usize,
+    ) -> anyhow::Result<Self> {
+        let mut class_hashes = state_update
+            .declared_cairo_classes
+            .iter()
+            .copied()
+            .chain(
+                state_update
+                    .declared_sierra_classes
+                    .keys()
+                    .map(|sierra_hash| ClassHash(sierra_hash.0)),
+            )
+            .collect::<Vec<_>>();
+        class_hashes.sort();
+
+        let mut classes = Self::default();
+        let mut size = 0;
+        for class_hash in class_hashes {
+            if size > max_size {
+                classes.omitted.push(class_hash);
+                continue;
+            }
+
+            let definition = get_class::cached_class(context, tx, class_hash, rpc_version)?;
+            size += definition.serialized_len();
+            if size > max_size {
+                classes.omitted.push(class_hash);
+            } else {
+                classes.definitions.push((class_hash, definition));
+            }
+        }
+
+        Ok(classes)
+    }
+}
+
+impl dto::SerializeForVersion for DeclaredClassDefinitions {
+    fn serialize(&self, serializer: dto::Serializer) -> Result<dto::Ok, dto::Error> {
+        struct Definition<'a> {
+            class_hash: &'a ClassHash,
+            definition: &'a get_class::Output,
+        }
+
+        impl dto::SerializeForVersion for Definition<'_> {
+            fn serialize(&self, serializer: dto::Serializer) -> Result<dto::Ok, dto::Error> {
+                let mut serializer = serializer.serialize_struct()?;
+                serializer.serialize_field("class_hash", self.class_hash)?;
+                serializer.serialize_field("definition", self.definition)?;
+                serializer.end()
+            }
+        }
+
+        let mut serializer = serializer.serialize_struct()?;
+        serializer.serialize_iter(
+            "definitions",
+            self.definitions.len(),
+            &mut self
+                .definitions
+                .iter()
+                .map(|(class_hash, definition)| Definition {
+                    class_hash,
+                    definition,
+                }),
+        )?;
+        serializer.serialize_iter("omitted", self.omitted.len(), &mut self.omitted.iter())?;
+        serializer.end()
+    }
+}
+
 pub async fn get_state_update(
     context: RpcContext,
     input: Input,
@@ -84,7 +189,19 @@ pub async fn get_state_update(
             .context("Fetching state diff")?
             .ok_or(Error::BlockNotFound)?;
 
-        Ok(Output::Full(Box::new(state_update)))
+        let declared_classes = if input.include_declared_classes {
+            Some(DeclaredClassDefinitions::load(
+                &context,
+                &tx,
+                &state_update,
+                rpc_version,
+                MAX_INLINED_CLASSES_SIZE,
+            )?)
+        } else {
+            None
+        };
+
+        Ok(Output::Full(Box::new(state_update), declared_classes))
     });
 
     jh.await.context("Database read panic or shutting down")?
@@ -612,7 +729,7 @@ mod tests {
     impl Output {
         fn unwrap_full(self) -> Box<StateUpdate> {
             match self {
-                Output::Full(x) => x,
+                Output::Full(x, _) => x,
                 Output::Pending(_) => panic!("Output was Pending variant"),
             }
         }
@@ -620,7 +737,7 @@ mod tests {
         fn unwrap_pending(self) -> Arc<StateUpdate> {
             match self {
                 Output::Pending(x) => x,
-                Output::Full(_) => panic!("Output was Full variant"),
+                Output::Full(..) => panic!("Output was Full variant"),
             }
         }
     }
@@ -637,7 +754,10 @@ mod tests {
     fn input_parsing(#[case] input: serde_json::Value, #[case] block_id: BlockId) {
         let input = Input::deserialize(crate::dto::Value::new(input, RpcVersion::V07)).unwrap();
 
-        let expected = Input { block_id };
+        let expected = Input {
+            block_id,
+            include_declared_classes: false,
+        };
 
         assert_eq!(input, expected);
     }
@@ -677,6 +797,7 @@ mod tests {
             ctx,
             Input {
                 block_id: BlockId::Latest,
+                include_declared_classes: false,
             },
             RPC_VERSION,
         )
@@ -695,6 +816,7 @@ mod tests {
             ctx,
             Input {
                 block_id: BlockId::Number(BlockNumber::GENESIS),
+                include_declared_classes: false,
             },
             RPC_VERSION,
         )
@@ -713,6 +835,7 @@ mod tests {
             ctx,
             Input {
                 block_id: BlockId::Hash(in_storage[1].block_hash),
+                include_declared_classes: false,
             },
             RPC_VERSION,
         )
@@ -731,6 +854,7 @@ mod tests {
             ctx,
             Input {
                 block_id: BlockId::Number(BlockNumber::MAX),
+                include_declared_classes: false,
             },
             RPC_VERSION,
         )
@@ -747,6 +871,7 @@ mod tests {
             ctx,
             Input {
                 block_id: BlockId::Hash(block_hash_bytes!(b"non-existent")),
+                include_declared_classes: false,
             },
             RPC_VERSION,
         )
@@ -760,6 +885,7 @@ mod tests {
         let context = RpcContext::for_tests_with_pending().await;
         let input = Input {
             block_id: BlockId::Pending,
+            include_declared_classes: false,
         };
 
         let expected = context.pending_data.get_unchecked().state_update();
@@ -777,6 +903,7 @@ mod tests {
         let context = RpcContext::for_tests_with_pre_confirmed().await;
         let input = Input {
             block_id: BlockId::Pending,
+            include_declared_classes: false,
         };
 
         let expected = context.pending_data.get_unchecked().state_update();
@@ -798,4 +925,124 @@ mod tests {
                 .into()
         );
     }
+
+    mod declared_classes {
+        use pathfinder_common::StarknetVersion;
+
+        use super::*;
+
+        /// Block 1 of the test storage declares two Cairo 0 classes and one
+        /// Sierra class.
+        async fn context() -> RpcContext {
+            let (storage, ..) =
+                crate::test_setup::test_storage(StarknetVersion::new(0, 13, 2, 0), |x| x).await;
+            RpcContext::for_tests().with_storage(storage)
+        }
+
+        fn input(include_declared_classes: bool) -> Input {
+            Input {
+                block_id: BlockNumber::new_or_panic(1).into(),
+                include_declared_classes,
+            }
+        }
+
+        #[tokio::test]
+        async fn not_inlined_by_default() {
+            let context = context().await;
+
+            let output = get_state_update(context, input(false), RPC_VERSION)
+                .await
+                .unwrap();
+            assert_matches::assert_matches!(output, Output::Full(_, None));
+
+            let serialized = output.serialize(dto::Serializer::new(RPC_VERSION)).unwrap();
+            assert!(serialized.get("pathfinder_declared_classes").is_none());
+        }
+
+        #[tokio::test]
+        async fn all_declared_classes_are_inlined() {
+            let context = context().await;
+
+            let output = get_state_update(context.clone(), input(true), RPC_VERSION)
+                .await
+                .unwrap();
+            let Output::Full(state_update, Some(classes)) = &output else {
+                panic!("Expected inlined classes, got {output:?}");
+            };
+
+            let mut expected = state_update
+                .declared_cairo_classes
+                .iter()
+                .copied()
+                .chain(
+                    state_update
+                        .declared_sierra_classes
+                        .keys()
+                        .map(|x| ClassHash(x.0)),
+                )
+                .collect::<Vec<_>>();
+            expected.sort();
+            assert_eq!(expected.len(), 3);
+
+            let mut db = context.storage.connection().unwrap();
+            let tx = db.transaction().unwrap();
+            let expected = expected
+                .into_iter()
+                .map(|class_hash| {
+                    let definition =
+                        get_class::cached_class(&context, &tx, class_hash, RPC_VERSION).unwrap();
+                    (class_hash, definition)
+                })
+                .collect::<Vec<_>>();
+            assert_eq!(classes.definitions, expected);
+            assert!(classes.omitted.is_empty());
+
+            let serialized = output.serialize(dto::Serializer::new(RPC_VERSION)).unwrap();
+            let inlined = &serialized["pathfinder_declared_classes"];
+            assert_eq!(inlined["definitions"].as_array().unwrap().len(), 3);
+            assert_eq!(inlined["omitted"], json!([]));
+            // The spec fields are unchanged.
+            assert!(serialized["state_diff"]["declared_classes"].is_array());
+        }
+
+        #[tokio::test]
+        async fn classes_beyond_size_limit_are_omitted() {
+            let context = context().await;
+            let state_update = get_state_update(context.clone(), input(false), RPC_VERSION)
+                .await
+                .unwrap()
+                .unwrap_full();
+
+            let mut db = context.storage.connection().unwrap();
+            let tx = db.transaction().unwrap();
+
+            let all = DeclaredClassDefinitions::load(
+                &context,
+                &tx,
+                &state_update,
+                RPC_VERSION,
+                usize::MAX,
+            )
+            .unwrap();
+            let (first, first_definition) = &all.definitions[0];
+
+            let limited = DeclaredClassDefinitions::load(
+                &context,
+                &tx,
+                &state_update,
+                RPC_VERSION,
+                first_definition.serialized_len(),
+            )
+            .unwrap();
+            assert_eq!(limited.definitions.len(), 1);
+            assert_eq!(limited.definitions[0].0, *first);
+            assert_eq!(
+                limited.omitted,
+                all.definitions[1..]
+                    .iter()
+                    .map(|(class_hash, _)| *class_hash)
+                    .collect::<Vec<_>>()
+            );
+        }
+    }
 }
@@ -993,4 +993,106 @@ mod tests {
             }
         }
     }
+
+    /// Walks `proof` from `root` towards `key`, checking the hash of every node
+    /// on the way. Returns the leaf value at `key`, or `None` if the proof
+    /// shows that `key` is not part of the trie.
+    fn verify_class_proof(
+        root: Felt,
+        key: ClassHash,
+        proof: &NodeHashToNodeMappings,
+    ) -> Option<Felt> {
+        use pathfinder_common::hash::PoseidonHash;
+
+        let nodes = proof
+            .0
+            .iter()
+            .map(|mapping| (mapping.node_hash, &mapping.node.0))
+            .collect::<std::collections::HashMap<_, _>>();
+
+        let mut expected_hash = root;
+        let mut remaining_path = &key.0.view_bits()[5..];
+        while !remaining_path.is_empty() {
+            let node = nodes.get(&expected_hash).expect("Proof node missing");
+            assert_eq!(node.hash::<PoseidonHash>(), expected_hash);
+
+            match node {
+                TrieNode::Binary { left, right } => {
+                    expected_hash = if remaining_path[0] { *right } else { *left };
+                    remaining_path = &remaining_path[1..];
+                }
+                TrieNode::Edge { child, path } => {
+                    if path != &remaining_path[..path.len()] {
+                        return None;
+                    }
+                    expected_hash = *child;
+                    remaining_path = &remaining_path[path.len()..];
+                }
+            }
+        }
+
+        Some(expected_hash)
+    }
+
+    #[tokio::test]
+    async fn class_proof_validates_against_root() {
+        use pathfinder_storage::fake::{fill, generate};
+        use pathfinder_storage::{StorageBuilder, TriePruneMode};
+
+        let storage = StorageBuilder::in_tempdir_with_trie_pruning_and_pool_size(
+            TriePruneMode::Archive,
+            NonZeroU32::new(5).unwrap(),
+        )
+        .unwrap();
+        let blocks = generate::with_config(
+            2,
+            Config {
+                update_tries: Box::new(update_starknet_state),
+                occurrence: OccurrencePerBlock {
+                    sierra: 1..=10,
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+        );
+        fill(&storage, &blocks, Some(Box::new(update_starknet_state)));
+
+        let declared = blocks[1]
+            .state_update
+            .as_ref()
+            .unwrap()
+            .declared_sierra_classes
+            .iter()
+            .map(|(sierra_hash, casm_hash)| (ClassHash(sierra_hash.0), *casm_hash))
+            .collect::<Vec<_>>();
+        let undeclared = class_hash!("0x1234");
+
+        let context = RpcContext::for_tests().with_storage(storage);
+        let input = Input {
+            block_id: BlockId::Number(BlockNumber::new_or_panic(1)),
+            class_hashes: Some(
+                declared
+                    .iter()
+                    .map(|(class_hash, _)| *class_hash)
+                    .chain(std::iter::once(undeclared))
+                    .collect(),
+            ),
+            contract_addresses: None,
+            contracts_storage_keys: None,
+        };
+        let output = get_storage_proof(context, input).await.unwrap();
+        let root = output.global_roots.classes_tree_root;
+
+        for (class_hash, casm_hash) in declared {
+            let leaf = calculate_class_commitment_leaf_hash(casm_hash);
+            assert_eq!(
+                verify_class_proof(root, class_hash, &output.classes_proof),
+                Some(leaf.0)
+            );
+        }
+        assert_eq!(
+            verify_class_proof(root, undeclared, &output.classes_proof),
+            None
+        );
+    }
 }
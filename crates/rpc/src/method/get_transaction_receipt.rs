@@ -36,6 +36,7 @@ pub enum Output {
         transaction: Transaction,
         events: Vec<Event>,
         finality: dto::TxnFinalityStatus,
+        block_fields_as_null: bool,
     },
 }
 
@@ -59,12 +60,14 @@ impl crate::dto::SerializeForVersion for Output {
                 transaction,
                 events,
                 finality: *finality,
+                pending_block_fields_as_null: false,
             },
             Output::Pending {
                 receipt,
                 transaction,
                 events,
                 finality,
+                block_fields_as_null,
             } => dto::TxnReceiptWithBlockInfo {
                 block_hash: None,
                 block_number: None,
@@ -72,6 +75,7 @@ impl crate::dto::SerializeForVersion for Output {
                 transaction,
                 events,
                 finality: *finality,
+                pending_block_fields_as_null: *block_fields_as_null,
             },
         }
         .serialize(serializer)
@@ -112,6 +116,7 @@ pub async fn get_transaction_receipt(
                 transaction,
                 events,
                 finality: pending.block().finality_status(),
+                block_fields_as_null: context.config.pending_receipt_block_fields_as_null,
             });
         }
 
@@ -237,6 +242,69 @@ mod tests {
         }
     }
 
+    #[rstest::rstest]
+    #[case::v06(RpcVersion::V06)]
+    #[case::v07(RpcVersion::V07)]
+    #[case::v08(RpcVersion::V08)]
+    #[case::v09(RpcVersion::V09)]
+    #[tokio::test]
+    async fn pending_block_fields(#[case] version: RpcVersion) {
+        let mut contexts = vec![(
+            RpcContext::for_tests_with_pending().await,
+            transaction_hash_bytes!(b"pending tx hash 0"),
+        )];
+        // Pre-confirmed blocks are only visible from v0.9 onwards.
+        if version >= RpcVersion::V09 {
+            contexts.push((
+                RpcContext::for_tests_with_pre_confirmed().await,
+                transaction_hash_bytes!(b"preconfirmed tx hash 0"),
+            ));
+        }
+
+        for (mut context, tx_hash) in contexts {
+            for as_null in [false, true] {
+                context.config.pending_receipt_block_fields_as_null = as_null;
+                let input = Input {
+                    transaction_hash: tx_hash,
+                };
+                let output_json = get_transaction_receipt(context.clone(), input, version)
+                    .await
+                    .unwrap()
+                    .serialize(Serializer { version })
+                    .unwrap();
+
+                assert_ne!(output_json["finality_status"], "ACCEPTED_ON_L1");
+                for field in ["block_hash", "block_number"] {
+                    if as_null {
+                        assert_eq!(output_json.get(field), Some(&serde_json::Value::Null));
+                    } else {
+                        assert_eq!(output_json.get(field), None);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn pending_receipt_accepted_on_l1_is_rejected() {
+        let output = Output::Pending {
+            receipt: Receipt::default(),
+            transaction: Transaction {
+                hash: transaction_hash_bytes!(b"pending tx"),
+                variant: Default::default(),
+            },
+            events: vec![],
+            finality: dto::TxnFinalityStatus::AcceptedOnL1,
+            block_fields_as_null: false,
+        };
+
+        output
+            .serialize(Serializer {
+                version: RpcVersion::V08,
+            })
+            .unwrap_err();
+    }
+
     #[rstest::rstest]
     #[case::v06(RpcVersion::V06)]
     #[case::v07(RpcVersion::V07)]
@@ -174,7 +174,9 @@ impl Output {
         use crate::dto::TxnStatus;
         match self {
             Output::Received => TxnStatus::Received,
-            Output::Rejected { .. } => TxnStatus::Rejected,
+            Output::Rejected { error_message } => TxnStatus::Rejected {
+                failure_reason: error_message.clone(),
+            },
             Output::Candidate => TxnStatus::Candidate,
             Output::PreConfirmed(_) => TxnStatus::PreConfirmed,
             Output::AcceptedOnL1(_) => TxnStatus::AcceptedOnL1,
@@ -192,11 +194,10 @@ impl Output {
     }
 
     fn failure_reason(&self) -> Option<String> {
-        match self {
-            Output::Rejected { error_message } => error_message.clone(),
-            Output::PreConfirmed(TxnExecutionStatus::Reverted { reason }) => reason.clone(),
-            Output::AcceptedOnL1(TxnExecutionStatus::Reverted { reason }) => reason.clone(),
-            Output::AcceptedOnL2(TxnExecutionStatus::Reverted { reason }) => reason.clone(),
+        use crate::dto::TxnStatus;
+        match (self.finality_status(), self.execution_status()) {
+            (TxnStatus::Rejected { failure_reason }, _) => failure_reason,
+            (_, Some(TxnExecutionStatus::Reverted { reason })) => reason,
             _ => None,
         }
     }
@@ -245,6 +246,31 @@ mod tests {
         assert_eq!(encoded, expected);
     }
 
+    #[rstest::rstest]
+    #[case::v07_without_reason(RpcVersion::V07, None, json!({"finality_status":"REJECTED"}))]
+    #[case::v07_with_reason(
+        RpcVersion::V07,
+        Some("Invalid nonce"),
+        json!({"finality_status":"REJECTED"})
+    )]
+    #[case::v08_without_reason(RpcVersion::V08, None, json!({"finality_status":"REJECTED"}))]
+    #[case::v08_with_reason(
+        RpcVersion::V08,
+        Some("Invalid nonce"),
+        json!({"finality_status":"REJECTED","failure_reason":"Invalid nonce"})
+    )]
+    fn rejected_serialization(
+        #[case] version: RpcVersion,
+        #[case] reason: Option<&str>,
+        #[case] expected: serde_json::Value,
+    ) {
+        let output = Output::Rejected {
+            error_message: reason.map(ToOwned::to_owned),
+        };
+        let encoded = output.serialize(Serializer { version }).unwrap();
+        assert_eq!(encoded, expected);
+    }
+
     const RPC_VERSION: RpcVersion = RpcVersion::V09;
 
     #[tokio::test]
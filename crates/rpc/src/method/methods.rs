@@ -0,0 +1,67 @@
+use crate::RpcVersion;
+
+crate::error::generate_rpc_error_subset!(Error);
+
+pub struct Output(Vec<&'static str>);
+
+/// Get the names of all methods and subscriptions served for `version`, so
+/// that clients can discover what differs between the supported RPC versions.
+pub async fn methods(version: RpcVersion) -> Result<Output, Error> {
+    let routes = match version {
+        RpcVersion::V06 => crate::v06::register_routes(),
+        RpcVersion::V07 => crate::v07::register_routes(),
+        RpcVersion::V08 => crate::v08::register_routes(),
+        RpcVersion::V09 => crate::v09::register_routes(),
+        RpcVersion::PathfinderV01 => crate::pathfinder::register_routes(),
+    };
+
+    Ok(Output(routes.method_names()))
+}
+
+impl crate::dto::SerializeForVersion for Output {
+    fn serialize(
+        &self,
+        serializer: crate::dto::Serializer,
+    ) -> Result<crate::dto::Ok, crate::dto::Error> {
+        serializer.serialize_iter(self.0.len(), &mut self.0.iter().copied())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::context::RpcContext;
+    use crate::dto::SerializeForVersion;
+
+    #[tokio::test]
+    async fn lists_registered_methods() {
+        let router = crate::v08::register_routes().build(RpcContext::for_tests());
+
+        let mut registered = include_str!("../v08.rs")
+            .split(".register(\"")
+            .skip(1)
+            .map(|x| x.split_once('"').unwrap().0)
+            .collect::<Vec<_>>();
+        registered.sort_unstable();
+
+        let output = methods(RpcVersion::V08).await.unwrap();
+        assert_eq!(output.0, registered);
+        assert_eq!(output.0, router.method_names());
+        assert!(output.0.contains(&"pathfinder_methods"));
+        assert!(output.0.contains(&"starknet_subscribeNewHeads"));
+
+        let output = output
+            .serialize(crate::dto::Serializer::new(RpcVersion::V08))
+            .unwrap();
+        assert_eq!(output, serde_json::json!(router.method_names()));
+    }
+
+    #[tokio::test]
+    async fn differs_per_version() {
+        let v06 = methods(RpcVersion::V06).await.unwrap().0;
+        let v08 = methods(RpcVersion::V08).await.unwrap().0;
+
+        assert!(!v06.contains(&"starknet_getStorageProof"));
+        assert!(v08.contains(&"starknet_getStorageProof"));
+    }
+}
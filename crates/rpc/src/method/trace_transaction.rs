@@ -40,6 +40,11 @@ impl crate::dto::SerializeForVersion for Output {
     }
 }
 
+/// Traces a single transaction.
+///
+/// The transaction is first looked up in the pending block, in which case it
+/// is traced against the pending block's context. Otherwise it is looked up
+/// in storage and traced as part of its confirmed block.
 pub async fn trace_transaction(
     context: RpcContext,
     input: Input,
@@ -259,7 +264,7 @@ pub mod tests {
         setup_multi_tx_trace_pending_test,
         setup_multi_tx_trace_test,
     };
-    use super::{trace_transaction, Input, Output};
+    use super::{trace_transaction, Input, Output, TraceTransactionError};
     use crate::dto::{SerializeForVersion, Serializer};
     use crate::RpcVersion;
 
@@ -328,4 +333,21 @@ pub mod tests {
 
         Ok(())
     }
+
+    #[tokio::test]
+    async fn test_transaction_not_in_pending_or_confirmed() -> anyhow::Result<()> {
+        use pathfinder_common::macro_prelude::*;
+
+        let (context, _) = setup_multi_tx_trace_pending_test().await?;
+
+        let input = Input {
+            transaction_hash: transaction_hash_bytes!(b"missing"),
+        };
+        let error = trace_transaction(context, input, RPC_VERSION)
+            .await
+            .unwrap_err();
+        assert_matches::assert_matches!(error, TraceTransactionError::TxnHashNotFound);
+
+        Ok(())
+    }
 }
@@ -0,0 +1,282 @@
+use anyhow::Context;
+use pathfinder_common::{BlockNumber, TransactionHash};
+use starknet_gateway_client::GatewayApi;
+
+use crate::context::RpcContext;
+use crate::dto::SerializeForVersion;
+use crate::{dto, RpcVersion};
+
+pub struct Input {
+    pub transaction_hash: TransactionHash,
+}
+
+impl crate::dto::DeserializeForVersion for Input {
+    fn deserialize(value: crate::dto::Value) -> Result<Self, serde_json::Error> {
+        value.deserialize_map(|value| {
+            Ok(Self {
+                transaction_hash: value.deserialize("transaction_hash").map(TransactionHash)?,
+            })
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct Output {
+    transaction_hash: TransactionHash,
+    block_number: BlockNumber,
+    differences: Vec<Difference>,
+}
+
+/// A single field whose value differs between the locally built receipt and
+/// the one served by the feeder gateway. `None` means the field is absent on
+/// that side.
+#[derive(Debug, PartialEq)]
+pub struct Difference {
+    pub path: String,
+    pub local: Option<serde_json::Value>,
+    pub gateway: Option<serde_json::Value>,
+}
+
+crate::error::generate_rpc_error_subset!(Error: TxnHashNotFound);
+
+/// Builds the receipt of a stored transaction exactly as
+/// `starknet_getTransactionReceipt` would, does the same for the receipt the
+/// feeder gateway returns for its block, and reports every field where the two
+/// disagree.
+pub async fn verify_transaction_receipt(
+    context: RpcContext,
+    input: Input,
+    rpc_version: RpcVersion,
+) -> Result<Output, Error> {
+    let span = tracing::Span::current();
+    let storage = context.storage.clone();
+    let (transaction, receipt, events, block_number) =
+        util::task::spawn_blocking(move |_| -> Result<_, Error> {
+            let _g = span.enter();
+            let mut db = storage
+                .connection()
+                .context("Opening database connection")?;
+            let db_tx = db.transaction().context("Creating database transaction")?;
+
+            db_tx
+                .transaction_with_receipt(input.transaction_hash)
+                .context("Reading transaction receipt from database")?
+                .ok_or(Error::TxnHashNotFound)
+        })
+        .await
+        .context("Joining blocking task")??;
+
+    let (block, _) = context
+        .sequencer
+        .state_update_with_block(block_number)
+        .await
+        .context("Fetching block from feeder gateway")?;
+
+    let (gateway_transaction, (gateway_receipt, gateway_events)) = block
+        .transactions
+        .iter()
+        .zip(block.transaction_receipts.iter())
+        .find(|(t, _)| t.hash == input.transaction_hash)
+        .with_context(|| {
+            format!(
+                "Transaction {} not found in feeder gateway block {block_number}",
+                input.transaction_hash
+            )
+        })?;
+
+    // The finality status is irrelevant for the comparison so both sides use
+    // the same one.
+    let finality = dto::TxnFinalityStatus::AcceptedOnL2;
+    let serializer = dto::Serializer::new(rpc_version);
+
+    let local = dto::TxnReceipt {
+        receipt: &receipt,
+        transaction: &transaction,
+        events: &events,
+        finality,
+    }
+    .serialize(serializer)
+    .context("Serializing local receipt")?;
+    let gateway = dto::TxnReceipt {
+        receipt: gateway_receipt,
+        transaction: gateway_transaction,
+        events: gateway_events,
+        finality,
+    }
+    .serialize(serializer)
+    .context("Serializing feeder gateway receipt")?;
+
+    Ok(Output {
+        transaction_hash: input.transaction_hash,
+        block_number,
+        differences: receipt_differences(&local, &gateway),
+    })
+}
+
+/// Lists every leaf where `local` and `gateway` differ, using a
+/// `/`-separated path of object keys and array indices.
+pub fn receipt_differences(
+    local: &serde_json::Value,
+    gateway: &serde_json::Value,
+) -> Vec<Difference> {
+    let mut differences = Vec::new();
+    collect_differences(String::new(), Some(local), Some(gateway), &mut differences);
+    differences
+}
+
+fn collect_differences(
+    path: String,
+    local: Option<&serde_json::Value>,
+    gateway: Option<&serde_json::Value>,
+    differences: &mut Vec<Difference>,
+) {
+    use serde_json::Value;
+
+    match (local, gateway) {
+        (Some(Value::Object(local)), Some(Value::Object(gateway))) => {
+            let mut keys = local.keys().chain(gateway.keys()).collect::<Vec<_>>();
+            keys.sort();
+            keys.dedup();
+
+            for key in keys {
+                collect_differences(
+                    format!("{path}/{key}"),
+                    local.get(key),
+                    gateway.get(key),
+                    differences,
+                );
+            }
+        }
+        (Some(Value::Array(local)), Some(Value::Array(gateway))) => {
+            for i in 0..local.len().max(gateway.len()) {
+                collect_differences(
+                    format!("{path}/{i}"),
+                    local.get(i),
+                    gateway.get(i),
+                    differences,
+                );
+            }
+        }
+        (local, gateway) if local != gateway => differences.push(Difference {
+            path,
+            local: local.cloned(),
+            gateway: gateway.cloned(),
+        }),
+        _ => {}
+    }
+}
+
+impl SerializeForVersion for Output {
+    fn serialize(
+        &self,
+        serializer: crate::dto::Serializer,
+    ) -> Result<crate::dto::Ok, crate::dto::Error> {
+        let mut serializer = serializer.serialize_struct()?;
+        serializer.serialize_field("transaction_hash", &self.transaction_hash)?;
+        serializer.serialize_field("block_number", &self.block_number)?;
+        serializer.serialize_iter(
+            "differences",
+            self.differences.len(),
+            &mut self.differences.iter(),
+        )?;
+        serializer.end()
+    }
+}
+
+impl SerializeForVersion for &Difference {
+    fn serialize(
+        &self,
+        serializer: crate::dto::Serializer,
+    ) -> Result<crate::dto::Ok, crate::dto::Error> {
+        let mut serializer = serializer.serialize_struct()?;
+        serializer.serialize_field("path", &self.path)?;
+        serializer.serialize_optional("local", self.local.as_ref())?;
+        serializer.serialize_optional("gateway", self.gateway.as_ref())?;
+        serializer.end()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pathfinder_common::macro_prelude::*;
+    use pathfinder_common::receipt::Receipt;
+    use pathfinder_common::transaction::{Transaction, TransactionVariant};
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn identical_receipts_have_no_differences() {
+        let value = json!({"a": [1, {"b": "0x1"}], "c": null});
+
+        assert!(receipt_differences(&value, &value).is_empty());
+    }
+
+    #[test]
+    fn missing_fields_and_array_elements_are_reported() {
+        let local = json!({"a": [1, 2], "b": "x"});
+        let gateway = json!({"a": [1], "c": "y"});
+
+        let differences = receipt_differences(&local, &gateway);
+
+        assert_eq!(
+            differences,
+            vec![
+                Difference {
+                    path: "/a/1".to_owned(),
+                    local: Some(json!(2)),
+                    gateway: None,
+                },
+                Difference {
+                    path: "/b".to_owned(),
+                    local: Some(json!("x")),
+                    gateway: None,
+                },
+                Difference {
+                    path: "/c".to_owned(),
+                    local: None,
+                    gateway: Some(json!("y")),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn diverging_actual_fee_is_reported() {
+        let transaction = Transaction {
+            hash: transaction_hash!("0x1234"),
+            variant: TransactionVariant::default(),
+        };
+        let local = Receipt {
+            transaction_hash: transaction.hash,
+            actual_fee: fee!("0x64"),
+            ..Default::default()
+        };
+        let gateway = Receipt {
+            actual_fee: fee!("0x65"),
+            ..local.clone()
+        };
+
+        let serialize = |receipt| {
+            dto::TxnReceipt {
+                receipt,
+                transaction: &transaction,
+                events: &[],
+                finality: dto::TxnFinalityStatus::AcceptedOnL2,
+            }
+            .serialize(dto::Serializer::new(RpcVersion::V08))
+            .unwrap()
+        };
+
+        let differences = receipt_differences(&serialize(&local), &serialize(&gateway));
+
+        assert_eq!(
+            differences,
+            vec![Difference {
+                path: "/actual_fee/amount".to_owned(),
+                local: Some(json!("0x64")),
+                gateway: Some(json!("0x65")),
+            }]
+        );
+    }
+}
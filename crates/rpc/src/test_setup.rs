@@ -141,3 +141,56 @@ pub async fn test_context_with_starknet_version(
         universal_deployer_address,
     )
 }
+
+/// A contract deployed with a Cairo 0 class in block 0, whose class is replaced
+/// by a Sierra class in block 1. Block 2 leaves the contract unchanged.
+///
+/// Returns the contract's address, its original class and the replacement
+/// class.
+pub fn replaced_class_context() -> (RpcContext, ContractAddress, ClassHash, ClassHash) {
+    use starknet_gateway_test_fixtures::class_definitions::{
+        CAIRO_0_11_SIERRA,
+        CONTRACT_DEFINITION,
+    };
+
+    let storage = pathfinder_storage::StorageBuilder::in_memory().unwrap();
+    let mut db = storage.connection().unwrap();
+    let tx = db.transaction().unwrap();
+
+    let contract = contract_address_bytes!(b"replaced contract");
+    let original = class_hash_bytes!(b"original class");
+    let replacement = sierra_hash_bytes!(b"replacement class");
+    let casm_hash = casm_hash_bytes!(b"replacement casm");
+
+    tx.insert_cairo_class(original, CONTRACT_DEFINITION)
+        .unwrap();
+    tx.insert_sierra_class(&replacement, CAIRO_0_11_SIERRA, &casm_hash, b"casm")
+        .unwrap();
+
+    let header0 = BlockHeader::builder().finalize_with_hash(block_hash_bytes!(b"block 0"));
+    let header1 = header0
+        .child_builder()
+        .finalize_with_hash(block_hash_bytes!(b"block 1"));
+    let header2 = header1
+        .child_builder()
+        .finalize_with_hash(block_hash_bytes!(b"block 2"));
+    let state_updates = [
+        StateUpdate::default()
+            .with_declared_cairo_class(original)
+            .with_deployed_contract(contract, original),
+        StateUpdate::default()
+            .with_declared_sierra_class(replacement, casm_hash)
+            .with_replaced_class(contract, ClassHash(replacement.0)),
+        StateUpdate::default(),
+    ];
+    for (header, state_update) in [header0, header1, header2].iter().zip(state_updates) {
+        tx.insert_block_header(header).unwrap();
+        tx.insert_state_update(header.number, &state_update)
+            .unwrap();
+    }
+
+    tx.commit().unwrap();
+
+    let context = RpcContext::for_tests().with_storage(storage);
+    (context, contract, original, ClassHash(replacement.0))
+}
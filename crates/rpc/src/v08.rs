@@ -47,4 +47,10 @@ pub fn register_routes() -> RpcRouterBuilder {
         .register("starknet_traceBlockTransactions",              crate::method::trace_block_transactions)
         .register("starknet_traceTransaction",                    crate::method::trace_transaction)
         .register("starknet_getCompiledCasm",                     crate::method::get_compiled_casm)
+        .register("pathfinder_classStorageInfo",                  crate::method::class_storage_info)
+        .register("pathfinder_classSyncStats",                    crate::method::class_sync_stats)
+        .register("pathfinder_classesTrieRoot",                   crate::method::classes_trie_root)
+        .register("pathfinder_compilerInfo",                      crate::method::compiler_info)
+        .register("pathfinder_methods",                           || crate::method::methods(crate::RpcVersion::V08))
+        .register("pathfinder_verifyTransactionReceipt",          crate::method::verify_transaction_receipt)
 }
@@ -1,3 +1,4 @@
+use std::cell::RefCell;
 use std::sync::{Arc, Mutex};
 
 mod block;
@@ -11,6 +12,7 @@ pub(crate) mod state_update;
 pub(crate) mod transaction;
 mod trie;
 
+pub use class::{CasmProvenance, CasmSource, ClassStorageInfo, MIN_DICTIONARY_TRAINING_SAMPLES};
 use event::RunningEventFilter;
 pub use event::{
     EmittedEvent,
@@ -38,6 +40,7 @@ pub struct Connection {
     running_event_filter: Arc<Mutex<RunningEventFilter>>,
     trie_prune_mode: TriePruneMode,
     pub blockchain_history_mode: BlockchainHistoryMode,
+    class_compression_dictionary: bool,
 }
 
 impl Connection {
@@ -47,6 +50,7 @@ impl Connection {
         running_event_filter: Arc<Mutex<RunningEventFilter>>,
         trie_prune_mode: TriePruneMode,
         blockchain_history_mode: BlockchainHistoryMode,
+        class_compression_dictionary: bool,
     ) -> Self {
         Self {
             connection,
@@ -54,6 +58,7 @@ impl Connection {
             running_event_filter,
             trie_prune_mode,
             blockchain_history_mode,
+            class_compression_dictionary,
         }
     }
 
@@ -65,6 +70,8 @@ impl Connection {
             running_event_filter: self.running_event_filter.clone(),
             trie_prune_mode: self.trie_prune_mode,
             blockchain_history_mode: self.blockchain_history_mode,
+            class_compression_dictionary: self.class_compression_dictionary,
+            class_compressor: RefCell::new(None),
         })
     }
 
@@ -79,6 +86,8 @@ impl Connection {
             running_event_filter: self.running_event_filter.clone(),
             trie_prune_mode: self.trie_prune_mode,
             blockchain_history_mode: self.blockchain_history_mode,
+            class_compression_dictionary: self.class_compression_dictionary,
+            class_compressor: RefCell::new(None),
         })
     }
 
@@ -97,6 +106,9 @@ pub struct Transaction<'inner> {
     running_event_filter: Arc<Mutex<RunningEventFilter>>,
     trie_prune_mode: TriePruneMode,
     pub blockchain_history_mode: BlockchainHistoryMode,
+    class_compression_dictionary: bool,
+    /// Compressor for class definitions, loaded on first use.
+    class_compressor: RefCell<Option<class::ClassCompressor>>,
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -10,7 +10,281 @@ use pathfinder_common::{
 
 use crate::prelude::*;
 
+/// Where the CASM definition of a Sierra class came from.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum CasmSource {
+    /// Compiled locally from the Sierra definition.
+    Compiled,
+    /// Fetched from the feeder gateway.
+    Gateway,
+}
+
+impl CasmSource {
+    fn to_sql_int(self) -> i64 {
+        match self {
+            CasmSource::Compiled => 0,
+            CasmSource::Gateway => 1,
+        }
+    }
+
+    fn from_sql_int(value: i64) -> anyhow::Result<Self> {
+        match value {
+            0 => Ok(CasmSource::Compiled),
+            1 => Ok(CasmSource::Gateway),
+            other => Err(anyhow::anyhow!("Unknown casm source: {other}")),
+        }
+    }
+}
+
+/// Audit record of where the CASM definition of a Sierra class came from.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct CasmProvenance {
+    pub source: CasmSource,
+    /// Seconds since the Unix epoch at which the CASM definition was stored.
+    pub recorded_at: u64,
+}
+
+/// Stored sizes of the definitions of a class.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ClassStorageInfo {
+    /// Size in bytes of the stored, compressed class definition or [None] if
+    /// the definition has not been downloaded yet.
+    pub definition_size: Option<u64>,
+    /// Whether the class definition was compressed with a trained dictionary,
+    /// see [Transaction::train_class_compression_dictionary].
+    pub dictionary_compressed: bool,
+    /// Size in bytes of the stored, compressed CASM definition. [None] for
+    /// Cairo 0 classes and Sierra classes whose CASM definition is missing or
+    /// was pruned.
+    pub casm_size: Option<u64>,
+}
+
+/// Prefix of class definitions compressed with a trained dictionary. It is
+/// followed by the little-endian `u32` id of the dictionary and the zstd frame
+/// itself. Plain zstd frames start with a different magic number so the two
+/// can't be confused.
+const DICTIONARY_MARKER: &[u8; 4] = b"PFCD";
+
+/// The minimum number of stored class definitions required to train a
+/// compression dictionary, see
+/// [Transaction::train_class_compression_dictionary].
+pub const MIN_DICTIONARY_TRAINING_SAMPLES: usize = 100;
+
+/// Compressor for class definitions, cached per [Transaction] so that the
+/// dictionary is only loaded once.
+pub(super) struct ClassCompressor {
+    dictionary_id: Option<u32>,
+    compressor: zstd::bulk::Compressor<'static>,
+}
+
 impl Transaction<'_> {
+    /// Compresses a class definition, using the most recently trained
+    /// dictionary if there is one and dictionaries are enabled.
+    fn compress_class_definition(&self, definition: &[u8]) -> anyhow::Result<Vec<u8>> {
+        let mut class_compressor = match self.class_compressor.take() {
+            Some(class_compressor) => class_compressor,
+            None => self.load_class_compressor()?,
+        };
+        let compressed = class_compressor.compressor.compress(definition);
+        let dictionary_id = class_compressor.dictionary_id;
+        self.class_compressor.replace(Some(class_compressor));
+
+        let compressed = compressed.context("Compressing class definition")?;
+        let Some(id) = dictionary_id else {
+            return Ok(compressed);
+        };
+
+        let mut result = Vec::with_capacity(DICTIONARY_MARKER.len() + 4 + compressed.len());
+        result.extend_from_slice(DICTIONARY_MARKER);
+        result.extend_from_slice(&id.to_le_bytes());
+        result.extend_from_slice(&compressed);
+        Ok(result)
+    }
+
+    /// Decompresses a class definition, selecting the dictionary it was
+    /// compressed with by its marker.
+    fn decompress_class_definition(&self, compressed: &[u8]) -> anyhow::Result<Vec<u8>> {
+        let Some(rest) = compressed.strip_prefix(DICTIONARY_MARKER) else {
+            return zstd::decode_all(compressed).context("Decompressing class definition");
+        };
+
+        let (id, frame) = rest
+            .split_first_chunk::<4>()
+            .context("Dictionary id missing from compressed class definition")?;
+        let id = u32::from_le_bytes(*id);
+
+        let mut stmt = self
+            .inner()
+            .prepare_cached("SELECT dictionary FROM class_compression_dictionaries WHERE id = ?")
+            .context("Preparing class compression dictionary query")?;
+        let dictionary = stmt
+            .query_row(params![&id], |row| row.get_blob(0).map(|x| x.to_vec()))
+            .optional()
+            .context("Querying class compression dictionary")?
+            .with_context(|| format!("Class compression dictionary {id} not found"))?;
+
+        let mut decoder = zstd::stream::read::Decoder::with_dictionary(frame, &dictionary)
+            .context("Creating zstd decoder")?;
+        let mut definition = Vec::new();
+        std::io::Read::read_to_end(&mut decoder, &mut definition)
+            .context("Decompressing class definition")?;
+
+        Ok(definition)
+    }
+
+    fn load_class_compressor(&self) -> anyhow::Result<ClassCompressor> {
+        let dictionary = if self.class_compression_dictionary {
+            self.latest_class_compression_dictionary()
+                .context("Querying class compression dictionary")?
+        } else {
+            None
+        };
+
+        let compressor = match &dictionary {
+            Some((_, dictionary)) => zstd::bulk::Compressor::with_dictionary(10, dictionary),
+            None => zstd::bulk::Compressor::new(10),
+        }
+        .context("Creating zstd compressor")?;
+
+        Ok(ClassCompressor {
+            dictionary_id: dictionary.map(|(id, _)| id),
+            compressor,
+        })
+    }
+
+    fn latest_class_compression_dictionary(&self) -> anyhow::Result<Option<(u32, Vec<u8>)>> {
+        let mut stmt = self.inner().prepare_cached(
+            "SELECT id, dictionary FROM class_compression_dictionaries ORDER BY id DESC LIMIT 1",
+        )?;
+
+        stmt.query_row([], |row| {
+            let id = row.get_i64(0)?;
+            let dictionary = row.get_blob(1).map(|x| x.to_vec())?;
+            Ok((id, dictionary))
+        })
+        .optional()
+        .context("Querying latest class compression dictionary")?
+        .map(|(id, dictionary)| {
+            let id = u32::try_from(id).context("Dictionary id out of range")?;
+            Ok((id, dictionary))
+        })
+        .transpose()
+    }
+
+    /// Returns the id of the dictionary used to compress new class definitions,
+    /// if one has been trained.
+    pub fn class_compression_dictionary_id(&self) -> anyhow::Result<Option<u32>> {
+        self.latest_class_compression_dictionary()
+            .map(|x| x.map(|(id, _)| id))
+    }
+
+    /// Trains a zstd dictionary over a random sample of at most `max_samples`
+    /// stored class definitions and stores it. If dictionaries are enabled,
+    /// all class definitions inserted afterwards are compressed with this
+    /// dictionary, existing ones are left as is.
+    ///
+    /// Returns the id of the new dictionary, or `None` if fewer than
+    /// [MIN_DICTIONARY_TRAINING_SAMPLES] class definitions are stored.
+    pub fn train_class_compression_dictionary(
+        &self,
+        max_samples: usize,
+        max_dictionary_size: usize,
+    ) -> anyhow::Result<Option<u32>> {
+        let samples = self
+            .class_definition_samples(max_samples)
+            .context("Sampling class definitions")?
+            .iter()
+            .map(|compressed| self.decompress_class_definition(compressed))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        if samples.len() < MIN_DICTIONARY_TRAINING_SAMPLES {
+            return Ok(None);
+        }
+
+        let dictionary = zstd::dict::from_samples(&samples, max_dictionary_size)
+            .context("Training class compression dictionary")?;
+
+        let id = self
+            .inner()
+            .query_row(
+                "INSERT INTO class_compression_dictionaries (dictionary) VALUES (?) RETURNING id",
+                params![&dictionary],
+                |row| row.get_i64(0),
+            )
+            .context("Inserting class compression dictionary")?;
+
+        // Pick up the new dictionary on the next insert.
+        self.class_compressor.take();
+
+        u32::try_from(id)
+            .context("Dictionary id out of range")
+            .map(Some)
+    }
+
+    /// Returns at most `max_samples` compressed class definitions. Sampling is
+    /// done by random rowid lookups, so that the definitions that aren't
+    /// sampled don't have to be read.
+    fn class_definition_samples(&self, max_samples: usize) -> anyhow::Result<Vec<Vec<u8>>> {
+        let (first, last) = self
+            .inner()
+            .query_row(
+                "SELECT MIN(rowid), MAX(rowid) FROM class_definitions",
+                [],
+                |row| Ok((row.get_optional_i64(0)?, row.get_optional_i64(1)?)),
+            )
+            .context("Querying class definition rowid range")?;
+        let (Some(first), Some(last)) = (first, last) else {
+            return Ok(Vec::new());
+        };
+
+        // Few enough rows to just take all of them.
+        if usize::try_from(last - first).is_ok_and(|span| span < max_samples) {
+            let mut stmt = self.inner().prepare_cached(
+                "SELECT definition FROM class_definitions WHERE definition IS NOT NULL",
+            )?;
+            let rows = stmt
+                .query_map([], |row| row.get_blob(0).map(|x| x.to_vec()))
+                .context("Querying class definitions")?;
+            return rows
+                .collect::<Result<Vec<_>, _>>()
+                .context("Iterating over rows");
+        }
+
+        let mut stmt = self.inner().prepare_cached(
+            r"SELECT rowid, definition FROM class_definitions
+            WHERE rowid >= ? AND definition IS NOT NULL
+            ORDER BY rowid
+            LIMIT 1",
+        )?;
+
+        let mut rng = rand::thread_rng();
+        let mut sampled = std::collections::HashSet::new();
+        let mut samples = Vec::new();
+        // Bounded, since gaps and missing definitions make rows be picked
+        // more than once.
+        for _ in 0..max_samples.saturating_mul(4) {
+            if samples.len() == max_samples {
+                break;
+            }
+
+            let rowid = rand::Rng::gen_range(&mut rng, first..=last);
+            let sample = stmt
+                .query_row(params![&rowid], |row| {
+                    Ok((row.get_i64(0)?, row.get_blob(1).map(|x| x.to_vec())?))
+                })
+                .optional()
+                .context("Querying class definition sample")?;
+
+            if let Some((rowid, definition)) = sample {
+                if sampled.insert(rowid) {
+                    samples.push(definition);
+                }
+            }
+        }
+
+        Ok(samples)
+    }
+
     pub fn insert_sierra_class(
         &self,
         sierra_hash: &SierraHash,
@@ -18,10 +292,10 @@ impl Transaction<'_> {
         casm_hash: &CasmHash,
         casm_definition: &[u8],
     ) -> anyhow::Result<()> {
-        let mut compressor = zstd::bulk::Compressor::new(10).context("Creating zstd compressor")?;
-        let sierra_definition = compressor
-            .compress(sierra_definition)
+        let sierra_definition = self
+            .compress_class_definition(sierra_definition)
             .context("Compressing sierra definition")?;
+        let mut compressor = zstd::bulk::Compressor::new(10).context("Creating zstd compressor")?;
         let casm_definition = compressor
             .compress(casm_definition)
             .context("Compressing casm definition")?;
@@ -50,6 +324,42 @@ impl Transaction<'_> {
         Ok(())
     }
 
+    /// Like [Self::insert_sierra_class], but only records the compiled class
+    /// hash and marks the CASM definition as pruned, see
+    /// [Self::casm_definition_is_pruned].
+    pub fn insert_sierra_class_without_casm(
+        &self,
+        sierra_hash: &SierraHash,
+        sierra_definition: &[u8],
+        casm_hash: &CasmHash,
+    ) -> anyhow::Result<()> {
+        let sierra_definition = self
+            .compress_class_definition(sierra_definition)
+            .context("Compressing sierra definition")?;
+
+        self.inner()
+            .execute(
+                r"INSERT OR IGNORE INTO class_definitions (hash,  definition) VALUES (?, ?)",
+                params![sierra_hash, &sierra_definition],
+            )
+            .context("Inserting sierra definition")?;
+
+        self.inner()
+            .execute(
+                r"INSERT OR REPLACE INTO casm_definitions
+                (hash, definition, compiled_class_hash, pruned)
+            VALUES
+                (:hash, NULL, :compiled_class_hash, 1)",
+                named_params! {
+                    ":hash": sierra_hash,
+                    ":compiled_class_hash": casm_hash,
+                },
+            )
+            .context("Inserting casm hash")?;
+
+        Ok(())
+    }
+
     pub fn update_sierra_class(
         &self,
         sierra_hash: &SierraHash,
@@ -57,13 +367,43 @@ impl Transaction<'_> {
         casm_hash: &CasmHash,
         casm_definition: &[u8],
     ) -> anyhow::Result<()> {
-        let mut compressor = zstd::bulk::Compressor::new(10).context("Creating zstd compressor")?;
-        let sierra_definition = compressor
-            .compress(sierra_definition)
+        self.update_sierra_definition(sierra_hash, sierra_definition)?;
+        self.update_casm_definition(sierra_hash, casm_hash, casm_definition)
+    }
+
+    /// Like [Self::update_sierra_class], but marks the CASM definition as
+    /// pruned instead of storing it, see [Self::casm_definition_is_pruned].
+    pub fn update_sierra_class_without_casm(
+        &self,
+        sierra_hash: &SierraHash,
+        sierra_definition: &[u8],
+        casm_hash: &CasmHash,
+    ) -> anyhow::Result<()> {
+        self.update_sierra_definition(sierra_hash, sierra_definition)?;
+
+        self.inner()
+            .execute(
+                r"UPDATE casm_definitions SET definition=NULL, compiled_class_hash=:compiled_class_hash, pruned=1 WHERE hash=:hash",
+                named_params! {
+                    ":compiled_class_hash": casm_hash,
+                    ":hash": sierra_hash,
+                },
+            )
+            .context("Marking casm definition as pruned")?;
+
+        Ok(())
+    }
+
+    /// Updates only the Sierra definition of a class, leaving its CASM
+    /// untouched.
+    pub fn update_sierra_definition(
+        &self,
+        sierra_hash: &SierraHash,
+        sierra_definition: &[u8],
+    ) -> anyhow::Result<()> {
+        let sierra_definition = self
+            .compress_class_definition(sierra_definition)
             .context("Compressing sierra definition")?;
-        let casm_definition = compressor
-            .compress(casm_definition)
-            .context("Compressing casm definition")?;
 
         self.inner()
             .execute(
@@ -72,9 +412,25 @@ impl Transaction<'_> {
             )
             .context("Updating sierra definition")?;
 
+        Ok(())
+    }
+
+    /// Updates only the CASM definition of a Sierra class. This also restores
+    /// a definition removed by [Self::prune_casm_definitions].
+    pub fn update_casm_definition(
+        &self,
+        sierra_hash: &SierraHash,
+        casm_hash: &CasmHash,
+        casm_definition: &[u8],
+    ) -> anyhow::Result<()> {
+        let mut compressor = zstd::bulk::Compressor::new(10).context("Creating zstd compressor")?;
+        let casm_definition = compressor
+            .compress(casm_definition)
+            .context("Compressing casm definition")?;
+
         self.inner()
             .execute(
-                r"UPDATE casm_definitions SET definition=:definition, compiled_class_hash=:compiled_class_hash WHERE hash=:hash",
+                r"UPDATE casm_definitions SET definition=:definition, compiled_class_hash=:compiled_class_hash, pruned=0 WHERE hash=:hash",
                 named_params! {
                     ":definition": &casm_definition,
                     ":compiled_class_hash": casm_hash,
@@ -86,14 +442,210 @@ impl Transaction<'_> {
         Ok(())
     }
 
+    /// Removes the CASM definitions of Sierra classes declared before `before`,
+    /// keeping their Sierra definitions and compiled class hashes. Returns the
+    /// number of definitions removed.
+    ///
+    /// Pruned definitions are not returned by [Self::casm_definition] and
+    /// friends anymore, see [Self::casm_definition_is_pruned]. Callers have to
+    /// recompile them from the Sierra definition instead, so a definition is
+    /// only pruned if `recompiles` returns `true` for the class' hash, Sierra
+    /// definition and compiled class hash. CASM fetched from the feeder gateway
+    /// is never pruned.
+    pub fn prune_casm_definitions(
+        &self,
+        before: BlockNumber,
+        mut recompiles: impl FnMut(ClassHash, &[u8], CasmHash) -> bool,
+    ) -> anyhow::Result<usize> {
+        let mut stmt = self.inner().prepare(
+            r"SELECT casm_definitions.hash, class_definitions.definition, compiled_class_hash
+            FROM casm_definitions
+            JOIN class_definitions ON class_definitions.hash = casm_definitions.hash
+            WHERE casm_definitions.definition IS NOT NULL
+                AND class_definitions.definition IS NOT NULL
+                AND class_definitions.block_number < ?
+                AND casm_definitions.hash NOT IN (
+                    SELECT hash FROM casm_provenance WHERE source = ?
+                )",
+        )?;
+        let mut rows = stmt
+            .query(params![&before, &CasmSource::Gateway.to_sql_int()])
+            .context("Querying casm definitions to prune")?;
+
+        let mut prunable = Vec::new();
+        while let Some(row) = rows.next().context("Iterating over rows")? {
+            let class_hash = row.get_class_hash(0)?;
+            let sierra_definition = self
+                .decompress_class_definition(row.get_blob(1)?)
+                .context("Decompressing sierra definition")?;
+            let casm_hash = row.get_casm_hash(2)?;
+
+            if recompiles(class_hash, &sierra_definition, casm_hash) {
+                prunable.push(class_hash);
+            }
+        }
+
+        let mut stmt = self.inner().prepare_cached(
+            "UPDATE casm_definitions SET definition=NULL, pruned=1 WHERE hash = ?",
+        )?;
+        for class_hash in &prunable {
+            stmt.execute(params![class_hash])
+                .context("Pruning casm definition")?;
+        }
+
+        Ok(prunable.len())
+    }
+
+    /// Marks the definition of a class as being written. The marker is removed
+    /// by [Self::finish_class_write] once all of the class's definitions are
+    /// stored, so a marker that is still present on startup means the write
+    /// was interrupted. It has to be committed before the write transaction is
+    /// started, otherwise it is rolled back together with the write.
+    pub fn begin_class_write(&self, class_hash: ClassHash) -> anyhow::Result<()> {
+        self.inner()
+            .execute(
+                r"INSERT OR IGNORE INTO class_writes_in_progress (hash) VALUES (?)",
+                params![&class_hash],
+            )
+            .context("Marking class write as in progress")?;
+
+        Ok(())
+    }
+
+    /// Removes the marker set by [Self::begin_class_write].
+    pub fn finish_class_write(&self, class_hash: ClassHash) -> anyhow::Result<()> {
+        self.inner()
+            .execute(
+                r"DELETE FROM class_writes_in_progress WHERE hash = ?",
+                params![&class_hash],
+            )
+            .context("Clearing class write in progress marker")?;
+
+        Ok(())
+    }
+
+    /// Returns the classes whose write was started with
+    /// [Self::begin_class_write] but never finished.
+    pub fn interrupted_class_writes(&self) -> anyhow::Result<Vec<ClassHash>> {
+        let mut stmt = self
+            .inner()
+            .prepare_cached(r"SELECT hash FROM class_writes_in_progress ORDER BY hash")?;
+
+        let mut rows = stmt
+            .query_map([], |row| row.get_class_hash(0))
+            .context("Querying interrupted class writes")?;
+
+        let mut result = Vec::new();
+        while let Some(class_hash) = rows
+            .next()
+            .transpose()
+            .context("Iterating over interrupted class write rows")?
+        {
+            result.push(class_hash);
+        }
+
+        Ok(result)
+    }
+
+    /// Clears the definition of a class, keeping its declaration, so that it
+    /// is considered missing again.
+    pub fn clear_class_definition(&self, class_hash: ClassHash) -> anyhow::Result<()> {
+        self.inner()
+            .execute(
+                r"UPDATE class_definitions SET definition=NULL WHERE hash=?",
+                params![&class_hash],
+            )
+            .context("Clearing class definition")?;
+
+        Ok(())
+    }
+
+    /// Returns the stored sizes of the definitions of a class or [None] if the
+    /// class has not been declared.
+    ///
+    /// All definitions are stored zstd compressed, so these sizes are the
+    /// on-disk footprint rather than the size of the definitions themselves.
+    pub fn class_storage_info(
+        &self,
+        class_hash: ClassHash,
+    ) -> anyhow::Result<Option<ClassStorageInfo>> {
+        let mut stmt = self.inner().prepare_cached(
+            r"SELECT length(class_definitions.definition),
+                substr(class_definitions.definition, 1, 4),
+                length(casm_definitions.definition)
+            FROM class_definitions
+            LEFT JOIN casm_definitions ON casm_definitions.hash = class_definitions.hash
+            WHERE class_definitions.hash = ?",
+        )?;
+
+        let info = stmt
+            .query_row(params![&class_hash], |row| {
+                let definition_size = row.get::<_, Option<i64>>(0)?;
+                let prefix = row.get::<_, Option<Vec<u8>>>(1)?;
+                let casm_size = row.get::<_, Option<i64>>(2)?;
+                Ok((definition_size, prefix, casm_size))
+            })
+            .optional()
+            .context("Querying class storage info")?;
+
+        let Some((definition_size, prefix, casm_size)) = info else {
+            return Ok(None);
+        };
+
+        Ok(Some(ClassStorageInfo {
+            definition_size: definition_size
+                .map(u64::try_from)
+                .transpose()
+                .context("Parsing definition size")?,
+            dictionary_compressed: prefix.as_deref() == Some(DICTIONARY_MARKER.as_slice()),
+            casm_size: casm_size
+                .map(u64::try_from)
+                .transpose()
+                .context("Parsing casm definition size")?,
+        }))
+    }
+
+    /// Returns whether the CASM definition of the class was deliberately not
+    /// stored, see [Self::prune_casm_definitions]. It has to be recompiled
+    /// from the Sierra definition. Definitions which were not downloaded yet
+    /// are not pruned.
+    pub fn casm_definition_is_pruned(&self, class_hash: ClassHash) -> anyhow::Result<bool> {
+        let mut stmt = self
+            .inner()
+            .prepare_cached("SELECT pruned FROM casm_definitions WHERE hash = ?")?;
+
+        let pruned = stmt
+            .query_row(params![&class_hash], |row| row.get::<_, bool>(0))
+            .optional()
+            .context("Querying for pruned casm definition")?;
+
+        Ok(pruned.unwrap_or(false))
+    }
+
+    /// Records the compiled class hash of a Sierra class without touching its
+    /// definitions.
+    pub fn insert_casm_hash(
+        &self,
+        sierra_hash: &SierraHash,
+        casm_hash: &CasmHash,
+    ) -> anyhow::Result<()> {
+        self.inner()
+            .execute(
+                r"INSERT OR IGNORE INTO casm_definitions (hash, compiled_class_hash) VALUES (?, ?)",
+                params![sierra_hash, casm_hash],
+            )
+            .context("Inserting casm hash")?;
+
+        Ok(())
+    }
+
     pub fn insert_cairo_class(
         &self,
         cairo_hash: ClassHash,
         definition: &[u8],
     ) -> anyhow::Result<()> {
-        let mut compressor = zstd::bulk::Compressor::new(10).context("Creating zstd compressor")?;
-        let definition = compressor
-            .compress(definition)
+        let definition = self
+            .compress_class_definition(definition)
             .context("Compressing cairo definition")?;
 
         self.inner()
@@ -111,9 +663,8 @@ impl Transaction<'_> {
         cairo_hash: ClassHash,
         definition: &[u8],
     ) -> anyhow::Result<()> {
-        let mut compressor = zstd::bulk::Compressor::new(10).context("Creating zstd compressor")?;
-        let definition = compressor
-            .compress(definition)
+        let definition = self
+            .compress_class_definition(definition)
             .context("Compressing cairo definition")?;
 
         self.inner()
@@ -172,14 +723,16 @@ impl Transaction<'_> {
         let Some((block_number, definition)) = result else {
             return Ok(None);
         };
-        let definition =
-            zstd::decode_all(definition.as_slice()).context("Decompressing class definition")?;
+        let definition = self.decompress_class_definition(&definition)?;
 
         Ok(Some((block_number, definition)))
     }
 
     /// Returns the compressed class definition if it has been declared at
     /// `block_id`.
+    ///
+    /// Note that the definition may have been compressed with a trained
+    /// dictionary, see [Self::train_class_compression_dictionary].
     pub fn compressed_class_definition_at(
         &self,
         block_id: FinalizedBlockId,
@@ -257,8 +810,7 @@ impl Transaction<'_> {
         let Some((block_number, definition)) = definition else {
             return Ok(None);
         };
-        let definition =
-            zstd::decode_all(definition.as_slice()).context("Decompressing class definition")?;
+        let definition = self.decompress_class_definition(&definition)?;
 
         Ok(Some((block_number, definition)))
     }
@@ -272,7 +824,7 @@ impl Transaction<'_> {
             .prepare_cached("SELECT definition FROM casm_definitions WHERE hash = ?")?;
         let definition = stmt
             .query_row(params![&class_hash], |row| {
-                row.get_blob(0).map(|x| x.to_vec())
+                row.get_optional_blob(0).map(|x| x.map(<[u8]>::to_vec))
             })
             .optional()
             .context("Querying for compiled class definition")?;
@@ -280,10 +832,8 @@ impl Transaction<'_> {
         let Some(definition) = definition else {
             return Ok(None);
         };
-        let definition = zstd::decode_all(definition.as_slice())
-            .context("Decompressing compiled class definition")?;
 
-        Ok(Some(definition))
+        decompress_casm(definition)
     }
 
     /// Returns the uncompressed compiled class definition, as well as the block
@@ -293,7 +843,7 @@ impl Transaction<'_> {
         class_hash: ClassHash,
     ) -> anyhow::Result<Option<(Option<BlockNumber>, Vec<u8>)>> {
         let from_row = |row: &rusqlite::Row<'_>| {
-            let definition = row.get_blob(0).map(|x| x.to_vec())?;
+            let definition = row.get_optional_blob(0).map(|x| x.map(<[u8]>::to_vec))?;
             let block_number = row.get_optional_block_number(1)?;
             Ok((block_number, definition))
         };
@@ -319,10 +869,9 @@ impl Transaction<'_> {
         let Some((block_number, definition)) = result else {
             return Ok(None);
         };
-        let definition = zstd::decode_all(definition.as_slice())
-            .context("Decompressing compiled class definition")?;
+        let definition = decompress_casm(definition)?;
 
-        Ok(Some((block_number, definition)))
+        Ok(definition.map(|definition| (block_number, definition)))
     }
 
     /// Returns the uncompressed compiled class definition if it has been
@@ -345,7 +894,7 @@ impl Transaction<'_> {
         class_hash: ClassHash,
     ) -> anyhow::Result<Option<(Option<BlockNumber>, Vec<u8>)>> {
         let from_row = |row: &rusqlite::Row<'_>| {
-            let definition = row.get_blob(0).map(|x| x.to_vec())?;
+            let definition = row.get_optional_blob(0).map(|x| x.map(<[u8]>::to_vec))?;
             let block_number = row.get_optional_block_number(1)?;
             Ok((block_number, definition))
         };
@@ -404,10 +953,9 @@ impl Transaction<'_> {
         let Some((block_number, definition)) = definition else {
             return Ok(None);
         };
-        let definition = zstd::decode_all(definition.as_slice())
-            .context("Decompressing compiled class definition")?;
+        let definition = decompress_casm(definition)?;
 
-        Ok(Some((block_number, definition)))
+        Ok(definition.map(|definition| (block_number, definition)))
     }
 
     /// Returns the compiled class hash for a class.
@@ -480,9 +1028,186 @@ impl Transaction<'_> {
         Ok(compiled_class_hash)
     }
 
+    /// Records where the CASM definition of a Sierra class came from,
+    /// replacing any previous record for the same class.
+    pub fn insert_casm_provenance(
+        &self,
+        sierra_hash: &SierraHash,
+        provenance: CasmProvenance,
+    ) -> anyhow::Result<()> {
+        self.inner()
+            .execute(
+                r"INSERT OR REPLACE INTO casm_provenance (hash, source, recorded_at) VALUES (?, ?, ?)",
+                params![
+                    sierra_hash,
+                    &provenance.source.to_sql_int(),
+                    &provenance.recorded_at
+                ],
+            )
+            .context("Inserting casm provenance")?;
+
+        Ok(())
+    }
+
+    /// Returns where the CASM definition of a Sierra class came from, if this
+    /// was recorded.
+    pub fn casm_provenance(&self, class_hash: ClassHash) -> anyhow::Result<Option<CasmProvenance>> {
+        let mut stmt = self
+            .inner()
+            .prepare_cached("SELECT source, recorded_at FROM casm_provenance WHERE hash = ?")?;
+
+        let provenance = stmt
+            .query_row(params![&class_hash], |row| {
+                Ok((row.get_i64(0)?, row.get_i64(1)?))
+            })
+            .optional()
+            .context("Querying casm provenance")?;
+
+        let Some((source, recorded_at)) = provenance else {
+            return Ok(None);
+        };
+
+        Ok(Some(CasmProvenance {
+            source: CasmSource::from_sql_int(source)?,
+            recorded_at: recorded_at.try_into().context("Parsing recorded_at")?,
+        }))
+    }
+
+    /// Returns the hashes of all Sierra classes whose CASM definition was
+    /// recorded as coming from `source`, ordered by class hash.
+    pub fn classes_with_casm_source(&self, source: CasmSource) -> anyhow::Result<Vec<ClassHash>> {
+        let mut stmt = self
+            .inner()
+            .prepare_cached("SELECT hash FROM casm_provenance WHERE source = ? ORDER BY hash")?;
+
+        let mut rows = stmt
+            .query_map(params![&source.to_sql_int()], |row| row.get_class_hash(0))
+            .context("Querying classes by casm source")?;
+
+        let mut result = Vec::new();
+        while let Some(class_hash) = rows
+            .next()
+            .transpose()
+            .context("Iterating over casm provenance rows")?
+        {
+            result.push(class_hash);
+        }
+
+        Ok(result)
+    }
+
+    /// Returns up to `limit` Sierra classes with a stored CASM definition along
+    /// with their casm hash, ordered by class hash and starting after `after`.
+    /// Lets all stored CASM be walked in batches that can be resumed later.
+    pub fn casm_hashes_after(
+        &self,
+        after: Option<ClassHash>,
+        limit: usize,
+    ) -> anyhow::Result<Vec<(ClassHash, CasmHash)>> {
+        let mut stmt = self.inner().prepare_cached(
+            r"SELECT hash, compiled_class_hash FROM casm_definitions
+            WHERE definition IS NOT NULL AND (?1 IS NULL OR hash > ?1)
+            ORDER BY hash
+            LIMIT ?2",
+        )?;
+
+        let mut rows = stmt
+            .query_map(params![&after, &limit], |row| {
+                Ok((row.get_class_hash(0)?, row.get_casm_hash(1)?))
+            })
+            .context("Querying casm hashes")?;
+
+        let mut result = Vec::new();
+        while let Some(row) = rows.next().transpose().context("Iterating over rows")? {
+            result.push(row);
+        }
+
+        Ok(result)
+    }
+
+    /// Returns the hashes of all classes whose definition is stored but which
+    /// have no declaration at all, ordered by class hash. That is, no state
+    /// update declares the class and, for Sierra classes, no casm hash is
+    /// known.
+    ///
+    /// Whether the declaring block's header is still stored does not matter,
+    /// blockchain history pruning removes the headers of old blocks. Sierra
+    /// classes of the pending block already have a casm hash and are not
+    /// orphans. Cairo 0 classes of the pending block are not declared yet
+    /// either, so only classes stored before the most recently declared class,
+    /// ie. before the latest L2 head, are considered.
+    ///
+    /// This scans all class definitions.
+    pub fn orphan_classes(&self) -> anyhow::Result<Vec<ClassHash>> {
+        let mut stmt = self.inner().prepare_cached(
+            r"SELECT hash FROM class_definitions
+            WHERE definition IS NOT NULL
+                AND block_number IS NULL
+                AND NOT EXISTS (
+                    SELECT 1 FROM casm_definitions
+                    WHERE casm_definitions.hash = class_definitions.hash
+                )
+                AND rowid < (
+                    SELECT MAX(rowid) FROM class_definitions WHERE block_number IS NOT NULL
+                )
+            ORDER BY hash",
+        )?;
+
+        let mut rows = stmt
+            .query_map([], |row| row.get_class_hash(0))
+            .context("Querying orphan classes")?;
+
+        let mut result = Vec::new();
+        while let Some(class_hash) = rows
+            .next()
+            .transpose()
+            .context("Iterating over orphan class rows")?
+        {
+            result.push(class_hash);
+        }
+
+        Ok(result)
+    }
+
+    /// Deletes all [orphan classes](Self::orphan_classes) along with their
+    /// CASM definitions and provenance. Returns the number of deleted classes.
+    pub fn prune_orphan_classes(&self) -> anyhow::Result<usize> {
+        let orphans = self.orphan_classes()?;
+
+        let mut delete_casm = self
+            .inner()
+            .prepare_cached("DELETE FROM casm_definitions WHERE hash = ?")?;
+        let mut delete_provenance = self
+            .inner()
+            .prepare_cached("DELETE FROM casm_provenance WHERE hash = ?")?;
+        let mut delete_class = self
+            .inner()
+            .prepare_cached("DELETE FROM class_definitions WHERE hash = ?")?;
+
+        for class_hash in &orphans {
+            delete_casm
+                .execute(params![class_hash])
+                .context("Deleting orphan casm definition")?;
+            delete_provenance
+                .execute(params![class_hash])
+                .context("Deleting orphan casm provenance")?;
+            delete_class
+                .execute(params![class_hash])
+                .context("Deleting orphan class definition")?;
+        }
+
+        Ok(orphans.len())
+    }
+
+    /// Returns whether the class is a Sierra class, or `None` if the class is
+    /// not known.
     pub fn is_sierra(&self, class_hash: ClassHash) -> anyhow::Result<Option<bool>> {
         let mut stmt = self.inner().prepare_cached(
-            "SELECT EXISTS(SELECT 1 FROM casm_definitions WHERE casm_definitions.hash = ?)",
+            r"SELECT EXISTS(
+                SELECT 1 FROM casm_definitions WHERE casm_definitions.hash = class_definitions.hash
+            )
+            FROM class_definitions
+            WHERE hash = ?",
         )?;
 
         let is_sierra = stmt
@@ -493,6 +1218,26 @@ impl Transaction<'_> {
         Ok(is_sierra)
     }
 
+    /// Returns the number of the block which declared the class, or `None` if
+    /// it is not declared in any block. Unlike
+    /// [Self::class_definition_with_block_number] this does not require the
+    /// definition to be stored.
+    pub fn class_declaration_block(
+        &self,
+        class_hash: ClassHash,
+    ) -> anyhow::Result<Option<BlockNumber>> {
+        let mut stmt = self
+            .inner()
+            .prepare_cached("SELECT block_number FROM class_definitions WHERE hash = ?")?;
+
+        let block_number = stmt
+            .query_row(params![&class_hash], |row| row.get_optional_block_number(0))
+            .optional()
+            .context("Querying class declaration block")?;
+
+        Ok(block_number.flatten())
+    }
+
     pub fn insert_class_commitment_leaf(
         &self,
         block: BlockNumber,
@@ -523,6 +1268,17 @@ impl Transaction<'_> {
     }
 }
 
+/// Decompresses a CASM definition as read from the database. Returns `None` if
+/// the definition is not stored, ie. it was pruned or not downloaded yet.
+fn decompress_casm(definition: Option<Vec<u8>>) -> anyhow::Result<Option<Vec<u8>>> {
+    definition
+        .map(|definition| {
+            zstd::decode_all(definition.as_slice())
+                .context("Decompressing compiled class definition")
+        })
+        .transpose()
+}
+
 #[cfg(test)]
 mod tests {
     use pathfinder_common::macro_prelude::*;
@@ -624,6 +1380,552 @@ mod tests {
         assert_eq!(definition, sierra_definition);
     }
 
+    #[test]
+    fn is_sierra() {
+        let mut connection = crate::StorageBuilder::in_memory()
+            .unwrap()
+            .connection()
+            .unwrap();
+        let tx = connection.transaction().unwrap();
+
+        let cairo_hash = class_hash_bytes!(b"cairo hash");
+        let sierra_hash = sierra_hash_bytes!(b"sierra hash");
+        tx.insert_cairo_class(cairo_hash, b"cairo program").unwrap();
+        tx.insert_sierra_class(
+            &sierra_hash,
+            b"sierra program",
+            &casm_hash_bytes!(b"casm hash"),
+            b"casm program",
+        )
+        .unwrap();
+
+        assert_eq!(tx.is_sierra(cairo_hash).unwrap(), Some(false));
+        assert_eq!(tx.is_sierra(ClassHash(sierra_hash.0)).unwrap(), Some(true));
+        assert_eq!(tx.is_sierra(class_hash_bytes!(b"unknown")).unwrap(), None);
+    }
+
+    #[test]
+    fn casm_hashes_after() {
+        let mut connection = crate::StorageBuilder::in_memory()
+            .unwrap()
+            .connection()
+            .unwrap();
+        let tx = connection.transaction().unwrap();
+
+        let hashes = [
+            sierra_hash!("0x1"),
+            sierra_hash!("0x2"),
+            sierra_hash!("0x3"),
+        ];
+        for (i, hash) in hashes.iter().enumerate() {
+            tx.insert_sierra_class(
+                hash,
+                b"sierra program",
+                &CasmHash(hash.0),
+                format!("casm program {i}").as_bytes(),
+            )
+            .unwrap();
+        }
+        // Classes without a CASM definition are skipped.
+        tx.inner()
+            .execute(
+                "UPDATE casm_definitions SET definition = NULL WHERE hash = ?",
+                params![&hashes[1]],
+            )
+            .unwrap();
+
+        let expected = |hash: &SierraHash| (ClassHash(hash.0), CasmHash(hash.0));
+        assert_eq!(
+            tx.casm_hashes_after(None, 10).unwrap(),
+            vec![expected(&hashes[0]), expected(&hashes[2])]
+        );
+        assert_eq!(
+            tx.casm_hashes_after(None, 1).unwrap(),
+            vec![expected(&hashes[0])]
+        );
+        assert_eq!(
+            tx.casm_hashes_after(Some(ClassHash(hashes[0].0)), 10)
+                .unwrap(),
+            vec![expected(&hashes[2])]
+        );
+        assert_eq!(
+            tx.casm_hashes_after(Some(ClassHash(hashes[2].0)), 10)
+                .unwrap(),
+            vec![]
+        );
+    }
+
+    #[test]
+    fn prune_casm_definitions() {
+        let mut connection = crate::StorageBuilder::in_memory()
+            .unwrap()
+            .connection()
+            .unwrap();
+        let tx = connection.transaction().unwrap();
+
+        let old_hash = sierra_hash_bytes!(b"old sierra");
+        let new_hash = sierra_hash_bytes!(b"new sierra");
+        let gateway_hash = sierra_hash_bytes!(b"gateway sierra");
+        let unreproducible_hash = sierra_hash_bytes!(b"unreproducible sierra");
+        let casm_hash = casm_hash_bytes!(b"casm hash");
+        for (hash, block) in [
+            (old_hash, 0),
+            (new_hash, 1),
+            (gateway_hash, 0),
+            (unreproducible_hash, 0),
+        ] {
+            tx.insert_sierra_class(&hash, b"sierra program", &casm_hash, b"casm program")
+                .unwrap();
+            tx.inner()
+                .execute(
+                    "UPDATE class_definitions SET block_number = ? WHERE hash = ?",
+                    params![&BlockNumber::new_or_panic(block), &hash],
+                )
+                .unwrap();
+        }
+        tx.insert_casm_provenance(
+            &gateway_hash,
+            CasmProvenance {
+                source: CasmSource::Gateway,
+                recorded_at: 0,
+            },
+        )
+        .unwrap();
+
+        let unreproducible = ClassHash(unreproducible_hash.0);
+        let mut tried = Vec::new();
+        let mut recompiles =
+            |class_hash: ClassHash, sierra_definition: &[u8], compiled_class_hash| {
+                assert_eq!(sierra_definition, b"sierra program");
+                assert_eq!(compiled_class_hash, casm_hash);
+                tried.push(class_hash);
+                class_hash != unreproducible
+            };
+        assert_eq!(
+            tx.prune_casm_definitions(BlockNumber::new_or_panic(1), &mut recompiles)
+                .unwrap(),
+            1
+        );
+        // Already pruned definitions are not counted again.
+        assert_eq!(
+            tx.prune_casm_definitions(BlockNumber::new_or_panic(1), &mut recompiles)
+                .unwrap(),
+            0
+        );
+        // CASM fetched from the gateway is not even tried.
+        let old_class = ClassHash(old_hash.0);
+        tried.sort();
+        let mut expected = vec![old_class, unreproducible, unreproducible];
+        expected.sort();
+        assert_eq!(tried, expected);
+
+        let new_class = ClassHash(new_hash.0);
+        let gateway_class = ClassHash(gateway_hash.0);
+        assert!(tx.casm_definition_is_pruned(old_class).unwrap());
+        assert_eq!(tx.casm_definition(old_class).unwrap(), None);
+        for class_hash in [new_class, gateway_class, unreproducible] {
+            assert!(!tx.casm_definition_is_pruned(class_hash).unwrap());
+            assert_eq!(
+                tx.casm_definition(class_hash).unwrap().unwrap(),
+                b"casm program"
+            );
+        }
+        assert!(!tx.casm_definition_is_pruned(class_hash!("0x404")).unwrap());
+
+        // The Sierra definition and the compiled class hash are kept.
+        assert_eq!(
+            tx.class_definition(old_class).unwrap().unwrap(),
+            b"sierra program"
+        );
+        assert_eq!(tx.casm_hash(old_class).unwrap(), Some(casm_hash));
+
+        tx.update_casm_definition(&old_hash, &casm_hash, b"recompiled")
+            .unwrap();
+        assert!(!tx.casm_definition_is_pruned(old_class).unwrap());
+        assert_eq!(
+            tx.casm_definition(old_class).unwrap().unwrap(),
+            b"recompiled"
+        );
+    }
+
+    #[test]
+    fn sierra_class_without_casm() {
+        let mut connection = crate::StorageBuilder::in_memory()
+            .unwrap()
+            .connection()
+            .unwrap();
+        let tx = connection.transaction().unwrap();
+
+        let inserted = sierra_hash_bytes!(b"inserted");
+        let updated = sierra_hash_bytes!(b"updated");
+        let undownloaded = sierra_hash_bytes!(b"undownloaded");
+        let casm_hash = casm_hash_bytes!(b"casm hash");
+        tx.insert_state_update(
+            BlockNumber::GENESIS,
+            &pathfinder_common::StateUpdate::default()
+                .with_declared_sierra_class(updated, casm_hash)
+                .with_declared_sierra_class(undownloaded, casm_hash),
+        )
+        .unwrap();
+
+        tx.insert_sierra_class_without_casm(&inserted, b"inserted program", &casm_hash)
+            .unwrap();
+        tx.update_sierra_class_without_casm(&updated, b"updated program", &casm_hash)
+            .unwrap();
+
+        for (hash, definition) in [
+            (inserted, b"inserted program".as_slice()),
+            (updated, b"updated program".as_slice()),
+        ] {
+            let class_hash = ClassHash(hash.0);
+            assert_eq!(
+                tx.class_definition(class_hash).unwrap().unwrap(),
+                definition
+            );
+            assert_eq!(tx.casm_hash(class_hash).unwrap(), Some(casm_hash));
+            assert!(tx.casm_definition_is_pruned(class_hash).unwrap());
+            assert_eq!(tx.casm_definition(class_hash).unwrap(), None);
+        }
+        assert_eq!(
+            tx.casm_definition_at(FinalizedBlockId::Latest, ClassHash(updated.0))
+                .unwrap(),
+            None
+        );
+
+        // A class which was not downloaded yet is not pruned.
+        let undownloaded = ClassHash(undownloaded.0);
+        assert!(!tx.casm_definition_is_pruned(undownloaded).unwrap());
+        assert_eq!(tx.casm_definition(undownloaded).unwrap(), None);
+    }
+
+    #[test]
+    fn interrupted_class_writes() {
+        let mut connection = crate::StorageBuilder::in_memory()
+            .unwrap()
+            .connection()
+            .unwrap();
+        let tx = connection.transaction().unwrap();
+
+        let pruned = sierra_hash_bytes!(b"pruned");
+        let complete = sierra_hash_bytes!(b"complete");
+        let interrupted = sierra_hash_bytes!(b"interrupted");
+        let casm_hash = casm_hash_bytes!(b"casm hash");
+        for (hash, block) in [(pruned, 0), (complete, 1), (interrupted, 1)] {
+            let class_hash = ClassHash(hash.0);
+            tx.begin_class_write(class_hash).unwrap();
+            tx.insert_sierra_class(&hash, b"sierra program", &casm_hash, b"casm program")
+                .unwrap();
+            tx.inner()
+                .execute(
+                    "UPDATE class_definitions SET block_number = ? WHERE hash = ?",
+                    params![&BlockNumber::new_or_panic(block), &hash],
+                )
+                .unwrap();
+            // Simulate a crash before the write of the last class finished.
+            if hash != interrupted {
+                tx.finish_class_write(class_hash).unwrap();
+            }
+        }
+        // Pruned CASM definitions are not mistaken for interrupted writes.
+        tx.prune_casm_definitions(BlockNumber::new_or_panic(2), |_, _, _| true)
+            .unwrap();
+
+        let interrupted = ClassHash(interrupted.0);
+        assert_eq!(tx.interrupted_class_writes().unwrap(), vec![interrupted]);
+
+        tx.clear_class_definition(interrupted).unwrap();
+        tx.finish_class_write(interrupted).unwrap();
+        assert!(tx.interrupted_class_writes().unwrap().is_empty());
+        assert_eq!(
+            tx.first_block_with_missing_class_definitions().unwrap(),
+            Some(BlockNumber::new_or_panic(1))
+        );
+        assert_eq!(tx.casm_hash(interrupted).unwrap(), Some(casm_hash));
+    }
+
+    #[test]
+    fn dictionary_compressed_classes_round_trip() {
+        let db_dir = tempfile::TempDir::new().unwrap();
+        let db_path = db_dir.path().join("db.sqlite");
+        let mut connection = crate::StorageBuilder::file(db_path.clone())
+            .class_compression_dictionary(true)
+            .migrate()
+            .unwrap()
+            .create_pool(std::num::NonZeroU32::new(1).unwrap())
+            .unwrap()
+            .connection()
+            .unwrap();
+        let tx = connection.transaction().unwrap();
+
+        let definition = |i: u64| {
+            serde_json::to_vec(&serde_json::json!({
+                "abi": [{ "name": format!("function_{i}"), "type": "function" }],
+                "program": {
+                    "data": [format!("0x{i:x}"), "0x40780017fff7fff", "0x1"],
+                    "builtins": ["pedersen", "range_check"],
+                },
+                "entry_points_by_type": {
+                    "EXTERNAL": [{
+                        "offset": format!("0x{i:x}"),
+                        "selector": format!("0x{i:064x}"),
+                    }],
+                },
+            }))
+            .unwrap()
+        };
+        let insert = |i: u64| {
+            let hash = ClassHash(Felt::from_u64(i));
+            tx.insert_cairo_class(hash, &definition(i)).unwrap();
+            hash
+        };
+        let stored = |hash: ClassHash| -> Vec<u8> {
+            tx.inner()
+                .query_row(
+                    "SELECT definition FROM class_definitions WHERE hash = ?",
+                    params![&hash],
+                    |row| row.get_blob(0).map(|x| x.to_vec()),
+                )
+                .unwrap()
+        };
+
+        // Too few classes to train on.
+        let plain = insert(1);
+        assert_eq!(
+            tx.train_class_compression_dictionary(1000, 4096).unwrap(),
+            None
+        );
+
+        for i in 2..=200 {
+            insert(i);
+        }
+        let first_id = tx
+            .train_class_compression_dictionary(1000, 4096)
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            tx.class_compression_dictionary_id().unwrap(),
+            Some(first_id)
+        );
+
+        let with_first = insert(1001);
+        let second_id = tx
+            .train_class_compression_dictionary(1000, 4096)
+            .unwrap()
+            .unwrap();
+        assert_ne!(first_id, second_id);
+        let with_second = insert(1002);
+
+        assert!(!stored(plain).starts_with(DICTIONARY_MARKER));
+        assert!(stored(with_first).starts_with(DICTIONARY_MARKER));
+        assert_eq!(stored(with_first)[4..8], first_id.to_le_bytes());
+        assert_eq!(stored(with_second)[4..8], second_id.to_le_bytes());
+
+        for (hash, i) in [(plain, 1), (with_first, 1001), (with_second, 1002)] {
+            assert_eq!(tx.class_definition(hash).unwrap(), Some(definition(i)));
+        }
+
+        let dictionary_compressed = |hash| {
+            tx.class_storage_info(hash)
+                .unwrap()
+                .unwrap()
+                .dictionary_compressed
+        };
+        assert!(!dictionary_compressed(plain));
+        assert!(dictionary_compressed(with_first));
+        tx.commit().unwrap();
+
+        // Dictionaries are not used unless enabled, but can still be read.
+        let mut connection = crate::StorageBuilder::file(db_path)
+            .migrate()
+            .unwrap()
+            .create_pool(std::num::NonZeroU32::new(1).unwrap())
+            .unwrap()
+            .connection()
+            .unwrap();
+        let tx = connection.transaction().unwrap();
+
+        let without_dictionary = ClassHash(Felt::from_u64(1003));
+        tx.insert_cairo_class(without_dictionary, &definition(1003))
+            .unwrap();
+        assert!(
+            !tx.class_storage_info(without_dictionary)
+                .unwrap()
+                .unwrap()
+                .dictionary_compressed
+        );
+        assert_eq!(
+            tx.class_definition(with_second).unwrap(),
+            Some(definition(1002))
+        );
+    }
+
+    #[test]
+    fn class_storage_info() {
+        let mut connection = crate::StorageBuilder::in_memory()
+            .unwrap()
+            .connection()
+            .unwrap();
+        let tx = connection.transaction().unwrap();
+
+        let sierra = sierra_hash_bytes!(b"sierra");
+        tx.insert_sierra_class(
+            &sierra,
+            b"sierra program",
+            &casm_hash_bytes!(b"casm hash"),
+            b"casm program",
+        )
+        .unwrap();
+        let cairo = class_hash_bytes!(b"cairo");
+        tx.insert_cairo_class(cairo, b"cairo program").unwrap();
+        let compressed_size = |hash: ClassHash, table: &str| -> u64 {
+            tx.inner()
+                .query_row(
+                    &format!("SELECT length(definition) FROM {table} WHERE hash = ?"),
+                    params![&hash],
+                    |row| row.get::<_, i64>(0),
+                )
+                .unwrap()
+                .try_into()
+                .unwrap()
+        };
+
+        let sierra = ClassHash(sierra.0);
+        assert_eq!(
+            tx.class_storage_info(sierra).unwrap(),
+            Some(ClassStorageInfo {
+                definition_size: Some(compressed_size(sierra, "class_definitions")),
+                dictionary_compressed: false,
+                casm_size: Some(compressed_size(sierra, "casm_definitions")),
+            })
+        );
+        assert_eq!(
+            tx.class_storage_info(cairo).unwrap(),
+            Some(ClassStorageInfo {
+                definition_size: Some(compressed_size(cairo, "class_definitions")),
+                dictionary_compressed: false,
+                casm_size: None,
+            })
+        );
+
+        tx.inner()
+            .execute(
+                "UPDATE casm_definitions SET definition = NULL WHERE hash = ?",
+                params![&sierra],
+            )
+            .unwrap();
+        assert_eq!(
+            tx.class_storage_info(sierra).unwrap().unwrap().casm_size,
+            None
+        );
+
+        assert_eq!(
+            tx.class_storage_info(class_hash_bytes!(b"unknown"))
+                .unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn casm_provenance() {
+        let mut connection = crate::StorageBuilder::in_memory()
+            .unwrap()
+            .connection()
+            .unwrap();
+        let tx = connection.transaction().unwrap();
+
+        let compiled = sierra_hash_bytes!(b"compiled");
+        let fetched = sierra_hash_bytes!(b"fetched");
+        let unrecorded = sierra_hash_bytes!(b"unrecorded");
+        for hash in [compiled, fetched, unrecorded] {
+            tx.insert_sierra_class(&hash, b"sierra", &casm_hash_bytes!(b"casm"), b"casm")
+                .unwrap();
+        }
+
+        let compiled_provenance = CasmProvenance {
+            source: CasmSource::Compiled,
+            recorded_at: 1,
+        };
+        let fetched_provenance = CasmProvenance {
+            source: CasmSource::Gateway,
+            recorded_at: 2,
+        };
+        tx.insert_casm_provenance(&compiled, compiled_provenance)
+            .unwrap();
+        tx.insert_casm_provenance(&fetched, fetched_provenance)
+            .unwrap();
+
+        assert_eq!(
+            tx.casm_provenance(ClassHash(compiled.0)).unwrap(),
+            Some(compiled_provenance)
+        );
+        assert_eq!(
+            tx.casm_provenance(ClassHash(fetched.0)).unwrap(),
+            Some(fetched_provenance)
+        );
+        assert_eq!(tx.casm_provenance(ClassHash(unrecorded.0)).unwrap(), None);
+
+        assert_eq!(
+            tx.classes_with_casm_source(CasmSource::Gateway).unwrap(),
+            vec![ClassHash(fetched.0)]
+        );
+        assert_eq!(
+            tx.classes_with_casm_source(CasmSource::Compiled).unwrap(),
+            vec![ClassHash(compiled.0)]
+        );
+    }
+
+    #[test]
+    fn orphan_classes() {
+        let storage = crate::StorageBuilder::in_memory().unwrap();
+        let mut connection = storage.connection().unwrap();
+
+        // Stored but never declared.
+        let undeclared = class_hash_bytes!(b"undeclared");
+        let tx = connection.transaction().unwrap();
+        tx.insert_cairo_class(undeclared, b"cairo").unwrap();
+        tx.commit().unwrap();
+
+        let blocks = crate::fake::generate::n_blocks(3);
+        crate::fake::fill(&storage, &blocks, None);
+
+        let tx = connection.transaction().unwrap();
+        // Cairo 0 classes of the pending block are stored after the latest L2 head.
+        let pending_cairo = class_hash_bytes!(b"pending cairo");
+        tx.insert_cairo_class(pending_cairo, b"pending cairo")
+            .unwrap();
+        // Sierra classes of the pending block only have a casm hash.
+        let pending = sierra_hash_bytes!(b"pending");
+        tx.insert_sierra_class(&pending, b"sierra", &casm_hash_bytes!(b"casm"), b"casm")
+            .unwrap();
+        // Blockchain history pruning removes the headers of declaring blocks.
+        tx.prune_block(BlockNumber::GENESIS).unwrap();
+        tx.prune_block(BlockNumber::GENESIS + 1).unwrap();
+
+        assert_eq!(tx.orphan_classes().unwrap(), vec![undeclared]);
+
+        assert_eq!(tx.prune_orphan_classes().unwrap(), 1);
+        assert!(tx.orphan_classes().unwrap().is_empty());
+        assert_eq!(tx.class_definition(undeclared).unwrap(), None);
+        assert!(tx.class_definition(ClassHash(pending.0)).unwrap().is_some());
+        assert!(tx.class_definition(pending_cairo).unwrap().is_some());
+
+        // Declared classes are left untouched.
+        for block in &blocks {
+            for (hash, definition) in &block.cairo_defs {
+                assert_eq!(
+                    tx.class_definition(*hash).unwrap().as_ref(),
+                    Some(definition)
+                );
+            }
+            for (hash, definition, _) in &block.sierra_defs {
+                assert_eq!(
+                    tx.class_definition(ClassHash(hash.0)).unwrap().as_ref(),
+                    Some(definition)
+                );
+            }
+        }
+    }
+
     #[test]
     fn compiled_class_leaves() {
         let mut connection = crate::StorageBuilder::in_memory()
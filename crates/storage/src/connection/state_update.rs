@@ -530,6 +530,92 @@ impl Transaction<'_> {
         Ok(ret)
     }
 
+    /// Same as [Self::declared_classes_counts], but aggregates the counts of
+    /// the whole range in a single pass instead of running two sub-queries per
+    /// block. Meant to be used with a much larger `max_num_blocks`.
+    pub fn declared_classes_counts_batched(
+        &self,
+        start: BlockNumber,
+        max_num_blocks: NonZeroUsize,
+    ) -> anyhow::Result<VecDeque<usize>> {
+        let mut stmt = self
+            .inner()
+            .prepare_cached(
+                r"WITH
+                blocks(number) AS (
+                    SELECT number FROM block_headers
+                    WHERE number >= ?1
+                    ORDER BY number ASC
+                    LIMIT ?2
+                ),
+                declared(block_number, n) AS (
+                    SELECT block_number, COUNT(*) FROM class_definitions
+                    WHERE block_number BETWEEN ?1 AND (SELECT MAX(number) FROM blocks)
+                    GROUP BY block_number
+                ),
+                redeclared(block_number, n) AS (
+                    SELECT block_number, COUNT(*) FROM redeclared_classes
+                    WHERE block_number BETWEEN ?1 AND (SELECT MAX(number) FROM blocks)
+                    GROUP BY block_number
+                )
+                SELECT IFNULL(declared.n, 0) + IFNULL(redeclared.n, 0)
+                FROM blocks
+                LEFT JOIN declared ON declared.block_number = blocks.number
+                LEFT JOIN redeclared ON redeclared.block_number = blocks.number
+                ORDER BY blocks.number ASC",
+            )
+            .context("Preparing get number of declared classes statement")?;
+
+        let max_len = u64::try_from(max_num_blocks.get()).expect("ptr size is 64 bits");
+        let mut counts = stmt
+            .query_map(params![&start, &max_len], |row| row.get(0))
+            .context("Querying declared classes counts")?;
+
+        let mut ret = VecDeque::with_capacity(max_num_blocks.get());
+
+        while let Some(stat) = counts.next().transpose().context("Iterating over rows")? {
+            ret.push_back(stat);
+        }
+
+        Ok(ret)
+    }
+
+    /// Returns the number of classes [declared](Self::declared_classes_at) by
+    /// each block in `first..=last`, aggregated in a single statement. Blocks
+    /// which declare no classes are left out.
+    pub fn declared_classes_counts_by_block(
+        &self,
+        first: BlockNumber,
+        last: BlockNumber,
+    ) -> anyhow::Result<HashMap<BlockNumber, usize>> {
+        let mut stmt = self
+            .inner()
+            .prepare_cached(
+                r"SELECT block_number, COUNT(*) FROM (
+                    SELECT block_number FROM class_definitions WHERE block_number BETWEEN ?1 AND ?2
+                    UNION ALL
+                    SELECT block_number FROM redeclared_classes WHERE block_number BETWEEN ?1 AND ?2
+                )
+                GROUP BY block_number",
+            )
+            .context("Preparing declared classes counts by block statement")?;
+
+        let mut rows = stmt
+            .query_map(params![&first, &last], |row| {
+                Ok((row.get_block_number(0)?, row.get::<_, usize>(1)?))
+            })
+            .context("Querying declared classes counts by block")?;
+
+        let mut counts = HashMap::new();
+        while let Some((block_number, count)) =
+            rows.next().transpose().context("Iterating over rows")?
+        {
+            counts.insert(block_number, count);
+        }
+
+        Ok(counts)
+    }
+
     /// Returns hashes of Cairo and Sierra classes declared at a given block.
     pub fn declared_classes_at(
         &self,
@@ -1059,6 +1145,58 @@ mod tests {
         assert_eq!(declared_at, header_0.number);
     }
 
+    #[test]
+    fn declared_classes_counts_batched_matches_per_batch_query() {
+        let storage = crate::StorageBuilder::in_memory().unwrap();
+        let mut blocks = crate::fake::generate::n_blocks(8);
+        // Re-declare a class from an earlier block so that redeclarations are
+        // counted as well.
+        let redeclared = blocks[..5]
+            .iter()
+            .find_map(|block| {
+                let state_update = block.state_update.as_ref().unwrap();
+                state_update.declared_cairo_classes.iter().next().copied()
+            })
+            .unwrap();
+        blocks[5]
+            .state_update
+            .as_mut()
+            .unwrap()
+            .declared_cairo_classes
+            .insert(redeclared);
+        crate::fake::fill(&storage, &blocks, None);
+
+        let mut db = storage.connection().unwrap();
+        let tx = db.transaction().unwrap();
+
+        for start in [0, 1, 5, 7, 8] {
+            let start = BlockNumber::new_or_panic(start);
+            for max_num_blocks in [1, 3, 8, 100] {
+                let max_num_blocks = NonZeroUsize::new(max_num_blocks).unwrap();
+                let per_batch = tx.declared_classes_counts(start, max_num_blocks).unwrap();
+                let batched = tx
+                    .declared_classes_counts_batched(start, max_num_blocks)
+                    .unwrap();
+                assert_eq!(batched, per_batch, "start {start}, max {max_num_blocks}");
+            }
+        }
+
+        let by_block = tx
+            .declared_classes_counts_by_block(BlockNumber::GENESIS, BlockNumber::new_or_panic(7))
+            .unwrap();
+        for block in &blocks {
+            let block_number = block.header.header.number;
+            let declared = tx
+                .declared_classes_at(block_number.into())
+                .unwrap()
+                .unwrap();
+            assert_eq!(
+                by_block.get(&block_number).copied().unwrap_or_default(),
+                declared.len()
+            );
+        }
+    }
+
     #[test]
     fn contract_class_hash() {
         let mut db = crate::StorageBuilder::in_memory()
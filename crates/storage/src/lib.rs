@@ -59,6 +59,7 @@ struct Inner {
     running_event_filter: Arc<Mutex<RunningEventFilter>>,
     trie_prune_mode: TriePruneMode,
     blockchain_history_mode: BlockchainHistoryMode,
+    class_compression_dictionary: bool,
 }
 
 pub struct StorageManager {
@@ -68,6 +69,7 @@ pub struct StorageManager {
     running_event_filter: Arc<Mutex<RunningEventFilter>>,
     trie_prune_mode: TriePruneMode,
     blockchain_history_mode: BlockchainHistoryMode,
+    class_compression_dictionary: bool,
 }
 
 impl std::fmt::Debug for StorageManager {
@@ -76,6 +78,10 @@ impl std::fmt::Debug for StorageManager {
             .field("database_path", &self.database_path)
             .field("journal_mode", &self.journal_mode)
             .field("trie_prune_mode", &self.trie_prune_mode)
+            .field(
+                "class_compression_dictionary",
+                &self.class_compression_dictionary,
+            )
             .finish()
     }
 }
@@ -101,6 +107,7 @@ impl StorageManager {
             running_event_filter: self.running_event_filter.clone(),
             trie_prune_mode: self.trie_prune_mode,
             blockchain_history_mode: self.blockchain_history_mode,
+            class_compression_dictionary: self.class_compression_dictionary,
         }))
     }
 
@@ -122,6 +129,7 @@ pub struct StorageBuilder {
     event_filter_cache_size: usize,
     trie_prune_mode: Option<TriePruneMode>,
     blockchain_history_mode: Option<BlockchainHistoryMode>,
+    class_compression_dictionary: bool,
 }
 
 impl StorageBuilder {
@@ -132,6 +140,7 @@ impl StorageBuilder {
             event_filter_cache_size: 16,
             trie_prune_mode: None,
             blockchain_history_mode: None,
+            class_compression_dictionary: false,
         }
     }
 
@@ -158,6 +167,14 @@ impl StorageBuilder {
         self
     }
 
+    /// Compress class definitions with the latest trained dictionary, see
+    /// [Transaction::train_class_compression_dictionary]. Class definitions
+    /// that were compressed with a dictionary can be read regardless.
+    pub fn class_compression_dictionary(mut self, enabled: bool) -> Self {
+        self.class_compression_dictionary = enabled;
+        self
+    }
+
     /// Convenience function for tests to create an in-memory database.
     pub fn in_memory() -> anyhow::Result<Storage> {
         Self::in_memory_with_trie_pruning(TriePruneMode::Archive)
@@ -350,6 +367,7 @@ impl StorageBuilder {
             running_event_filter: Arc::new(Mutex::new(running_event_filter)),
             trie_prune_mode,
             blockchain_history_mode,
+            class_compression_dictionary: self.class_compression_dictionary,
         })
     }
 
@@ -545,6 +563,7 @@ impl Storage {
             self.0.running_event_filter.clone(),
             self.0.trie_prune_mode,
             self.0.blockchain_history_mode,
+            self.0.class_compression_dictionary,
         ))
     }
 
@@ -553,6 +572,20 @@ impl Storage {
     }
 }
 
+/// Whether `error` was caused by another connection holding a lock on the
+/// database, in which case retrying the failed operation later can succeed.
+/// Any other storage error is considered fatal.
+pub fn is_transient_error(error: &anyhow::Error) -> bool {
+    error.chain().any(|cause| {
+        matches!(
+            cause
+                .downcast_ref::<rusqlite::Error>()
+                .and_then(rusqlite::Error::sqlite_error_code),
+            Some(rusqlite::ErrorCode::DatabaseBusy | rusqlite::ErrorCode::DatabaseLocked)
+        )
+    })
+}
+
 fn setup_journal_mode(
     connection: &mut rusqlite::Connection,
     journal_mode: JournalMode,
@@ -724,6 +757,25 @@ mod tests {
         assert_eq!(version, 0);
     }
 
+    #[test]
+    fn busy_database_is_a_transient_error() {
+        let storage = StorageBuilder::in_tempdir().unwrap();
+        let mut holder = storage.connection().unwrap();
+        let _lock = holder
+            .transaction_with_behavior(TransactionBehavior::Exclusive)
+            .unwrap();
+
+        let mut connection = storage.connection().unwrap();
+        let error = connection
+            .transaction_with_behavior(TransactionBehavior::Immediate)
+            .map(|_| ())
+            .unwrap_err()
+            .context("Creating database transaction");
+
+        assert!(is_transient_error(&error));
+        assert!(!is_transient_error(&anyhow::anyhow!("Casm hash not found")));
+    }
+
     #[test]
     fn full_migration() {
         let mut conn = rusqlite::Connection::open_in_memory().unwrap();
@@ -32,6 +32,10 @@ mod revision_0069;
 mod revision_0070;
 mod revision_0071;
 mod revision_0072;
+mod revision_0073;
+mod revision_0074;
+mod revision_0075;
+mod revision_0076;
 
 pub(crate) use base::base_schema;
 
@@ -72,6 +76,10 @@ pub fn migrations() -> &'static [MigrationFn] {
         revision_0070::migrate,
         revision_0071::migrate,
         revision_0072::migrate,
+        revision_0073::migrate,
+        revision_0074::migrate,
+        revision_0075::migrate,
+        revision_0076::migrate,
     ]
 }
 
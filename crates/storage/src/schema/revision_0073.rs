@@ -0,0 +1,21 @@
+use anyhow::Context;
+
+/// Adds a side table recording where the CASM definition of each Sierra class
+/// came from, i.e. whether it was compiled locally or fetched from the feeder
+/// gateway.
+pub(crate) fn migrate(tx: &rusqlite::Transaction<'_>) -> anyhow::Result<()> {
+    tracing::info!("Creating casm_provenance table");
+
+    tx.execute(
+        r"CREATE TABLE casm_provenance (
+            hash        BLOB    PRIMARY KEY NOT NULL,
+            source      INTEGER NOT NULL,
+            recorded_at INTEGER NOT NULL,
+            FOREIGN KEY(hash) REFERENCES class_definitions(hash) ON DELETE CASCADE
+        )",
+        [],
+    )
+    .context("Creating casm_provenance table")?;
+
+    Ok(())
+}
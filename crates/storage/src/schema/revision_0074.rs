@@ -0,0 +1,18 @@
+use anyhow::Context;
+
+/// Adds a table holding trained zstd dictionaries used to compress class
+/// definitions. Definitions compressed with a dictionary reference it by id.
+pub(crate) fn migrate(tx: &rusqlite::Transaction<'_>) -> anyhow::Result<()> {
+    tracing::info!("Creating class_compression_dictionaries table");
+
+    tx.execute(
+        r"CREATE TABLE class_compression_dictionaries (
+            id         INTEGER PRIMARY KEY NOT NULL,
+            dictionary BLOB    NOT NULL
+        )",
+        [],
+    )
+    .context("Creating class_compression_dictionaries table")?;
+
+    Ok(())
+}
@@ -0,0 +1,17 @@
+use anyhow::Context;
+
+/// Adds a table marking classes whose definitions are being written, so that
+/// writes interrupted by a crash can be detected on startup.
+pub(crate) fn migrate(tx: &rusqlite::Transaction<'_>) -> anyhow::Result<()> {
+    tracing::info!("Creating class_writes_in_progress table");
+
+    tx.execute(
+        r"CREATE TABLE class_writes_in_progress (
+            hash BLOB PRIMARY KEY NOT NULL
+        )",
+        [],
+    )
+    .context("Creating class_writes_in_progress table")?;
+
+    Ok(())
+}
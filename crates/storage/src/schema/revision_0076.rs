@@ -0,0 +1,15 @@
+use anyhow::Context;
+
+/// Marks CASM definitions which were deliberately not stored, so that they can
+/// be told apart from definitions which were not downloaded yet.
+pub(crate) fn migrate(tx: &rusqlite::Transaction<'_>) -> anyhow::Result<()> {
+    tracing::info!("Adding casm_definitions.pruned column");
+
+    tx.execute(
+        "ALTER TABLE casm_definitions ADD COLUMN pruned INTEGER NOT NULL DEFAULT 0",
+        [],
+    )
+    .context("Adding casm_definitions.pruned column")?;
+
+    Ok(())
+}
@@ -1,4 +1,5 @@
 use std::future::Future;
+use std::num::NonZeroUsize;
 
 use tokio::sync::mpsc::{self, Sender};
 use tokio_stream::wrappers::ReceiverStream;
@@ -18,7 +19,17 @@ where
     U: FnOnce(Sender<T>) -> V + Send + 'static,
     V: Future<Output = ()> + Send + 'static,
 {
-    let (tx, rx) = mpsc::channel(1);
+    from_future_with_capacity(NonZeroUsize::MIN, src)
+}
+
+/// Same as [from_future], but up to `capacity` items can be yielded ahead of
+/// the consumer.
+pub fn from_future_with_capacity<T, U, V>(capacity: NonZeroUsize, src: U) -> impl Stream<Item = T>
+where
+    U: FnOnce(Sender<T>) -> V + Send + 'static,
+    V: Future<Output = ()> + Send + 'static,
+{
+    let (tx, rx) = mpsc::channel(capacity.get());
     crate::task::spawn(src(tx));
 
     ReceiverStream::new(rx)
@@ -41,7 +52,17 @@ where
     T: Send + 'static,
     U: FnOnce(CancellationToken, Sender<T>) + Send + 'static,
 {
-    let (tx, rx) = mpsc::channel(1);
+    from_blocking_with_capacity(NonZeroUsize::MIN, src)
+}
+
+/// Same as [from_blocking], but up to `capacity` items can be yielded ahead of
+/// the consumer.
+pub fn from_blocking_with_capacity<T, U>(capacity: NonZeroUsize, src: U) -> impl Stream<Item = T>
+where
+    T: Send + 'static,
+    U: FnOnce(CancellationToken, Sender<T>) + Send + 'static,
+{
+    let (tx, rx) = mpsc::channel(capacity.get());
     crate::task::spawn_std(move |cancellation_token| src(cancellation_token, tx));
 
     ReceiverStream::new(rx)